@@ -1,6 +1,10 @@
 #![feature(test)]
 #[cfg(test)]
 extern crate test;
+#[cfg(test)]
+extern crate asciii;
+#[cfg(test)]
+extern crate tempdir;
 
 
 #[cfg(test)]
@@ -82,3 +86,49 @@ mod list_move{
     }
 
 }
+
+#[cfg(test)]
+mod index_cache{
+    //! how much does the `IndexCache` save compared to opening every project?
+
+    use std::fs;
+    use tempdir::TempDir;
+    use super::test::Bencher;
+
+    use asciii::project::Project;
+    use asciii::storage::{Storage, StorageDir};
+
+    static FIELDS: [&'static str;4] = ["Name", "InvoiceNumber", "Date", "Canceled"];
+
+    fn setup(count:usize) -> (TempDir, Storage<Project>) {
+        let dir = TempDir::new("index_cache").unwrap();
+        let storage = Storage::new(dir.path(), "working", "archive", "templates").unwrap();
+        storage.create_dirs().unwrap();
+        for i in 0..count {
+            let project_dir = storage.working_dir().join(format!("project{}", i));
+            fs::create_dir(&project_dir).unwrap();
+            fs::copy("./tests/current.yml", project_dir.join(format!("project{}.yml", i))).unwrap();
+        }
+        (dir, storage)
+    }
+
+    #[bench]
+    fn open_projects(b: &mut Bencher) {
+        let (_dir, storage) = setup(50);
+        b.iter(||{ storage.open_projects(StorageDir::Working).unwrap() });
+    }
+
+    #[bench]
+    fn open_index_uncached(b: &mut Bencher) {
+        let (_dir, storage) = setup(50);
+        b.iter(||{ storage.open_index(StorageDir::Working, &FIELDS, false).unwrap() });
+    }
+
+    #[bench]
+    fn open_index_cached(b: &mut Bencher) {
+        let (_dir, storage) = setup(50);
+        storage.open_index(StorageDir::Working, &FIELDS, true).unwrap();
+        b.iter(||{ storage.open_index(StorageDir::Working, &FIELDS, true).unwrap() });
+    }
+
+}
@@ -41,6 +41,7 @@ use std::error::Error as ErrorTrait;
 use project;
 // use project::error::ProjectError;
 use storage::error::StorageError;
+use util::latex;
 
 error_chain!{
 
@@ -63,5 +64,10 @@ error_chain!{
         ActionError{
             description("unexpected response from service")
         }
+
+        ConversionFailed(failure: latex::Failure){
+            description("converting the document failed")
+            display("{}", failure)
+        }
     }
 }
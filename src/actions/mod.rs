@@ -5,6 +5,7 @@
 
 
 use chrono::*;
+use rustc_serialize::json::{Json, ToJson};
 use bill::Currency;
 use icalendar::Calendar;
 
@@ -14,14 +15,20 @@ use std::fmt::Write;
 use std::path::{Path,PathBuf};
 
 use util;
-use super::BillType;
-use storage::{Storage,StorageDir,Storable,StorageResult};
+use print;
+use super::{BillType, DocumentFormat};
+use storage::{Storage,StorageDir,Storable,StorageResult,StorageError,IndexEntry,ImportReport,AutoCommit,TemplateComparison};
+use storage::repo::{LogEntry, Repository};
 use project::Project;
+use project::history::{self, Change};
+use project::issued;
 use project::spec::IsProject;
 use project::spec::IsClient;
 use project::spec::Invoicable;
 use project::spec::ProvidesData;
 use project::spec::events::HasEvents;
+use project::spec::Validatable;
+use util::merge;
 
 #[cfg(feature="document_export")]
 use fill_docs::fill_template;
@@ -63,8 +70,8 @@ pub fn simple_with_projects<F>(dir:StorageDir, search_terms:&[&str], f:F)
 
 /// Helper method that passes projects matching the `search_terms` to the passt closure `f`
 /// TODO Really move this to `Storage`
-pub fn with_projects<F>(dir:StorageDir, search_terms:&[&str], f:F) -> Result<()>
-    where F:Fn(&Project)->Result<()>
+pub fn with_projects<F>(dir:StorageDir, search_terms:&[&str], mut f:F) -> Result<()>
+    where F:FnMut(&Project)->Result<()>
 {
     trace!("with_projects({:?})", search_terms);
     let luigi = setup_luigi()?;
@@ -78,11 +85,82 @@ pub fn with_projects<F>(dir:StorageDir, search_terms:&[&str], f:F) -> Result<()>
     Ok(())
 }
 
-pub fn csv(year:i32) -> Result<String> {
+/// Commits what a command changed, if `git/auto_commit` is turned on.
+pub fn auto_commit(commit:&AutoCommit) -> Result<()> {
+    if !::CONFIG.get_bool("git/auto_commit") {
+        return Ok(());
+    }
+    let luigi = setup_luigi_with_git()?;
+    if let Some(entry) = luigi.auto_commit(commit)? {
+        info!("committed {} {}", entry.short_id(), entry.summary());
+    }
+    Ok(())
+}
+
+/// Collects the projects that were moved to or from the archive, by opening them at their new location.
+fn moved_projects_commit(operation:&str, moved_files:&[PathBuf]) -> AutoCommit {
+    let mut commit = AutoCommit::new(operation);
+    for dir in moved_files.iter().filter(|path| path.is_dir()) {
+        if let Ok(project) = Project::open(dir) {
+            commit.touch(&project, moved_files);
+        }
+    }
+    commit
+}
+
+/// Finds exactly one project, either in the working directory or as `archive/<year>/<search term>`.
+///
+/// Used by `new --from`.
+pub fn find_unique_project(luigi:&Storage<Project>, description:&str) -> Result<Project> {
+    let parts = description.splitn(3, '/').collect::<Vec<_>>();
+    let (dir, search_term) = match (parts.get(0), parts.get(1).and_then(|y| y.parse::<i32>().ok()), parts.get(2)) {
+        (Some(&"archive"), Some(year), Some(search_term)) => (StorageDir::Archive(year), *search_term),
+        _ => (StorageDir::Working, description)
+    };
+
+    let mut projects = luigi.search_projects(dir, search_term)?;
+    match projects.len() {
+        0 => Err(format!("Nothing found for {:?}", description).into()),
+        1 => Ok(projects.remove(0)),
+        _ => Err(format!("{:?} is ambiguous, it matches: {}",
+                         description,
+                         projects.iter().map(|p| p.short_desc()).collect::<Vec<_>>().join(", ")).into())
+    }
+}
+
+pub fn csv(year:i32, use_cache:bool) -> Result<String> {
     let luigi = setup_luigi()?;
-    let mut projects = luigi.open_projects(StorageDir::Year(year))?;
-    projects.sort_by(|pa,pb| pa.index().unwrap_or_else(||"zzzz".to_owned()).cmp( &pb.index().unwrap_or("zzzz".to_owned())));
-    projects_to_csv(&projects)
+    let mut entries = luigi.open_index(StorageDir::Year(year), &CSV_FIELDS, use_cache)?;
+    entries.sort_by(|ea,eb| ea.get("SortIndex").unwrap_or("zzzz").cmp(eb.get("SortIndex").unwrap_or("zzzz")));
+    Ok(index_to_csv(&entries))
+}
+
+/// Fields of a project that `index_to_csv()` needs.
+pub const CSV_FIELDS:[&'static str;10] = [
+    "InvoiceNumber", "Name", "event/dates/0/begin", "invoice/date", "Employees",
+    "Responsible", "invoice/payed_date", "FinalValue", "Canceled", "SortIndex"
+];
+
+/// Like `projects_to_csv()`, but from the `IndexCache`.
+pub fn index_to_csv(entries:&[IndexEntry]) -> String{
+    let splitter = ";";
+    let empty = r#""""#;
+    let mut lines = vec![[ "Rnum", "Bezeichnung", "Datum", "Rechnungsdatum", "Betreuer", "Verantwortlich", "Bezahlt am", "Betrag", "Canceled"].join(splitter)];
+    for entry in entries{
+        lines.push([
+                   entry.get("InvoiceNumber")       .unwrap_or(empty),
+                   entry.get("Name")                .unwrap_or(empty),
+                   entry.get("event/dates/0/begin") .unwrap_or(empty),
+                   entry.get("invoice/date")        .unwrap_or(empty),
+                   entry.get("Employees")           .unwrap_or(empty),
+                   entry.get("Responsible")         .unwrap_or(empty),
+                   entry.get("invoice/payed_date")  .unwrap_or(empty),
+                   entry.get("FinalValue")          .unwrap_or(empty),
+                   if entry.get("Canceled") == Some("true") {"canceled"} else {""}
+        ].join(splitter));
+    }
+    lines.push(String::new());
+    lines.join("\n")
 }
 
 /// Produces a csv string from a list of `Project`s
@@ -108,13 +186,23 @@ pub fn projects_to_csv(projects:&[Project]) -> Result<String>{
 }
 
 /// Creates the latex files within each projects directory, either for Invoice or Offer.
+///
+/// Finalizing an invoice records its fingerprint, see [`issued`](../project/issued/index.html).
+/// An invoice that was modified since it was issued is only produced again with a `reissue` reason.
 #[cfg(feature="document_export")]
-pub fn project_to_doc(project: &Project, template_name:&str, bill_type:&Option<BillType>, dry_run:bool, force:bool) -> Result<()> {
+pub fn project_to_doc(project: &Project, template_name:&str, format:DocumentFormat, bill_type:&Option<BillType>, dry_run:bool, force:bool, reissue:Option<&str>) -> Result<()> {
+    if format == DocumentFormat::XRechnung {
+        return project_to_xrechnung(project, bill_type, dry_run, force, reissue);
+    }
 
     // init_export_config()
-    let template_ext  = ::CONFIG.get_str("extensions/output_template").expect("Faulty default config");
-    let output_ext    = ::CONFIG.get_str("extensions/output_file").expect("Faulty default config");
-    let convert_ext   = ::CONFIG.get_str("convert/output_extension").expect("Faulty default config");
+    let output_ext    = format.output_extension();
+    let output_ext    = output_ext.as_str();
+    // html is already the final document
+    let convert_ext   = match format {
+        DocumentFormat::Latex => ::CONFIG.get_str("convert/output_extension").expect("Faulty default config"),
+        _ => output_ext
+    };
     let convert_tool  = ::CONFIG.get_str("convert/tool");
     let output_folder = ::CONFIG.get_str("output_path").and_then(util::get_valid_path).expect("Faulty config \"output_path\"");
     let trash_exts    = ::CONFIG.get("convert/trash_extensions") .expect("Faulty default config")
@@ -123,19 +211,7 @@ pub fn project_to_doc(project: &Project, template_name:&str, bill_type:&Option<B
                                 .map(|v|v.as_str()).collect::<Vec<_>>();
 
 
-    // construct_template_path(&template_name) {
-    let mut template_path = PathBuf::new();
-    template_path.push(util::get_storage_path());
-    template_path.push(::CONFIG.get_str("dirs/templates").expect("Faulty config: dirs/templates does not contain a value"));
-    template_path.push(template_name);
-    template_path.set_extension(template_ext);
-    // }
-
-    // check stays here
-    debug!("template file={:?} exists={}", template_path, template_path.exists());
-    if !template_path.exists() {
-        return Err(format!("Template not found at {}", template_path.display()).into())
-    }
+    let template_path = output_template_path(template_name, format)?;
 
 
     // project_readyness(&project) {
@@ -169,33 +245,59 @@ pub fn project_to_doc(project: &Project, template_name:&str, bill_type:&Option<B
     //debug!("{:?} -> {:?}",(bill_type, project.is_ready_for_offer(), project.is_ready_for_invoice()), (dyn_bill_type, outfile_tex));
 
     if let (Some(outfile), Some(dyn_bill)) = (outfile_tex, dyn_bill_type) {
-        let filled = fill_template(project, &dyn_bill, &template_path)?;
+        let is_invoice = match dyn_bill { Invoice => true, Offer => false };
+        if is_invoice && reissue.is_none() && project.modified_since_issued() {
+            return Err(::project::error::Error::from(::project::error::ErrorKind::ModifiedSinceIssued(project.short_desc())).into());
+        }
+
+        let mut document = project.to_json();
+        if is_invoice {
+            if let Json::Object(ref mut fields) = document {
+                fields.insert(String::from("payment"), project.payment_to_json());
+            }
+        }
+        let filled = fill_template(&document, &dyn_bill, &template_path)?;
 
         let pdffile = to_local_file(&outfile, convert_ext);
         let target = output_folder.join(&pdffile);
 
         // ok, so apparently we can create a tex file, so lets do it
-        if !force && target.exists() && file_age(&target)? < file_age(&project_file)? {
+        if !force && target.exists()
+            && file_age(&target)? < file_age(&project_file)?
+            && file_age(&target)? < file_age(&template_path)? {
             // no wait, nothing has changed, so lets save ourselves the work
-            info!("nothing to be done, {} is younger than {}
-                         use --force if you don't agree
-                         use --pdf to only rebuild the pdf",
+            println!("nothing to be done, {} is younger than {} and {}\n use --force if you don't agree",
                   target.display(),
-                  project_file.display());
-            unimplemented!();
+                  project_file.display(),
+                  template_path.display());
         } else {
             // \o/ we created a tex file
 
-            if dry_run{
+            let failure = if dry_run{
                 warn!("Dry run! This does not produce any output:\n * {}\n * {}", outfile.display(), pdffile.display());
+                None
+            } else if format == DocumentFormat::Html {
+                let mut file = fs::File::create(&target)?;
+                ::std::io::Write::write_all(&mut file, filled.as_bytes())?;
+                file.sync_all()?;
+                None
             } else {
                 let outfileb = project.write_to_file(&filled,&dyn_bill,output_ext)?;
                 debug!("{} vs\n        {}", outfile.display(), outfileb.display());
-                util::pass_to_command(&convert_tool, &[&outfileb]);
-            }
-            // clean up expected trash files
+                match convert_tool {
+                    Some(tool) => util::latex::convert(tool, &outfileb, &to_local_file(&outfile, "log"))?,
+                    None => { util::pass_to_command(&convert_tool, &[&outfileb]); None }
+                }
+            };
+            let kept_log = failure.as_ref().and_then(|f| f.log.to_owned());
+
+            // clean up expected trash files, but keep the log if something went wrong
+            let trash_exts = if format == DocumentFormat::Latex { trash_exts } else { Vec::new() };
             for trash_ext in trash_exts.iter().filter_map(|x|*x){
                 let trash_file = to_local_file(&outfile, trash_ext);
+                if kept_log.as_ref() == Some(&trash_file) {
+                    continue
+                }
                 if  trash_file.exists() {
                     fs::remove_file(&trash_file)?;
                     debug!("just deleted: {}", trash_file.display())
@@ -204,46 +306,224 @@ pub fn project_to_doc(project: &Project, template_name:&str, bill_type:&Option<B
                     debug!("I expected there to be a {}, but there wasn't any ?", trash_file.display())
                 }
             }
-            if pdffile.exists(){
+            if let Some(failure) = failure {
+                return Err(ErrorKind::ConversionFailed(failure).into());
+            }
+            if format == DocumentFormat::Latex && pdffile.exists(){
                 debug!("now there is be a {:?} -> {:?}", pdffile, target);
                 fs::rename(&pdffile, &target)?;
             }
+
+            if is_invoice && !dry_run && issued::record(project, reissue)? {
+                match reissue {
+                    Some(reason) => info!("{} reissued: {}", project.short_desc(), reason),
+                    None         => info!("{} issued, fingerprint recorded", project.short_desc())
+                }
+            }
         }
     }
 
     Ok(())
 }
 
+/// Writes the invoice as an e-invoice into `output_path`, see [`xrechnung`](../project/xrechnung/index.html).
+///
+/// Refuses if mandatory fields are missing, issuing is recorded just like with other formats.
+#[cfg(feature="document_export")]
+fn project_to_xrechnung(project: &Project, bill_type:&Option<BillType>, dry_run:bool, force:bool, reissue:Option<&str>) -> Result<()> {
+    use project::xrechnung::{self, Seller};
+    use project::error::{Error as ProjectError, ErrorKind as ProjectErrorKind};
+
+    if let Some(BillType::Offer) = *bill_type {
+        return Err("e-invoices can only be made from invoices, drop --offer".into());
+    }
+    if let Err(errors) = project.is_ready_for_invoice() {
+        return Err(format!("cannot create an invoice, check out:{}", errors).into());
+    }
+    let seller = Seller::from_config();
+    if let Err(missing) = xrechnung::validate(project, &seller) {
+        return Err(ProjectError::from(ProjectErrorKind::EInvoiceIncomplete(project.short_desc(), missing.join(", "))).into());
+    }
+    if reissue.is_none() && project.modified_since_issued() {
+        return Err(ProjectError::from(ProjectErrorKind::ModifiedSinceIssued(project.short_desc())).into());
+    }
+
+    let output_folder = ::CONFIG.get_str("output_path").and_then(util::get_valid_path).expect("Faulty config \"output_path\"");
+    let file_name = project.invoice_file_name("xml").ok_or("cannot determine the name of the invoice")?;
+    let target = output_folder.join(file_name);
+
+    if !force && target.exists() && file_age(&target)? < file_age(&project.file())? {
+        println!("nothing to be done, {} is younger than {}\n use --force if you don't agree",
+                 target.display(), project.file().display());
+        return Ok(());
+    }
+
+    let xml = xrechnung::to_xml(project, &seller)?;
+    if dry_run {
+        warn!("Dry run! This does not produce any output:\n * {}", target.display());
+        return Ok(());
+    }
+    let mut file = fs::File::create(&target)?;
+    ::std::io::Write::write_all(&mut file, xml.as_bytes())?;
+    file.sync_all()?;
+    info!("wrote {}", target.display());
+
+    if issued::record(project, reissue)? {
+        match reissue {
+            Some(reason) => info!("{} reissued: {}", project.short_desc(), reason),
+            None         => info!("{} issued, fingerprint recorded", project.short_desc())
+        }
+    }
+    Ok(())
+}
+
 /// Creates the latex files within each projects directory, either for Invoice or Offer.
 #[cfg(feature="document_export")]
-pub fn projects_to_doc(dir:StorageDir, search_term:&str, template_name:&str, bill_type:&Option<BillType>, dry_run:bool, force:bool) -> Result<()> {
-    with_projects(dir, &[search_term], |p| project_to_doc(p, template_name, bill_type, dry_run, force) )
+pub fn projects_to_doc(dir:StorageDir, search_term:&str, template_name:&str, format:DocumentFormat, bill_type:&Option<BillType>, dry_run:bool, force:bool, reissue:Option<&str>) -> Result<()> {
+    let mut commit = AutoCommit::new(match *bill_type {
+        Some(BillType::Offer)   => "make offer",
+        Some(BillType::Invoice) => "make invoice",
+        None                    => "make"
+    });
+    with_projects(dir, &[search_term], |p| {
+        project_to_doc(p, template_name, format, bill_type, dry_run, force, reissue)?;
+        if !dry_run {
+            commit.touch(p, &[p.dir()]);
+        }
+        Ok(())
+    })?;
+    auto_commit(&commit)
 }
 
+/// Path to the output template (`.tex.hbs`, `.html.hbs`) of that name.
+#[cfg(feature="document_export")]
+fn output_template_path(template_name:&str, format:DocumentFormat) -> Result<PathBuf> {
+    let template_ext  = format.template_extension()
+                              .ok_or_else(|| format!("{:?} documents are not made from templates", format))?;
+
+    let mut template_path = PathBuf::new();
+    template_path.push(util::get_storage_path());
+    template_path.push(::CONFIG.get_str("dirs/templates").expect("Faulty config: dirs/templates does not contain a value"));
+    template_path.push(template_name);
+    template_path.set_extension(template_ext);
+
+    debug!("template file={:?} exists={}", template_path, template_path.exists());
+    if !template_path.exists() {
+        return Err(format!("Template not found at {}", template_path.display()).into())
+    }
+    Ok(template_path)
+}
+
+/// Command MAKE --watch
+///
+/// Renders the document once, then again every time the project file or the template changes.
+/// Rapid successive saves are only rendered once, errors are printed and watching goes on.
+#[cfg(feature="document_export")]
+pub fn watch_project_doc(dir:StorageDir, search_term:&str, template_name:&str, format:DocumentFormat, bill_type:&Option<BillType>, dry_run:bool) -> Result<()> {
+    use std::time::{Duration, SystemTime};
+
+    const POLL: u64 = 250;
+    const DEBOUNCE: u64 = 500;
+
+    let luigi = setup_luigi()?;
+    let mut projects = luigi.search_projects(dir, search_term)?;
+    if projects.is_empty() {
+        return Err(format!("Nothing found for {:?}", search_term).into())
+    }
+    if projects.len() > 1 {
+        warn!("{:?} matches {} projects, only watching the first", search_term, projects.len());
+    }
+    let project_file = projects.remove(0).file();
+    let watched = [project_file.to_owned(), output_template_path(template_name, format)?];
+
+    let modification_times = || watched.iter()
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect::<Vec<Option<SystemTime>>>();
+
+    let rebuild = || {
+        let project = match Project::open_file(&project_file) {
+            Ok(project) => project,
+            Err(err) => { println!("{}: {}", project_file.display(), err); return }
+        };
+        // without a bill type, project_to_doc() makes whichever is possible
+        let readiness = match *bill_type {
+            Some(BillType::Invoice) => project.is_ready_for_invoice(),
+            Some(BillType::Offer)   => project.is_ready_for_offer(),
+            None => project.is_ready_for_offer().or_else(|_| project.is_ready_for_invoice())
+        };
+        if let Err(errors) = readiness {
+            println!("{}", errors);
+            return
+        }
+        match project_to_doc(&project, template_name, format, bill_type, dry_run, true, None) {
+            Ok(_) => println!("{} rendered", Local::now().format("%H:%M:%S")),
+            Err(err) => println!("{}", err)
+        }
+    };
+
+    rebuild();
+    println!("watching {} and {} (Ctrl-C to stop)", watched[0].display(), watched[1].display());
+
+    let mut last = modification_times();
+    loop {
+        last = wait_for_change(&last, Duration::from_millis(POLL), Duration::from_millis(DEBOUNCE), &modification_times);
+        rebuild();
+    }
+}
+
+/// Polls `state` every `poll` until it differs from `last`, then until it stayed the same for `debounce`.
+///
+/// Editors tend to save several times in a row, this returns once things settled.
+#[cfg(feature="document_export")]
+fn wait_for_change<T, F>(last:&T, poll:time::Duration, debounce:time::Duration, state:F) -> T
+    where T: PartialEq, F: Fn() -> T
+{
+    use std::thread;
+
+    let mut current = state();
+    while current == *last {
+        thread::sleep(poll);
+        current = state();
+    }
+    loop {
+        thread::sleep(debounce);
+        let again = state();
+        if again == current { return current }
+        current = again;
+    }
+}
+
+/// Time since the file was last modified.
 fn file_age(path:&Path) -> Result<time::Duration> {
     let metadata = fs::metadata(path)?;
-    let accessed = metadata.accessed()?;
-    Ok(accessed.elapsed()?)
+    let modified = metadata.modified()?;
+    Ok(modified.elapsed()?)
 }
 
 /// Command DUES
-pub fn open_wages() -> Result<Currency>{
-    let luigi = setup_luigi()?;
-    let projects = luigi.open_projects(StorageDir::Working)?;
-    Ok(projects.iter()
-        .filter(|p| !p.canceled() && p.age().unwrap_or(0) > 0)
-        .filter_map(|p| p.wages())
-        .fold(Currency::default(), |acc, x| acc + x))
+pub fn open_wages(use_cache:bool) -> Result<Currency>{
+    sum_open(&["Canceled", "Date", "WagesValue"], "WagesValue", use_cache,
+             |entry| entry.get("Canceled") != Some("true"))
 }
 
 
 /// Command DUES
-pub fn open_payments() -> Result<Currency>{
+pub fn open_payments(use_cache:bool) -> Result<Currency>{
+    sum_open(&["Canceled", "Payed", "Date", "FinalValue"], "FinalValue", use_cache,
+             |entry| entry.get("Canceled") != Some("true") && entry.get("Payed") != Some("true"))
+}
+
+/// Sums up the raw currency values in `field` of all past working projects matching `filter`.
+fn sum_open<F>(fields:&[&str], field:&str, use_cache:bool, filter:F) -> Result<Currency>
+    where F: Fn(&IndexEntry) -> bool
+{
     let luigi = setup_luigi()?;
-    let projects = luigi.open_projects(StorageDir::Working)?;
-    Ok(projects.iter()
-       .filter(|p| !p.canceled() && !p.payed_by_client() && p.age().unwrap_or(0) > 0)
-       .filter_map(|p| p.sum_sold().ok())
+    let entries = luigi.open_index(StorageDir::Working, fields, use_cache)?;
+    let symbol = ::CONFIG.get_char("currency");
+    Ok(entries.iter()
+       .filter(|e| filter(e) && print::entry_date(e).map_or(false, |date| (Local::today() - date).num_days() > 0))
+       .filter_map(|e| e.get(field).and_then(|v| v.parse::<i64>().ok()))
+       .map(|value| Currency(symbol, value))
        .fold(Currency::default(), |acc, x| acc + x))
 }
 
@@ -283,18 +563,134 @@ pub fn spec() -> Result<()> {
 
 pub fn delete_project_confirmation(dir: StorageDir, search_terms:&[&str]) -> Result<()> {
     let luigi = setup_luigi_with_git()?;
+    let mut commit = AutoCommit::new("delete");
     for project in luigi.search_projects_any(dir, search_terms)? {
         luigi.delete_project_if(&project,
                 || util::really(&format!("you want me to delete {:?} [y/N]", project.dir())) && util::really("really? [y/N]")
-                )?
+                )?;
+        if !project.dir().exists() {
+            commit.touch(&project, &[project.dir()]);
+        }
     }
-    Ok(())
+    auto_commit(&commit)
 }
 
 pub fn archive_projects(search_terms:&[&str], manual_year:Option<i32>, force:bool) -> Result<Vec<PathBuf>>{
     trace!("archive_projects matching ({:?},{:?},{:?})", search_terms, manual_year,force);
     let luigi = setup_luigi_with_git()?;
-    Ok( luigi.archive_projects_if(search_terms, manual_year, || force) ?)
+    let moved_files = luigi.archive_projects_if(search_terms, manual_year, || force)?;
+    auto_commit(&moved_projects_commit("archive", &moved_files))?;
+    Ok(moved_files)
+}
+
+/// Command EXPORT-BUNDLE
+///
+/// Bundles all projects of `year` with their generated documents and the templates.
+pub fn export_bundle(year:i32, target:&Path) -> Result<Vec<PathBuf>> {
+    let luigi = setup_luigi()?;
+    let convert_ext   = ::CONFIG.get_str("convert/output_extension").expect("Faulty default config");
+    let output_folder = ::CONFIG.get_str("output_path").and_then(util::get_valid_path);
+
+    Ok(luigi.export_bundle(year, target, |project| {
+        let names = vec![project.offer_file_name(convert_ext), project.invoice_file_name(convert_ext)];
+        names.into_iter()
+             .filter_map(|name| name)
+             .filter_map(|name| output_folder.as_ref().map(|folder| folder.join(name)))
+             .filter(|document| document.exists())
+             .collect()
+    })?)
+}
+
+/// Command EXPORT DATEV
+///
+/// Writes the bookings of all projects between `from` and `to` to `target`, returns how many.
+pub fn export_datev(from:Date<UTC>, to:Date<UTC>, target:&Path) -> Result<usize> {
+    use export::datev::{self, Accounts, Header};
+
+    datev::check_range(from, to)?;
+    let luigi = setup_luigi()?;
+    let accounts = Accounts::from_config()?;
+    let mut bookings = Vec::new();
+    for project in luigi.open_projects(StorageDir::All)?.iter() {
+        bookings.extend(datev::bookings(project, &accounts, from, to)?);
+    }
+    bookings.sort_by(|a, b| (a.date, &a.document, a.debit).cmp(&(b.date, &b.document, b.debit)));
+
+    let mut file = fs::File::create(target)?;
+    ::std::io::Write::write_all(&mut file, &datev::to_csv(&Header::from_config(from, to), &bookings))?;
+    Ok(bookings.len())
+}
+
+/// Command EXPORT LEDGER, EXPORT BEANCOUNT
+///
+/// Journal of all invoices, payments and wages in all projects.
+pub fn export_journal(syntax: ::export::journal::Syntax) -> Result<String> {
+    use export::journal::{self, Accounts};
+
+    let luigi = setup_luigi()?;
+    let accounts = Accounts::from_config();
+    let mut transactions = Vec::new();
+    for project in luigi.open_projects(StorageDir::All)?.iter() {
+        transactions.extend(journal::transactions(project, &accounts)?);
+    }
+    journal::sort(&mut transactions);
+    Ok(journal::render(&transactions, syntax, &accounts.currency))
+}
+
+/// Command NEW --from-ics
+///
+/// Bookings of an iCalendar file, one per event or one per `UID`.
+pub fn ics_bookings(path:&Path, by_uid:bool) -> Result<Vec<::import::ics::Booking>> {
+    let events = ::import::ics::read_ics(path)?;
+    Ok(::import::ics::bookings(&events, by_uid))
+}
+
+/// Command NEW --client-vcard
+pub fn vcard_contact(path:&Path) -> Result<::import::vcard::Contact> {
+    Ok(::import::vcard::read_vcard(path)?)
+}
+
+/// Command SHOW --vcard
+///
+/// vCards of the clients of all matching projects, or of all projects in `dir` if there are no search terms.
+/// Every client appears only once.
+pub fn vcards(dir:StorageDir, search_terms:&[&str]) -> Result<String> {
+    let luigi = setup_luigi()?;
+    let projects = if search_terms.is_empty() {
+        luigi.open_projects(dir)?.projects
+    } else {
+        luigi.search_projects_any(dir, search_terms)?
+    };
+    Ok(::import::vcard::contacts(&projects).iter().map(|c| c.to_vcard()).collect())
+}
+
+/// Command BANK IMPORT
+///
+/// Reads a statement and proposes which credit pays which open invoice of the working directory.
+pub fn bank_matches(statement:&Path) -> Result<::bank::Matching> {
+    let transactions = ::bank::read_statement(statement)?;
+    let luigi = setup_luigi()?;
+    let projects = luigi.open_projects(StorageDir::Working)?;
+    Ok(::bank::match_payments(&transactions, &projects))
+}
+
+/// Command BANK IMPORT
+///
+/// Writes the dates of the confirmed payments into their project files.
+pub fn record_payments(matches:&[::bank::Match]) -> Result<()> {
+    let mut commit = AutoCommit::new("bank import");
+    for payment in matches {
+        let project = Project::open_file(&payment.project_file)?;
+        project.set_payed_date(payment.transaction.date)?;
+        commit.touch(&project, &[payment.project_file.to_owned()]);
+    }
+    auto_commit(&commit)
+}
+
+/// Command IMPORT-BUNDLE
+pub fn import_bundle(bundle:&Path) -> Result<ImportReport> {
+    let luigi = setup_luigi_with_git()?;
+    Ok(luigi.import_bundle(bundle)?)
 }
 
 pub fn archive_all_projects() -> Result<Vec<PathBuf>> {
@@ -307,6 +703,7 @@ pub fn archive_all_projects() -> Result<Vec<PathBuf>> {
         moved_files.push(project.dir());
         moved_files.append(&mut luigi.archive_project(&project, project.year().unwrap())?);
     }
+    auto_commit(&moved_projects_commit("archive", &moved_files))?;
     Ok(moved_files)
 }
 
@@ -314,18 +711,241 @@ pub fn archive_all_projects() -> Result<Vec<PathBuf>> {
 /// TODO: return a list of files that have to be updated in git
 pub fn unarchive_projects(year:i32, search_terms:&[&str]) -> Result<Vec<PathBuf>> {
     let luigi = setup_luigi_with_git()?;
-    Ok( luigi.unarchive_projects(year, search_terms) ?)
+    let moved_files = luigi.unarchive_projects(year, search_terms)?;
+    auto_commit(&moved_projects_commit("unarchive", &moved_files))?;
+    Ok(moved_files)
+}
+
+/// One commit in the history of a project.
+#[derive(Debug)]
+pub struct HistoryEntry {
+    pub commit: LogEntry,
+    pub changes: Vec<Change>
+}
+
+/// Command HISTORY
+///
+/// What happened to a project, newest first.
+/// Works like `find_unique_project`, so archived projects are found as `archive/<year>/<search term>`.
+pub fn history(description:&str) -> Result<Vec<HistoryEntry>> {
+    let luigi = setup_luigi_with_git()?;
+    let project = find_unique_project(&luigi, description)?;
+    let repo = luigi.repository().ok_or("storage is not a git repository")?;
+    let mut revisions = repo.file_history(&project.file()).map_err(StorageError::from)?;
+    revisions.reverse();
+
+    let mut entries = Vec::new();
+    let mut previous_path = None;
+    let mut previous_snapshot = None;
+    for revision in revisions {
+        let mut changes = Vec::new();
+        match previous_path {
+            None => changes.push(Change::Created),
+            Some(ref path) if *path != revision.path =>
+                changes.push(Change::Moved{ from: path.to_owned(), to: revision.path.to_owned() }),
+            _ => {}
+        }
+
+        match Project::from_content(&revision.path, revision.content) {
+            Ok(project) => {
+                let snapshot = history::snapshot(&project);
+                if let Some(ref previous) = previous_snapshot {
+                    changes.append(&mut history::changes(previous, &snapshot));
+                }
+                previous_snapshot = Some(snapshot);
+            },
+            Err(err) => changes.push(Change::Unreadable(err.to_string()))
+        }
+
+        previous_path = Some(revision.path);
+        entries.push(HistoryEntry{ commit: revision.commit, changes: changes });
+    }
+
+    entries.reverse();
+    Ok(entries)
+}
+
+fn read_to_string(path:&Path) -> Result<String> {
+    use std::io::Read;
+    let mut content = String::new();
+    fs::File::open(path)?.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+/// Command MERGE-DRIVER
+///
+/// Merges project files key by key, as git's `%O %A %B`, the result is written to `ours`.
+/// Returns what could not be merged, nothing is written if the result would not be a valid project.
+pub fn merge_project_files(base:&Path, ours:&Path, theirs:&Path) -> Result<Vec<String>> {
+    let ours_content = read_to_string(ours)?;
+    let merged = merge::merge(&read_to_string(base)?, &ours_content, &read_to_string(theirs)?);
+    let mut problems = merged.conflicts.iter().map(|c| c.to_string()).collect::<Vec<_>>();
+
+    let merged_project = match Project::from_content(ours, merged.content.to_owned()) {
+        Ok(project) => project,
+        Err(err) => {
+            problems.push(format!("the merged file can't be parsed: {}", err));
+            return Ok(problems);
+        }
+    };
+    let ours_valid = Project::from_content(ours, ours_content).map(|p| p.is_valid()).unwrap_or(false);
+    if ours_valid {
+        if let Err(errors) = merged_project.validate() {
+            problems.push(format!("the merged file is not valid: {}", errors));
+            return Ok(problems);
+        }
+    }
+
+    let mut file = fs::File::create(ours)?;
+    ::std::io::Write::write_all(&mut file, merged.content.as_bytes())?;
+    Ok(problems)
+}
+
+/// Command MERGE-DRIVER --install
+///
+/// Registers `asciii merge-driver` for all project files of the storage repository.
+pub fn install_merge_driver() -> Result<()> {
+    use std::io::Write;
+
+    let luigi = setup_luigi_with_git()?;
+    let repo = luigi.repository().ok_or("storage is not a git repository")?;
+    let exe = env::current_exe().map(|p| p.display().to_string()).unwrap_or_else(|_| "asciii".to_owned());
+    repo.install_merge_driver("asciii",
+                              "asciii project files",
+                              &format!("{} merge-driver %O %A %B", exe))
+        .map_err(StorageError::from)?;
+
+    let extension = ::CONFIG.get_str("extensions/project_file").expect("Faulty default config");
+    let attribute = format!("*.{} merge=asciii", extension);
+    let attributes_path = luigi.root_dir().join(".gitattributes");
+    let attributes = if attributes_path.exists() { read_to_string(&attributes_path)? } else { String::new() };
+    if !attributes.lines().any(|line| line.trim() == attribute) {
+        let mut file = fs::OpenOptions::new().create(true).append(true).open(&attributes_path)?;
+        if !attributes.is_empty() && !attributes.ends_with('\n') {
+            writeln!(file, "")?;
+        }
+        writeln!(file, "{}", attribute)?;
+    }
+    Ok(())
+}
+
+/// Command INIT
+///
+/// Creates the storage directories at `path` (default: the configured storage path),
+/// optionally a git repository, and installs the built-in templates that are missing.
+/// Returns the root of the storage and the installed templates.
+pub fn init_storage(path:Option<&Path>, git:bool) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let root = match path {
+        Some(path) if path.is_absolute() => path.to_owned(),
+        Some(path) => env::current_dir()?.join(path),
+        None => util::get_storage_path()
+    };
+    let working   = ::CONFIG.get_str("dirs/working").ok_or("Faulty config: dirs/working does not contain a value")?;
+    let archive   = ::CONFIG.get_str("dirs/archive").ok_or("Faulty config: dirs/archive does not contain a value")?;
+    let templates = ::CONFIG.get_str("dirs/templates").ok_or("Faulty config: dirs/templates does not contain a value")?;
+
+    let storage:Storage<Project> = Storage::new(&root, working, archive, templates)?;
+    storage.create_dirs()?;
+
+    let storage = if git {
+        if !root.join(".git").exists() {
+            Repository::init(&root).map_err(StorageError::from)?;
+        }
+        Storage::new_with_git(&root, working, archive, templates)?
+    } else {
+        storage
+    };
+
+    let installed = storage.install_templates()?;
+    Ok((root, installed))
+}
+
+/// Command TEMPLATES
+pub fn compare_templates() -> Result<Vec<TemplateComparison>> {
+    let luigi = setup_luigi()?;
+    Ok(luigi.compare_templates()?)
 }
 
 /// Command CALENDAR
-pub fn calendar(dir: StorageDir) -> Result<String> {
+pub fn calendar(dir: StorageDir, use_cache:bool) -> Result<String> {
     let luigi = setup_luigi()?;
-    let projects = luigi.open_projects(dir)?;
-    let mut cal = Calendar::new();
-    for project in projects {
-        cal.append(&mut project.to_ical())
-    }
-    Ok(cal.to_string())
+    let events = luigi.open_index(dir, &["Ical"], use_cache)?
+        .iter()
+        .filter_map(|entry| entry.get("Ical"))
+        .collect::<String>();
+    let mut cal = Calendar::new().to_string();
+    let end = cal.rfind("END:VCALENDAR").unwrap_or(cal.len());
+    cal.insert_str(end, &events);
+    Ok(cal)
 }
 
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::path::Path;
+    use tempdir::TempDir;
+
+    use super::{merge_project_files, read_to_string};
+
+    fn write(path:&Path, content:&str) {
+        use std::io::Write;
+        fs::File::create(path).unwrap().write_all(content.as_bytes()).unwrap();
+    }
+
+    fn merge_into(dir:&Path, ours:&str, theirs:&str) -> (Vec<String>, String) {
+        let base = read_to_string(Path::new("./tests/current.yml")).unwrap();
+        let paths = ["base", "ours", "theirs"].iter().map(|name| dir.join(name)).collect::<Vec<_>>();
+        write(&paths[0], &base);
+        write(&paths[1], ours);
+        write(&paths[2], theirs);
+        let problems = merge_project_files(&paths[0], &paths[1], &paths[2]).unwrap();
+        (problems, read_to_string(&paths[1]).unwrap())
+    }
+
+    #[test]
+    fn merge_writes_valid_result(){
+        let dir = TempDir::new("merge").unwrap();
+        let base = read_to_string(Path::new("./tests/current.yml")).unwrap();
+        let ours = base.replace("name: Party Hard", "name: Party Harder");
+        let theirs = base.replace("number: 41", "number: 42");
+
+        let (problems, merged) = merge_into(dir.path(), &ours, &theirs);
+        assert!(problems.is_empty());
+        assert!(merged.contains("name: Party Harder"));
+        assert!(merged.contains("number: 42"));
+    }
+
+    #[test]
+    fn merge_keeps_ours_if_invalid(){
+        let dir = TempDir::new("merge").unwrap();
+        let base = read_to_string(Path::new("./tests/current.yml")).unwrap();
+        let ours = base.replace("name: Party Hard", "name: Party Harder");
+        let theirs = base.replace("manager: Hendrik Sollich\n", "");
+
+        let (problems, merged) = merge_into(dir.path(), &ours, &theirs);
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("manager"));
+        assert_eq!(merged, ours);
+    }
 
+    #[test]
+    #[cfg(feature="document_export")]
+    fn wait_for_change_debounces(){
+        use std::cell::Cell;
+        use std::time::Duration;
+        use super::wait_for_change;
+
+        // one save, then two more in quick succession
+        let states = [1, 1, 1, 2, 3, 3, 3];
+        let polled = Cell::new(0);
+        let state = || {
+            let index = polled.get().min(states.len() - 1);
+            polled.set(polled.get() + 1);
+            states[index]
+        };
+
+        let none = Duration::from_millis(0);
+        assert_eq!(wait_for_change(&1, none, none, &state), 3);
+        assert_eq!(polled.get(), 6);
+    }
+}
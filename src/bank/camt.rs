@@ -0,0 +1,123 @@
+//! CAMT.053 account statements (ISO 20022 `BkToCstmrStmt`).
+//!
+//! Only what is needed for matching is read: amount, credit or debit, booking date,
+//! the other party's name and the remittance information of every `Ntry`.
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+use super::{Transaction, parse_date, parse_amount};
+
+/// Every element that is read.
+const TAGS: [&'static str; 14] = ["BkToCstmrStmt", "Ntry", "Amt", "CdtDbtInd", "BookgDt", "ValDt", "Dt", "DtTm",
+                                  "Dbtr", "Cdtr", "Nm", "Ustrd", "RmtInf", "Ref"];
+
+lazy_static!{
+    /// One regex per tag, statements have thousands of entries.
+    static ref ELEMENTS: HashMap<&'static str, Regex> = TAGS.iter().map(|tag| {
+        let pattern = format!(r"(?s)<(?:\w+:)?{tag}(?:\s[^>]*)?>(.*?)</(?:\w+:)?{tag}>", tag = tag);
+        (*tag, Regex::new(&pattern).expect("broken regex"))
+    }).collect();
+}
+
+/// Contents of every `<tag>` in `xml`, namespace prefixes are ignored.
+fn elements<'a>(xml:&'a str, tag:&str) -> Vec<&'a str> {
+    ELEMENTS.get(tag).expect("tag is missing in TAGS")
+        .captures_iter(xml)
+        .filter_map(|caps| caps.at(1))
+        .collect()
+}
+
+fn first<'a>(xml:&'a str, tag:&str) -> Option<&'a str> {
+    elements(xml, tag).into_iter().next()
+}
+
+fn unescape(text:&str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Reads all entries of all statements in the document.
+pub fn parse(xml:&str) -> Result<Vec<Transaction>, String> {
+    if first(xml, "BkToCstmrStmt").is_none() {
+        return Err(String::from("not a CAMT.053 statement, BkToCstmrStmt is missing"));
+    }
+
+    let mut transactions = Vec::new();
+    for entry in elements(xml, "Ntry") {
+        let amount = first(entry, "Amt").and_then(parse_amount)
+            .ok_or_else(|| format!("entry without amount: {}", entry.trim()))?;
+        let credit = first(entry, "CdtDbtInd").map_or(true, |indicator| indicator.trim() == "CRDT");
+        let date = first(entry, "BookgDt").or_else(|| first(entry, "ValDt"))
+            .and_then(|date| first(date, "Dt").or_else(|| first(date, "DtTm")))
+            .and_then(|date| parse_date(&date[..date.len().min(10)]))
+            .ok_or_else(|| format!("entry without booking date: {}", entry.trim()))?;
+
+        // a credit names the debtor, a debit the creditor
+        let party = if credit { first(entry, "Dbtr") } else { first(entry, "Cdtr") };
+        let name = party.and_then(|party| first(party, "Nm")).map(unescape).unwrap_or_else(String::new);
+        let reference = elements(entry, "Ustrd").into_iter()
+            .chain(first(entry, "RmtInf").and_then(|info| first(info, "Ref")))
+            .map(|r| unescape(r.trim()))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        transactions.push(Transaction {
+            date: date,
+            amount: if credit { amount } else { -amount },
+            name: name,
+            reference: reference
+        });
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::*;
+    use super::*;
+
+    static STATEMENT: &'static str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <Stmt>
+      <Ntry>
+        <Amt Ccy="EUR">45.20</Amt>
+        <CdtDbtInd>CRDT</CdtDbtInd>
+        <BookgDt><Dt>2016-12-20</Dt></BookgDt>
+        <NtryDtls><TxDtls>
+          <RltdPties><Dbtr><Nm>Graf Zahl &amp; Söhne</Nm></Dbtr></RltdPties>
+          <RmtInf><Ustrd>Rechnung R2016-042</Ustrd><Ustrd>Party Hard</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">12.00</Amt>
+        <CdtDbtInd>DBIT</CdtDbtInd>
+        <BookgDt><DtTm>2016-12-21T10:00:00</DtTm></BookgDt>
+        <NtryDtls><TxDtls>
+          <RltdPties><Cdtr><Nm>Kaffeerösterei</Nm></Cdtr></RltdPties>
+        </TxDtls></NtryDtls>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+
+    #[test]
+    fn entries(){
+        let transactions = parse(STATEMENT).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].date, UTC.ymd(2016, 12, 20));
+        assert_eq!(transactions[0].amount, 45.2);
+        assert_eq!(transactions[0].name, "Graf Zahl & Söhne");
+        assert_eq!(transactions[0].reference, "Rechnung R2016-042 Party Hard");
+        assert_eq!(transactions[1].amount, -12.0);
+        assert_eq!(transactions[1].date, UTC.ymd(2016, 12, 21));
+        assert_eq!(transactions[1].name, "Kaffeerösterei");
+        assert!(parse("<Document/>").is_err());
+    }
+}
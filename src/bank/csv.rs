@@ -0,0 +1,111 @@
+//! Csv exports of online banking.
+//!
+//! Banks don't agree on much, so the header line is searched for among the first lines
+//! and columns are found by their usual (german or english) names.
+
+use util::cp1252;
+use super::{Transaction, parse_date, parse_amount};
+
+const DATE_COLUMNS: [&'static str; 6] = ["buchungstag", "buchungsdatum", "valutadatum", "wertstellung", "datum", "date"];
+const AMOUNT_COLUMNS: [&'static str; 4] = ["betrag", "umsatz", "amount", "betrag (eur)"];
+const NAME_COLUMNS: [&'static str; 6] = ["beguenstigter/zahlungspflichtiger", "auftraggeber / begünstigter",
+                                         "auftraggeber/empfänger", "zahlungspflichtiger", "name", "payee"];
+const REFERENCE_COLUMNS: [&'static str; 4] = ["verwendungszweck", "buchungstext", "reference", "purpose"];
+
+/// Statements are utf-8 or, more likely, Windows-1252.
+pub fn decode(bytes:&[u8]) -> String {
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(text) => text,
+        Err(_) => cp1252::decode(bytes)
+    }
+}
+
+/// Splits one line, respecting quotes.
+fn fields(line:&str, delimiter:char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => { field.push('"'); chars.next(); },
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(::std::mem::replace(&mut field, String::new())),
+            c => field.push(c)
+        }
+    }
+    fields.push(field);
+    fields.into_iter().map(|f| f.trim().to_owned()).collect()
+}
+
+fn column(header:&[String], names:&[&str]) -> Option<usize> {
+    names.iter()
+         .filter_map(|name| header.iter().position(|h| h.to_lowercase() == *name))
+         .next()
+}
+
+pub fn parse(content:&str) -> Result<Vec<Transaction>, String> {
+    let lines = content.lines().collect::<Vec<_>>();
+    let delimiter = if lines.iter().take(20).any(|l| l.contains(';')) { ';' } else { ',' };
+
+    let (header_index, header) = lines.iter()
+        .take(20)
+        .map(|line| fields(line, delimiter))
+        .enumerate()
+        .find(|&(_, ref header)| column(header, &DATE_COLUMNS).is_some() && column(header, &AMOUNT_COLUMNS).is_some())
+        .ok_or("no header line with a date and an amount column found")?;
+
+    let date = column(&header, &DATE_COLUMNS).unwrap_or(0);
+    let amount = column(&header, &AMOUNT_COLUMNS).unwrap_or(0);
+    let name = column(&header, &NAME_COLUMNS);
+    let reference = column(&header, &REFERENCE_COLUMNS);
+
+    let mut transactions = Vec::new();
+    for (index, line) in lines.iter().enumerate().skip(header_index + 1) {
+        if line.trim().is_empty() { continue }
+        let row = fields(line, delimiter);
+        let get = |column:Option<usize>| column.and_then(|c| row.get(c)).map(|v| v.to_owned()).unwrap_or_else(String::new);
+
+        let parsed_date = match parse_date(&get(Some(date))) {
+            Some(date) => date,
+            // closing balances and the like
+            None => continue
+        };
+        let parsed_amount = parse_amount(&get(Some(amount)))
+            .ok_or_else(|| format!("line {}: can't read amount {:?}", index + 1, get(Some(amount))))?;
+
+        transactions.push(Transaction {
+            date: parsed_date,
+            amount: parsed_amount,
+            name: get(name),
+            reference: get(reference)
+        });
+    }
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::*;
+    use super::*;
+
+    #[test]
+    fn sparkasse(){
+        let csv = "\"Auftragskonto\";\"Buchungstag\";\"Valutadatum\";\"Buchungstext\";\"Verwendungszweck\";\"Beguenstigter/Zahlungspflichtiger\";\"Betrag\";\"Waehrung\"\n\
+                   \"DE02120300000000202051\";\"20.12.16\";\"20.12.16\";\"GUTSCHRIFT\";\"RE R042; Party Hard\";\"Graf Zahl\";\"1.045,20\";\"EUR\"\n\
+                   \"DE02120300000000202051\";\"21.12.16\";\"21.12.16\";\"LASTSCHRIFT\";\"Kaffee\";\"Kaffeerösterei\";\"-12,00\";\"EUR\"\n";
+        let transactions = parse(csv).unwrap();
+        assert_eq!(transactions.len(), 2);
+        assert_eq!(transactions[0].date, UTC.ymd(2016, 12, 20));
+        assert_eq!(transactions[0].amount, 1045.2);
+        assert_eq!(transactions[0].name, "Graf Zahl");
+        assert_eq!(transactions[0].reference, "RE R042; Party Hard");
+        assert_eq!(transactions[1].amount, -12.0);
+    }
+
+    #[test]
+    fn windows_1252(){
+        assert_eq!(decode(b"Kaffeer\xf6sterei \x80"), "Kaffeerösterei €");
+        assert_eq!(decode(b"\x8aerif \x85 \x91Z\x92"), "Šerif … ‘Z’");
+    }
+}
@@ -0,0 +1,252 @@
+//! Reading bank statements and matching their credits to open invoices.
+//!
+//! Statements come as CAMT.053 xml ([`camt`](camt/index.html)) or as the csv export
+//! of online banking ([`csv`](csv/index.html)).
+//! A credit matches an invoice if its reference text contains the invoice number (`R042` or `R2016-042`),
+//! or, failing that, if its amount equals the gross total of exactly one open invoice.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use chrono::*;
+
+use project::Project;
+use project::spec::{IsProject, Invoicable, Redeemable};
+use storage::Storable;
+
+pub mod camt;
+pub mod csv;
+
+/// One booking on the account, credits are positive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub date: Date<UTC>,
+    pub amount: f64,
+    /// Who sent or received the money.
+    pub name: String,
+    /// Verwendungszweck
+    pub reference: String
+}
+
+impl fmt::Display for Transaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:>10.2} {} {:?}", self.date.format("%d.%m.%Y"), self.amount, self.name, self.reference)
+    }
+}
+
+/// Reads a statement, CAMT.053 if it looks like xml, csv otherwise.
+pub fn read_statement(path:&Path) -> Result<Vec<Transaction>, String> {
+    use std::fs::File;
+    use std::io::Read;
+
+    let mut bytes = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    let content = csv::decode(&bytes);
+
+    if content.trim_left().starts_with('<') {
+        camt::parse(&content)
+    } else {
+        csv::parse(&content)
+    }
+}
+
+/// Why a credit was matched to an invoice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Evidence {
+    /// Invoice number in the reference and the right amount.
+    NumberAndAmount,
+    /// Invoice number in the reference, but a different amount.
+    Number,
+    /// No number, but the only open invoice of that amount.
+    Amount
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Evidence::NumberAndAmount => "number and amount",
+            Evidence::Number          => "number only, amount differs",
+            Evidence::Amount          => "amount only"
+        })
+    }
+}
+
+/// A proposed payment.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub transaction: Transaction,
+    pub project_file: PathBuf,
+    pub project: String,
+    /// Gross total of the invoice.
+    pub total: f64,
+    pub evidence: Evidence
+}
+
+/// Everything found in a statement.
+#[derive(Debug, Clone)]
+pub struct Matching {
+    pub matches: Vec<Match>,
+    /// Credits that could not be matched.
+    pub unmatched: Vec<Transaction>
+}
+
+/// Whether `number` occurs in `text` on its own, `R042` is not in `R0421`.
+fn mentions(text:&str, number:&str) -> bool {
+    let text = text.to_uppercase();
+    let number = number.to_uppercase();
+    text.match_indices(&number).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + number.len()..].chars().next();
+        !before.map_or(false, |c| c.is_alphanumeric()) && !after.map_or(false, |c| c.is_digit(10))
+    })
+}
+
+struct OpenInvoice<'a> {
+    project: &'a Project,
+    numbers: Vec<String>,
+    total: f64
+}
+
+fn same_amount(a:f64, b:f64) -> bool {
+    (a - b).abs() < 0.005
+}
+
+/// Matches the credits of `transactions` to invoices of `projects` that are not payed yet.
+///
+/// Every invoice is matched at most once, the earlier credit wins.
+pub fn match_payments(transactions:&[Transaction], projects:&[Project]) -> Matching {
+    let mut open = projects.iter()
+        .filter(|p| !p.canceled() && p.payed_date().is_none())
+        .filter_map(|p| {
+            let numbers = vec![p.invoice().number_long_str(), p.invoice().number_str()]
+                .into_iter().filter_map(|n| n).collect::<Vec<_>>();
+            let total = p.bills().ok().map(|(_, invoice)| invoice.net_total().as_float());
+            match (numbers.is_empty(), total) {
+                (false, Some(total)) => Some(OpenInvoice { project: p, numbers: numbers, total: total }),
+                _ => None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let mut matching = Matching { matches: Vec::new(), unmatched: Vec::new() };
+    for transaction in transactions.iter().filter(|t| t.amount > 0.0) {
+        let by_number = open.iter()
+            .position(|invoice| invoice.numbers.iter().any(|n| mentions(&transaction.reference, n)));
+        let found = match by_number {
+            Some(index) => Some((index, if same_amount(open[index].total, transaction.amount) {
+                Evidence::NumberAndAmount
+            } else {
+                Evidence::Number
+            })),
+            None => {
+                let same = open.iter()
+                    .enumerate()
+                    .filter(|&(_, invoice)| same_amount(invoice.total, transaction.amount))
+                    .map(|(index, _)| index)
+                    .collect::<Vec<_>>();
+                if same.len() == 1 { Some((same[0], Evidence::Amount)) } else { None }
+            }
+        };
+
+        match found {
+            Some((index, evidence)) => {
+                let invoice = open.remove(index);
+                matching.matches.push(Match {
+                    transaction: transaction.to_owned(),
+                    project_file: invoice.project.file(),
+                    project: invoice.project.short_desc(),
+                    total: invoice.total,
+                    evidence: evidence
+                });
+            },
+            None => matching.unmatched.push(transaction.to_owned())
+        }
+    }
+    matching
+}
+
+/// `"20.12.2016"`, `"20.12.16"` or `"2016-12-20"`
+pub fn parse_date(date:&str) -> Option<Date<UTC>> {
+    let date = date.trim();
+    NaiveDate::parse_from_str(date, "%d.%m.%Y")
+        .or_else(|_| NaiveDate::parse_from_str(date, "%Y-%m-%d"))
+        .ok()
+        .or_else(|| NaiveDate::parse_from_str(date, "%d.%m.%y").ok().map(|d| {
+            // two digit years are this century
+            if d.year() < 2000 { d.with_year(d.year() + 100).unwrap_or(d) } else { d }
+        }))
+        .map(|d| UTC.from_utc_date(&d))
+}
+
+/// `"1.234,50"`, `"1,234.50"`, `"-12,00 EUR"`
+pub fn parse_amount(amount:&str) -> Option<f64> {
+    let amount = amount.chars()
+        .filter(|c| c.is_digit(10) || *c == ',' || *c == '.' || *c == '-')
+        .collect::<String>();
+    let amount = match (amount.rfind(','), amount.rfind('.')) {
+        (Some(comma), Some(dot)) if comma > dot => amount.replace('.', "").replace(',', "."),
+        (Some(_), Some(_))                      => amount.replace(',', ""),
+        (Some(_), None)                         => amount.replace(',', "."),
+        _                                       => amount
+    };
+    amount.parse().ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use chrono::*;
+
+    use super::*;
+    use project::Project;
+    use storage::Storable;
+
+    fn credit(day:u32, amount:f64, reference:&str) -> Transaction {
+        Transaction { date: UTC.ymd(2016, 12, day), amount: amount, name: String::from("Graf Zahl"), reference: reference.to_owned() }
+    }
+
+    #[test]
+    fn numbers_in_references(){
+        assert!(mentions("Rechnung R042 vom 06.12.", "R042"));
+        assert!(mentions("rechnung r2016-042", "R2016-042"));
+        assert!(!mentions("R0421", "R042"));
+        assert!(!mentions("AR042", "R042"));
+    }
+
+    #[test]
+    fn amounts_and_dates(){
+        assert_eq!(parse_amount("1.234,50"), Some(1234.5));
+        assert_eq!(parse_amount("1,234.50"), Some(1234.5));
+        assert_eq!(parse_amount("-12,00 EUR"), Some(-12.0));
+        assert_eq!(parse_amount("45.2"), Some(45.2));
+        assert_eq!(parse_date("20.12.2016"), Some(UTC.ymd(2016, 12, 20)));
+        assert_eq!(parse_date("20.12.16"), Some(UTC.ymd(2016, 12, 20)));
+        assert_eq!(parse_date("2016-12-20"), Some(UTC.ymd(2016, 12, 20)));
+    }
+
+    #[test]
+    fn matching(){
+        // R2016-042 is open over 45.20, R2014-041 is already payed
+        let projects = vec![Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap(),
+                            Project::open_file(Path::new("./tests/current.yml")).unwrap()];
+
+        let by_number = match_payments(&[credit(20, 45.2, "RE R2016-042 Party Hard")], &projects);
+        assert_eq!(by_number.matches.len(), 1);
+        assert_eq!(by_number.matches[0].evidence, Evidence::NumberAndAmount);
+
+        let wrong_amount = match_payments(&[credit(20, 40.0, "R042")], &projects);
+        assert_eq!(wrong_amount.matches[0].evidence, Evidence::Number);
+
+        let by_amount = match_payments(&[credit(20, 45.2, "Catering")], &projects);
+        assert_eq!(by_amount.matches[0].evidence, Evidence::Amount);
+
+        // only matched once, payed invoices and debits are ignored
+        let twice = match_payments(&[credit(20, 45.2, "R042"), credit(21, 45.2, "R042"), credit(22, 100.0, "R041"), credit(23, -45.2, "R042")], &projects);
+        assert_eq!(twice.matches.len(), 1);
+        assert_eq!(twice.matches[0].transaction.date, UTC.ymd(2016, 12, 20));
+        assert_eq!(twice.unmatched.len(), 2);
+    }
+}
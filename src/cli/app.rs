@@ -1,4 +1,8 @@
+use std::env;
+
 use asciii;
+use asciii::config::PROFILE_VAR;
+use asciii::util::yaml::parse_dmy_date;
 use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use super::subcommands;
 
@@ -10,6 +14,13 @@ pub fn build_cli() -> App<'static, 'static> {
         .settings(&[AppSettings::SubcommandRequiredElseHelp,AppSettings::ColoredHelp])
         .after_help(asciii::DOCUMENTATION_URL)
 
+        .arg(Arg::with_name("profile")
+             .help("Use a profile from the config, overrides ASCIII_PROFILE")
+             .long("profile")
+             .takes_value(true)
+             .global(true)
+            )
+
         .subcommand(SubCommand::with_name("doc")
             .about("Opens the online documentation, please read it")
         )
@@ -23,7 +34,7 @@ pub fn build_cli() -> App<'static, 'static> {
 
                     .arg(Arg::with_name("name")
                          .help("Project name")
-                         .required(true))
+                         .required_unless("from-ics"))
 
                     .arg(Arg::with_name("date")
                          .help("Manually set the date of the project")
@@ -42,6 +53,29 @@ pub fn build_cli() -> App<'static, 'static> {
                          .long("template")
                          .short("t"))
 
+                    .arg(Arg::with_name("from")
+                         .help("Copy client, location, products and staff from an existing project, e.g. \"name\" or \"archive/2016/name\"")
+                         .long("from")
+                         .takes_value(true)
+                         .conflicts_with("template"))
+
+                    .arg(Arg::with_name("from-ics")
+                         .help("Fill in name, dates, times, location and description from the events of an iCalendar file, one project per event")
+                         .long("from-ics")
+                         .takes_value(true)
+                         .conflicts_with("from"))
+
+                    .arg(Arg::with_name("client-vcard")
+                         .help("Fill in title, name, email and address of the client from a vCard")
+                         .long("client-vcard")
+                         .takes_value(true)
+                         .conflicts_with("from"))
+
+                    .arg(Arg::with_name("by-uid")
+                         .help("With --from-ics: one project per UID, with all dates of its events")
+                         .long("by-uid")
+                         .requires("from-ics"))
+
                     .arg(Arg::with_name("editor")
                          .help("Override the configured editor")
                          .long("editor")
@@ -170,11 +204,11 @@ pub fn build_cli() -> App<'static, 'static> {
                          .short("p")
                         )
 
-                    //.arg(Arg::with_name("broken")
-                    //     .help("List broken projects  without project file")
-                    //     .long("broken")
-                    //     .short("b")
-                    //    )
+                    .arg(Arg::with_name("broken")
+                         .help("List broken projects and why they can't be opened")
+                         .long("broken")
+                         .short("b")
+                        )
 
                     .arg(Arg::with_name("computed_fields")
                          .help("List all computed data fields that can be used with --details")
@@ -187,6 +221,11 @@ pub fn build_cli() -> App<'static, 'static> {
                          .long("nothing")
                          .short("x")
                         )
+
+                    .arg(Arg::with_name("no-cache")
+                         .help("Open every project instead of using the cached index")
+                         .long("no-cache")
+                        )
                     )
 
         .subcommand(SubCommand::with_name("csv")
@@ -198,6 +237,11 @@ pub fn build_cli() -> App<'static, 'static> {
                          .validator(|y| y.parse::<i32>().map(|_ok|()).map_err(|e|e.to_string()))
                          .takes_value(true)
                         )
+
+                    .arg(Arg::with_name("no-cache")
+                         .help("Open every project instead of using the cached index")
+                         .long("no-cache")
+                        )
                     )
 
         .subcommand(SubCommand::with_name("archive")
@@ -394,7 +438,7 @@ pub fn build_cli() -> App<'static, 'static> {
                     .about("Display a specific project")
                     .arg(Arg::with_name("search_term")
                          .help("Search term, possibly event name")
-                         .required(true)
+                         .required_unless("vcard")
                          .multiple(true)
                         )
 
@@ -408,6 +452,17 @@ pub fn build_cli() -> App<'static, 'static> {
                          .long("ical")
                          .short("C"))
 
+                    .arg(Arg::with_name("vcard")
+                         .help("Show the clients as vCards, each only once, without search terms all of --year or --archive")
+                         .long("vcard"))
+
+                    .arg(Arg::with_name("year")
+                         .help("Pick projects from that year, archived or not")
+                         .long("year")
+                         .takes_value(true)
+                         .conflicts_with("archive")
+                        )
+
                     .arg(Arg::with_name("dump")
                          .help("Dump project yaml")
                          .long("dump"))
@@ -443,6 +498,7 @@ pub fn build_cli() -> App<'static, 'static> {
                          .help("Show show fields in templates that are filled")
                          .long("template")
                          .short("t")
+                         .requires("search_term")
                         )
                     //#conflicts_with: archive  # this causes a crash
 
@@ -501,6 +557,11 @@ pub fn build_cli() -> App<'static, 'static> {
                          .help("List all projects, ever")
                          .short("A")
                          .long("all"))
+
+                    .arg(Arg::with_name("no-cache")
+                         .help("Open every project instead of using the cached index")
+                         .long("no-cache")
+                        )
                    )
 
         .subcommand(SubCommand::with_name("spec")
@@ -541,12 +602,34 @@ pub fn build_cli() -> App<'static, 'static> {
                          .long("invoice")
                          )
 
+                    .arg(Arg::with_name("reissue")
+                         .help("Produce an invoice again although it changed since it was issued, records why")
+                         .long("reissue")
+                         .value_name("reason")
+                         .takes_value(true)
+                         .conflicts_with("offer")
+                         )
+
                     .arg(Arg::with_name("template")
                          .help("Use a particular template")
                          .short("t")
                          .long("template")
                          .takes_value(true)
                          )
+
+                    .arg(Arg::with_name("format")
+                         .help("Output format, html needs a .html.hbs template, xrechnung produces an e-invoice")
+                         .long("format")
+                         .takes_value(true)
+                         .possible_values(&["latex", "html", "xrechnung"])
+                         .default_value("latex")
+                         )
+
+                    .arg(Arg::with_name("watch")
+                         .help("Render again whenever the project or the template changes")
+                         .short("w")
+                         .long("watch")
+                         )
                    )
 
         .subcommand(SubCommand::with_name("delete")
@@ -578,6 +661,137 @@ pub fn build_cli() -> App<'static, 'static> {
                     //    )
                    )
 
+        .subcommand(SubCommand::with_name("trash")
+                    .about("Show, restore or remove deleted projects")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("list")
+                                .aliases(&["ls"])
+                                .about("List deleted projects")
+                               )
+                    .subcommand(SubCommand::with_name("restore")
+                                .about("Move a deleted project back to where it was")
+                                .arg(Arg::with_name("item")
+                                     .help("Id or name of the deleted project, as shown by \"trash list\"")
+                                     .required(true))
+                               )
+                    .subcommand(SubCommand::with_name("empty")
+                                .about("Remove all deleted projects for good")
+                                .arg(Arg::with_name("yes")
+                                     .help("Do not ask for confirmation")
+                                     .long("yes")
+                                     .short("y"))
+                               )
+                   )
+
+        .subcommand(SubCommand::with_name("undo")
+                    .about("Revert the last archive, unarchive or delete")
+                   )
+
+        .subcommand(SubCommand::with_name("export-bundle")
+                    .about("Pack all projects of a year, their documents and templates into one file")
+                    .arg(Arg::with_name("year")
+                         .help("The year to export, defaults to the current year")
+                         .long("year")
+                         .short("y")
+                         .takes_value(true)
+                         .validator(|y| y.parse::<i32>().map(|_ok|()).map_err(|e|e.to_string()))
+                        )
+                    .arg(Arg::with_name("output")
+                         .help("Where to write the bundle, defaults to asciii-YEAR.tar")
+                         .long("output")
+                         .short("o")
+                         .takes_value(true)
+                        )
+                   )
+
+        .subcommand(SubCommand::with_name("export")
+                    .about("Export bookings for the accountant")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("datev")
+                                .about("DATEV booking batch (EXTF) of invoices and payments in a date range")
+                                .arg(Arg::with_name("from")
+                                     .help("First day, e.g. 01.01.2017")
+                                     .long("from")
+                                     .takes_value(true)
+                                     .required(true)
+                                     .validator(|d| parse_dmy_date(&d).map(|_ok|()).ok_or_else(|| format!("can't read date {:?}", d)))
+                                    )
+                                .arg(Arg::with_name("to")
+                                     .help("Last day, e.g. 31.03.2017")
+                                     .long("to")
+                                     .takes_value(true)
+                                     .required(true)
+                                     .validator(|d| parse_dmy_date(&d).map(|_ok|()).ok_or_else(|| format!("can't read date {:?}", d)))
+                                    )
+                                .arg(Arg::with_name("output")
+                                     .help("Where to write the bookings, defaults to EXTF_Buchungsstapel_FROM_TO.csv")
+                                     .long("output")
+                                     .short("o")
+                                     .takes_value(true)
+                                    )
+                               )
+                    .subcommand(SubCommand::with_name("ledger")
+                                .about("Journal of invoices, payments and wages for ledger and hledger")
+                               )
+                    .subcommand(SubCommand::with_name("beancount")
+                                .about("Journal of invoices, payments and wages for beancount")
+                               )
+                   )
+
+        .subcommand(SubCommand::with_name("bank")
+                    .about("Match bank statements to open invoices")
+                    .setting(AppSettings::SubcommandRequiredElseHelp)
+                    .subcommand(SubCommand::with_name("import")
+                                .about("Read a CAMT.053 or csv statement and record the payments it contains")
+                                .arg(Arg::with_name("statement")
+                                     .help("Path to the statement")
+                                     .required(true)
+                                    )
+                                .arg(Arg::with_name("yes")
+                                     .help("Record all proposed payments without asking")
+                                     .long("yes")
+                                     .short("y")
+                                    )
+                               )
+                   )
+
+        .subcommand(SubCommand::with_name("import-bundle")
+                    .about("Verify a bundle and add its projects, existing ones are left alone")
+                    .arg(Arg::with_name("bundle")
+                         .help("Path to the bundle")
+                         .required(true)
+                        )
+                   )
+
+        .subcommand(SubCommand::with_name("fsck")
+                    .about("Check all projects for inconsistencies")
+                    .arg(Arg::with_name("fix")
+                         .help("Fix what can be fixed safely: wrong archive year, wrong prefix, empty folders")
+                         .long("fix")
+                        )
+                   )
+
+        .subcommand(SubCommand::with_name("init")
+                    .about("Creates the storage directories and installs the built-in templates")
+                    .arg(Arg::with_name("path")
+                         .help("Where to create the storage, defaults to the configured storage path")
+                        )
+                    .arg(Arg::with_name("git")
+                         .help("Also initialize a git repository")
+                         .long("git")
+                         .short("g")
+                        )
+                   )
+
+        .subcommand(SubCommand::with_name("templates")
+                    .about("Compares your templates with the built-in ones")
+                    .arg(Arg::with_name("diff")
+                         .help("Show what changed")
+                         .long("diff")
+                         .short("d")
+                        )
+                   )
+
 
         .subcommand(SubCommand::with_name("config")
                     .aliases(&["settings"])
@@ -618,7 +832,7 @@ pub fn build_cli() -> App<'static, 'static> {
 
 
         .subcommand(SubCommand::with_name("whoami")
-                    .about("Show your name from config")
+                    .about("Show your name and the active profile from config")
                    )
 
         .subcommand(SubCommand::with_name("dues")
@@ -637,6 +851,11 @@ pub fn build_cli() -> App<'static, 'static> {
                          .conflicts_with("invoices")
                         )
 
+                    .arg(Arg::with_name("no-cache")
+                         .help("Open every project instead of using the cached index")
+                         .long("no-cache")
+                        )
+
                    )
 
         //# GIT STUFF
@@ -723,6 +942,12 @@ pub fn build_cli() -> App<'static, 'static> {
         .subcommand(SubCommand::with_name("commit")
                     .aliases(&["cm"])
                     .about("Save changes locally")
+                    .arg(Arg::with_name("message")
+                         .help("Use this message instead of opening the editor")
+                         .short("m")
+                         .long("message")
+                         .takes_value(true)
+                        )
                    )
         .subcommand(SubCommand::with_name("remote")
                     .about("Show information about the remote")
@@ -736,13 +961,61 @@ pub fn build_cli() -> App<'static, 'static> {
         .subcommand(SubCommand::with_name("pop").about(""))
 
         .subcommand(SubCommand::with_name("log")
-                    .aliases(&["lg", "hist", "history"])
+                    .aliases(&["lg", "hist"])
                     .about("Show commit logs")
                    )
+
+        .subcommand(SubCommand::with_name("merge-driver")
+                    .about("Merges project files key by key, meant to be called by git")
+                    .arg(Arg::with_name("base")
+                         .help("Common ancestor, %O")
+                         .required_unless("install")
+                        )
+                    .arg(Arg::with_name("ours")
+                         .help("Our version, the result is written here, %A")
+                         .required_unless("install")
+                        )
+                    .arg(Arg::with_name("theirs")
+                         .help("Their version, %B")
+                         .required_unless("install")
+                        )
+                    .arg(Arg::with_name("install")
+                         .help("Register this merge driver in the storage repository")
+                         .long("install")
+                        )
+                   )
+
+        .subcommand(SubCommand::with_name("history")
+                    .about("Show what changed in a project, commit by commit, following archiving and renames")
+                    .arg(Arg::with_name("project")
+                         .help("Search term, archived projects as \"archive/2016/name\"")
+                         .required(true)
+                        )
+                   )
+}
+
+/// Activates the profile passed with `--profile`.
+///
+/// Has to happen before `CONFIG` is read for the first time.
+fn select_profile(matches: &ArgMatches) {
+    let profile = matches.value_of("profile")
+        .or_else(|| matches.subcommand().1.and_then(|m| m.value_of("profile")));
+    if let Some(profile) = profile {
+        env::set_var(PROFILE_VAR, profile);
+    }
+
+    if !asciii::CONFIG.profile_exists() {
+        super::fail(format!("There is no profile {:?} in {}, known profiles: {}",
+                            asciii::CONFIG.profile.as_ref().map_or("", |p| p.as_str()),
+                            asciii::CONFIG.path.display(),
+                            asciii::CONFIG.profiles().join(", ")));
+    }
 }
 
 /// Starting point for handling commandline matches
 pub fn match_matches(matches: &ArgMatches) {
+    select_profile(matches);
+
     match matches.subcommand() {
      ("list",      Some(sub_m)) => subcommands::list(sub_m),
      ("csv",       Some(sub_m)) => subcommands::csv(sub_m),
@@ -754,13 +1027,22 @@ pub fn match_matches(matches: &ArgMatches) {
      ("archive",   Some(sub_m)) => subcommands::archive(sub_m),
      ("unarchive", Some(sub_m)) => subcommands::unarchive(sub_m),
      ("config",    Some(sub_m)) => subcommands::config(sub_m),
-     ("whoami",    _          ) => subcommands::config_show("user/name"),
+     ("whoami",    _          ) => subcommands::whoami(),
 
      ("path",      Some(sub_m)) => subcommands::show_path(sub_m),
      ("open",      Some(sub_m)) => subcommands::open_path(sub_m),
 
      ("make",      Some(sub_m)) => subcommands::make(sub_m),
      ("delete",    Some(sub_m)) => subcommands::delete(sub_m),
+     ("trash",     Some(sub_m)) => subcommands::trash(sub_m),
+     ("export",    Some(sub_m)) => subcommands::export(sub_m),
+     ("bank",      Some(sub_m)) => subcommands::bank(sub_m),
+     ("undo",      _          ) => subcommands::undo(),
+     ("fsck",      Some(sub_m)) => subcommands::fsck(sub_m),
+     ("init",      Some(sub_m)) => subcommands::init(sub_m),
+     ("templates", Some(sub_m)) => subcommands::templates(sub_m),
+     ("export-bundle", Some(sub_m)) => subcommands::export_bundle(sub_m),
+     ("import-bundle", Some(sub_m)) => subcommands::import_bundle(sub_m),
      ("spec",      Some(sub_m)) => subcommands::spec(sub_m),
 
      ("doc",       _          ) => subcommands::doc(),
@@ -776,11 +1058,13 @@ pub fn match_matches(matches: &ArgMatches) {
      ("status",    _          ) => subcommands::git_status(),
      ("add",       Some(sub_m)) => subcommands::git_add(sub_m),
      //("unadd",     Some(sub_m)) => subcommands::git_unadd(sub_m),
-     ("commit",    _          ) => subcommands::git_commit(),
+     ("commit",    Some(sub_m)) => subcommands::git_commit(sub_m),
      ("push",      _          ) => subcommands::git_push(),
      ("stash",     _          ) => subcommands::git_stash(),
      ("pop",       _          ) => subcommands::git_stash_pop(),
      ("log",       _          ) => subcommands::git_log(),
+     ("history",   Some(sub_m)) => subcommands::history(sub_m),
+     ("merge-driver", Some(sub_m)) => subcommands::merge_driver(sub_m),
      _                          => ()
     }
 }
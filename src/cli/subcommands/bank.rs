@@ -0,0 +1,40 @@
+use std::path::Path;
+
+use clap::ArgMatches;
+
+use asciii::actions;
+use asciii::util;
+
+use ::cli::execute;
+
+/// Command BANK
+pub fn bank(matches: &ArgMatches) {
+    if let ("import", Some(sub_m)) = matches.subcommand() {
+        bank_import(sub_m)
+    }
+}
+
+/// Command BANK IMPORT
+fn bank_import(matches: &ArgMatches) {
+    // required by clap
+    let statement = Path::new(matches.value_of("statement").unwrap());
+    let matching = execute(|| actions::bank_matches(statement));
+
+    let mut confirmed = Vec::new();
+    for payment in matching.matches {
+        println!("{}\n  pays {} ({:.2}, matched by {})", payment.transaction, payment.project, payment.total, payment.evidence);
+        if matches.is_present("yes") || util::really("record this payment? [y/N]") {
+            confirmed.push(payment);
+        }
+    }
+
+    if !matching.unmatched.is_empty() {
+        println!("\nno open invoice found for:");
+        for transaction in &matching.unmatched {
+            println!("  {}", transaction);
+        }
+    }
+
+    execute(|| actions::record_payments(&confirmed));
+    println!("recorded {} payments", confirmed.len());
+}
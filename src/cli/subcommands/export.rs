@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use clap::ArgMatches;
+
+use asciii::actions;
+use asciii::export::journal::Syntax;
+use asciii::util::yaml::parse_dmy_date;
+
+use ::cli::execute;
+
+/// Command EXPORT
+pub fn export(matches: &ArgMatches) {
+    match matches.subcommand() {
+        ("datev", Some(sub_m)) => export_datev(sub_m),
+        ("ledger",    _)       => print!("{}", execute(|| actions::export_journal(Syntax::Ledger))),
+        ("beancount", _)       => print!("{}", execute(|| actions::export_journal(Syntax::Beancount))),
+        _                      => ()
+    }
+}
+
+/// Command EXPORT DATEV
+fn export_datev(matches: &ArgMatches) {
+    // validated by clap
+    let from = parse_dmy_date(matches.value_of("from").unwrap()).unwrap();
+    let to = parse_dmy_date(matches.value_of("to").unwrap()).unwrap();
+    let target = matches.value_of("output")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| PathBuf::from(format!("EXTF_Buchungsstapel_{}_{}.csv",
+                                                                 from.format("%Y%m%d"), to.format("%Y%m%d"))));
+
+    let count = execute(|| actions::export_datev(from, to, &target));
+    println!("wrote {} bookings to {}", count, target.display());
+}
@@ -0,0 +1,41 @@
+use clap::ArgMatches;
+
+use asciii::actions::setup_luigi_with_git;
+
+use ::cli::execute;
+
+/// Command FSCK
+pub fn fsck(matches: &ArgMatches) {
+    let luigi = execute(setup_luigi_with_git);
+    let issues = execute(|| luigi.check());
+
+    if issues.is_empty() {
+        println!("everything is fine");
+        return;
+    }
+
+    for issue in &issues {
+        println!("{}{}", issue, if issue.is_fixable() {" (fixable)"} else {""});
+    }
+
+    if matches.is_present("fix") {
+        // fixing one issue may move a folder that another issue refers to,
+        // so check again after every fix
+        let mut failed = Vec::new();
+        loop {
+            let next = execute(|| luigi.check())
+                .into_iter()
+                .find(|issue| issue.is_fixable() && !failed.contains(&issue.to_string()));
+            let issue = match next { Some(issue) => issue, None => break };
+            match luigi.fix(&issue) {
+                Ok(_) => println!("fixed: {}", issue),
+                Err(err) => {
+                    error!("could not fix {}: {}", issue, err);
+                    failed.push(issue.to_string());
+                }
+            }
+        }
+    } else if issues.iter().any(|issue| issue.is_fixable()) {
+        println!("run with --fix to repair what can be fixed safely");
+    }
+}
@@ -1,17 +1,69 @@
+use std::fs::File;
+use std::path::Path;
+use std::io::{Read, Write};
+
 use clap::ArgMatches;
 
+use asciii::CONFIG;
+use asciii::actions;
 use asciii::actions::setup_luigi_with_git;
+use asciii::project::Project;
+use asciii::storage::Storage;
+use asciii::storage::repo::{GitStatus, StatusEntry};
 use asciii::util;
 
-use ::cli::execute;
+use ::cli::{execute, fail};
 use super::matches_to_paths;
 
 /// Command LOG
 pub fn git_log() {
     let luigi = execute(setup_luigi_with_git);
     let repo = luigi.repository().unwrap();
-    if !repo.log().success() {
-        error!("git log did not exit successfully")
+    for entry in execute(|| repo.log()) {
+        println!("{}", entry);
+    }
+}
+
+fn status_label(status: &GitStatus) -> &'static str {
+    match *status {
+        GitStatus::IndexNew => "new file",
+        GitStatus::IndexDeleted | GitStatus::WorkingDeleted => "deleted",
+        GitStatus::IndexRenamed | GitStatus::WorkingRenamed => "renamed",
+        GitStatus::IndexTypechange | GitStatus::WorkingTypechange => "typechange",
+        GitStatus::Conflict => "both modified",
+        _ => "modified"
+    }
+}
+
+fn status_line(entry: &StatusEntry) -> String {
+    format!("\t{:<12}{}", format!("{}:", status_label(&entry.status)), entry.path.display())
+}
+
+/// `git status` without the hints.
+fn print_status(entries: &[StatusEntry], branch: Option<String>) {
+    println!("On branch {}", branch.unwrap_or_else(|| "master".into()));
+
+    let staged    = entries.iter().filter(|e| e.status.is_staged()).collect::<Vec<_>>();
+    let untracked = entries.iter().filter(|e| e.status.is_untracked()).collect::<Vec<_>>();
+    let unstaged  = entries.iter().filter(|e| !e.status.is_staged() && !e.status.is_untracked()).collect::<Vec<_>>();
+
+    if !staged.is_empty() {
+        println!("Changes to be committed:\n");
+        for entry in staged { println!("{}", status_line(entry)); }
+        println!("");
+    }
+    if !unstaged.is_empty() {
+        println!("Changes not staged for commit:\n");
+        for entry in unstaged { println!("{}", status_line(entry)); }
+        println!("");
+    }
+    if !untracked.is_empty() {
+        println!("Untracked files:\n");
+        for entry in untracked { println!("\t{}", entry.path.display()); }
+        println!("");
+    }
+    if entries.is_empty() {
+        println!("nothing to commit, working tree clean");
     }
 }
 
@@ -19,18 +71,57 @@ pub fn git_log() {
 pub fn git_status() {
     let luigi = execute(setup_luigi_with_git);
     let repo = luigi.repository().unwrap();
-    if !repo.status().success() {
-        error!("git status did not exit successfully")
+    let entries = execute(|| repo.status());
+    print_status(&entries, execute(|| repo.branch()));
+}
+
+/// Opens the editor on `.git/COMMIT_EDITMSG`, like `git commit` does.
+fn edit_commit_message(luigi: &Storage<Project>) -> String {
+    let repo = luigi.repository().unwrap();
+    let path = luigi.root_dir().join(".git").join("COMMIT_EDITMSG");
+
+    let mut template = String::from("\n# Please enter the commit message for your changes. Lines starting\n\
+                                     # with '#' will be ignored, and an empty message aborts the commit.\n");
+    let staged = execute(|| repo.status()).into_iter().filter(|e| e.status.is_staged()).collect::<Vec<_>>();
+    if !staged.is_empty() {
+        template.push_str("#\n# Changes to be committed:\n");
+        for entry in &staged {
+            template.push_str(&format!("#{}\n", status_line(entry)));
+        }
     }
+    execute(|| File::create(&path).and_then(|mut file| file.write_all(template.as_bytes())));
+
+    util::pass_to_command(&CONFIG.get_str("user/editor"), &[&path]);
+
+    let mut content = String::new();
+    execute(|| File::open(&path).and_then(|mut file| file.read_to_string(&mut content)));
+    content.lines()
+           .filter(|line| !line.starts_with('#'))
+           .collect::<Vec<_>>()
+           .join("\n")
+           .trim()
+           .to_owned()
 }
 
 /// Command COMMIT
-pub fn git_commit() {
+pub fn git_commit(matches: &ArgMatches) {
     let luigi = execute(setup_luigi_with_git);
     let repo = luigi.repository().unwrap();
-    if !repo.commit().success() {
-        error!("git commit did not exit successfully")
+
+    let message = match matches.value_of("message") {
+        Some(message) => message.to_owned(),
+        None => edit_commit_message(&luigi)
+    };
+    if message.is_empty() {
+        error!("Aborting commit due to empty commit message.");
+        return;
     }
+
+    let commit = match repo.commit(&message) {
+        Ok(commit) => commit,
+        Err(err) => fail(err)
+    };
+    println!("[{} {}] {}", execute(|| repo.branch()).unwrap_or_else(|| "master".into()), commit.short_id(), commit.summary());
 }
 
 /// Command REMOTE
@@ -74,14 +165,49 @@ pub fn git_remote() {
 
 }
 
+/// Command HISTORY
+pub fn history(matches: &ArgMatches) {
+    let project = matches.value_of("project").unwrap();
+    for entry in execute(|| actions::history(project)) {
+        println!("{} {} {}",
+                 entry.commit.short_id(),
+                 entry.commit.date.format("%Y-%m-%d"),
+                 entry.commit.summary());
+        if entry.changes.is_empty() {
+            println!("    no changes to the project itself");
+        }
+        for change in &entry.changes {
+            println!("    {}", change);
+        }
+    }
+}
+
+/// Command MERGE-DRIVER
+///
+/// Called by git, see `asciii merge-driver --install`.
+pub fn merge_driver(matches: &ArgMatches) {
+    if matches.is_present("install") {
+        execute(actions::install_merge_driver);
+        println!("project files are now merged by asciii");
+        return;
+    }
+
+    let path = |name| Path::new(matches.value_of(name).unwrap());
+    let problems = execute(|| actions::merge_project_files(path("base"), path("ours"), path("theirs")));
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("{}", problem);
+        }
+        fail(format!("{} conflicts, our version was kept there", problems.len()));
+    }
+}
+
 /// Command ADD
 pub fn git_add(matches: &ArgMatches) {
     let luigi = execute(setup_luigi_with_git);
     let paths = matches_to_paths(matches, &luigi);
     let repo = luigi.repository().unwrap();
-    if !repo.add(&paths).success() {
-        error!("git add did not exit successfully")
-    }
+    execute(|| repo.add(&paths));
 }
 
 
@@ -90,8 +216,8 @@ pub fn git_diff(matches: &ArgMatches) {
     let luigi = execute(setup_luigi_with_git);
     let paths = matches_to_paths(matches, &luigi);
     let repo = luigi.repository().unwrap();
-    if !repo.diff(&paths).success() {
-        error!("git diff did not exit successfully")
+    for line in execute(|| repo.diff(&paths)) {
+        print!("{}", line);
     }
 }
 
@@ -123,9 +249,8 @@ pub fn git_push() {
 pub fn git_stash() {
     let luigi = execute(setup_luigi_with_git);
     let repo = luigi.repository().unwrap();
-    if !repo.stash().success() {
-        error!("git stash did not exit successfully")
-    }
+    let stash = execute(|| repo.stash());
+    println!("Saved working directory and index state {}", stash);
 }
 
 /// Command CLEANUP
@@ -148,7 +273,7 @@ pub fn git_cleanup(matches: &ArgMatches) {
 pub fn git_stash_pop() {
     let luigi = execute(setup_luigi_with_git);
     let repo = luigi.repository().unwrap();
-    if !repo.stash_pop().success() {
-        error!("git stash pop did not exit successfully")
-    }
+    execute(|| repo.stash_pop());
+    let entries = execute(|| repo.status());
+    print_status(&entries, execute(|| repo.branch()));
 }
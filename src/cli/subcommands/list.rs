@@ -2,17 +2,16 @@ use clap::ArgMatches;
 use chrono::*;
 
 use asciii::CONFIG;
+use asciii::actions;
 use asciii::actions::{setup_luigi, setup_luigi_with_git};
 use asciii::storage::*;
 use asciii::print;
 use asciii::print::{ListConfig, ListMode};
-use asciii::project::Project;
 use asciii::project::spec::IsProject;
 use asciii::project::ComputedField;
 
 use ::cli::execute;
 
-use std::path::PathBuf;
 
 /// Command LIST
 pub fn list(matches: &ArgMatches) {
@@ -78,8 +77,16 @@ pub fn list(matches: &ArgMatches) {
             // or list normal
             else { StorageDir::Working };
 
+        let use_index = !matches.is_present("no-cache") && match list_config.mode {
+            ListMode::Verbose => false,
+            ListMode::Nothing => !list_config.show_errors,
+            _ => true
+        };
+
         if matches.is_present("broken"){
-            list_broken_projects(dir); // XXX Broken
+            list_broken_projects(dir);
+        } else if use_index {
+            list_index(dir, &list_config);
         } else {
             list_projects(dir, &list_config);
         }
@@ -137,16 +144,54 @@ fn list_projects(dir: StorageDir, list_config: &ListConfig) {
     }
 }
 
+/// Command LIST [--simple, --paths, --csv, --nothing]
+///
+/// Same as `list_projects()`, but only uses the fields stored in the `IndexCache`.
+fn list_index(dir: StorageDir, list_config: &ListConfig) {
+    let luigi = execute(setup_luigi);
+
+    let mut fields = print::INDEX_FIELDS.to_vec();
+    fields.push("Responsible");
+    if list_config.mode == ListMode::Csv {
+        fields.extend_from_slice(&actions::CSV_FIELDS);
+    }
+    if let Some(ref details) = list_config.details {
+        fields.extend_from_slice(details);
+    }
+    if let Some(ref filters) = list_config.filter_by {
+        fields.extend(filters.iter().map(|f| &f[..f.find(':').unwrap_or(0)]));
+    }
+
+    let mut entries = execute(|| luigi.open_index(dir, &fields, true));
+
+    if let Some(ref filters) = list_config.filter_by {
+        for filter in filters {
+            let (key, val) = filter.split_at(filter.find(':').unwrap_or(0));
+            entries.retain(|e| e.matches_filter(key, &val[1..]));
+        }
+    }
+
+    match list_config.sort_by {
+        "manager" => entries.sort_by(|ea,eb| ea.get("Responsible").cmp(&eb.get("Responsible"))),
+        "date"    => entries.sort_by(|ea,eb| ea.get("Date").cmp(&eb.get("Date"))),
+        "name"    => entries.sort_by(|ea,eb| ea.get("Name").cmp(&eb.get("Name"))),
+        _         => entries.sort_by(|ea,eb| ea.get("SortIndex").unwrap_or("zzzz").cmp(eb.get("SortIndex").unwrap_or("zzzz"))),
+    }
+
+    debug!("list_mode: {:?} (cached)", list_config.mode );
+    match list_config.mode{
+        ListMode::Csv     => print::print_index_csv(&entries),
+        ListMode::Paths   => print::print_projects(print::path_index_rows(&entries, list_config)),
+        ListMode::Nothing => print::print_projects(print::dynamic_index_rows(&entries, list_config)),
+        _                 => print::print_projects(print::simple_index_rows(&entries, list_config)),
+    }
+}
+
 /// Command LIST --broken
 fn list_broken_projects(dir: StorageDir) {
     let luigi = execute(setup_luigi);
-    let invalid_files = execute(|| luigi.list_project_files(dir));
-    let tups = invalid_files.iter()
-                            .filter_map(|dir| Project::open(dir).err().map(|e| (e, dir)))
-                            .collect::<Vec<(StorageError, &PathBuf)>>();
-
-    for (err, path) in tups {
-        println!("{}: {:?}", path.display(), err);
+    for (path, err) in execute(|| luigi.list_broken_projects(dir)) {
+        println!("{}: {}", path.display(), err);
     }
 }
 
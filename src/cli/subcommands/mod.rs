@@ -12,7 +12,8 @@ use asciii;
 use asciii::CONFIG;
 use asciii::config;
 use asciii::util;
-use asciii::BillType;
+use asciii::util::yaml;
+use asciii::{BillType, DocumentFormat};
 use asciii::actions;
 use asciii::storage::*;
 use asciii::project::Project;
@@ -32,6 +33,21 @@ pub use self::list::*;
 pub mod show;
 pub use self::show::*;
 
+pub mod trash;
+pub use self::trash::*;
+
+pub mod fsck;
+pub use self::fsck::*;
+
+pub mod templates;
+pub use self::templates::*;
+
+pub mod export;
+pub use self::export::*;
+
+pub mod bank;
+pub use self::bank::*;
+
 use super::{execute, fail};
 
 #[cfg(feature="shell")] use super::shell;
@@ -41,7 +57,6 @@ use super::{execute, fail};
 /// Create NEW Project
 // #[deprecated(note="move to asciii::actions")]
 pub fn new(matches: &ArgMatches) {
-    let project_name = matches.value_of("name").expect("You did not pass a \"Name\"!");
     let editor = CONFIG.get("user/editor").and_then(|e| e.as_str());
 
     let template_name = matches.value_of("template")
@@ -78,10 +93,56 @@ pub fn new(matches: &ArgMatches) {
         fill_data.insert("MANAGER", manager.to_owned());
     }
 
-    let project = execute(|| luigi.create_project(project_name, template_name, &fill_data));
-    let project_file = project.file();
+    if let Some(vcard) = matches.value_of("client-vcard") {
+        debug!("Filling in CLIENT-* from {}", vcard);
+        let contact = execute(|| actions::vcard_contact(Path::new(vcard)));
+        fill_data.extend(contact.fill_data());
+    }
+
+    let mut failed = false;
+    let projects = if let Some(ics) = matches.value_of("from-ics") {
+        let bookings = execute(|| actions::ics_bookings(Path::new(ics), matches.is_present("by-uid")));
+        let names = asciii::import::ics::project_names(&bookings);
+        let single = bookings.len() == 1;
+        bookings.iter().zip(names.iter()).filter_map(|(booking, name)| {
+            // the given name only makes sense for a single project
+            let project_name = if single { matches.value_of("name") } else { None }.unwrap_or(name);
+            let mut booking_data = booking.fill_data();
+            booking_data.insert("PROJECT-NAME", yaml::quote(project_name));
+            // explicit arguments win
+            booking_data.extend(fill_data.iter().map(|(k, v)| (*k, v.to_owned())));
+            match luigi.create_project(project_name, template_name, &booking_data) {
+                Ok(project) => Some(project),
+                Err(err) => {
+                    // keep going, what was created is still committed below
+                    error!("could not create {:?}: {}", project_name, err);
+                    failed = true;
+                    None
+                }
+            }
+        }).collect::<Vec<_>>()
+    } else {
+        let project_name = matches.value_of("name").expect("You did not pass a \"Name\"!");
+        if let Some(source) = matches.value_of("from") {
+            let source = execute(|| actions::find_unique_project(&luigi, source));
+            vec![execute(|| luigi.create_project_from(project_name, &source, &fill_data))]
+        } else {
+            vec![execute(|| luigi.create_project(project_name, template_name, &fill_data))]
+        }
+    };
+
     if edit {
-        util::pass_to_command(&editor, &[project_file]);
+        let project_files = projects.iter().map(|p| p.file()).collect::<Vec<_>>();
+        util::pass_to_command(&editor, &project_files);
+    }
+
+    let mut commit = AutoCommit::new("new");
+    for project in &projects {
+        commit.touch(project, &[project.dir()]);
+    }
+    execute(|| actions::auto_commit(&commit));
+    if failed {
+        fail("not every event became a project");
     }
 }
 
@@ -166,7 +227,7 @@ pub fn csv(matches: &ArgMatches) {
                       .unwrap_or(Local::now().year());
 
     debug!("asciii csv --year {}", year);
-    let csv = execute(|| actions::csv(year));
+    let csv = execute(|| actions::csv(year, !matches.is_present("no-cache")));
     println!("{}", csv);
 }
 
@@ -231,6 +292,7 @@ pub fn set(m: &ArgMatches) {
                             .collect::<String>();
     let value = m.value_of("field value").unwrap();
     let (search_terms, dir) = matches_to_search(m);
+    let mut commit = AutoCommit::new(&format!("set {}", field));
 
     execute(|| {
         actions::with_projects(dir, &search_terms, |project| {
@@ -241,12 +303,15 @@ pub fn set(m: &ArgMatches) {
             if util::really(&format!("do you want to set the field {} in {:?} [y|N]",
                                      field,
                                      project.short_desc())) {
-                project.replace_field(&field, &value).map_err(|e| e.into())
+                project.replace_field(&field, &value)?;
+                commit.touch(project, &[project.file()]);
+                Ok(())
             } else {
                 Err("Don't want to".into())
             }
         })
-    })
+    });
+    execute(|| actions::auto_commit(&commit));
 }
 
 
@@ -262,7 +327,7 @@ fn infer_bill_type(m: &ArgMatches) -> Option<BillType> {
 
 /// Command CALENDAR
 pub fn calendar(matches: &ArgMatches) {
-    let calendar = execute(||actions::calendar(matches_to_dir(matches)));
+    let calendar = execute(||actions::calendar(matches_to_dir(matches), !matches.is_present("no-cache")));
     println!("{}", calendar);
 }
 
@@ -280,6 +345,7 @@ pub fn spec(_: &ArgMatches) {
 pub fn make(m: &ArgMatches) {
     let template_name = m.value_of("template").unwrap_or("document");
     let bill_type = infer_bill_type(m);
+    let format = m.value_of("format").unwrap_or("latex").parse::<DocumentFormat>().unwrap();
     let (search_terms, dir) = matches_to_search(m);
 
     debug!("make {t}({s}/{d:?}, invoice={i:?})",
@@ -288,13 +354,20 @@ pub fn make(m: &ArgMatches) {
            t = template_name,
            i = bill_type);
 
+    if m.is_present("watch") {
+        execute(|| actions::watch_project_doc(dir, search_terms[0], template_name, format, &bill_type, m.is_present("dry-run")));
+        return;
+    }
+
     execute(|| {
         actions::projects_to_doc(dir,
                                  search_terms[0],
                                  template_name,
+                                 format,
                                  &bill_type,
                                  m.is_present("dry-run"),
-                                 m.is_present("force"))
+                                 m.is_present("force"),
+                                 m.value_of("reissue"))
     });
 }
 
@@ -337,6 +410,34 @@ pub fn archive(matches: &ArgMatches) {
     }
 }
 
+/// Command EXPORT-BUNDLE
+pub fn export_bundle(matches: &ArgMatches) {
+    let year = matches.value_of("year")
+                      .and_then(|y| y.parse::<i32>().ok())
+                      .unwrap_or(Local::today().year());
+    let target = matches.value_of("output")
+                        .map(PathBuf::from)
+                        .unwrap_or_else(|| PathBuf::from(format!("asciii-{}.tar", year)));
+
+    let bundled = execute(|| actions::export_bundle(year, &target));
+    for file in &bundled {
+        debug!("bundled {}", file.display());
+    }
+    println!("wrote {} files to {}", bundled.len(), target.display());
+}
+
+/// Command IMPORT-BUNDLE
+pub fn import_bundle(matches: &ArgMatches) {
+    let bundle = Path::new(matches.value_of("bundle").unwrap());
+    let report = execute(|| actions::import_bundle(bundle));
+    for unit in &report.imported {
+        println!("imported {}", unit.display());
+    }
+    for unit in &report.skipped {
+        println!("skipped {}, it already exists", unit.display());
+    }
+}
+
 pub fn unarchive(matches: &ArgMatches) {
     let year = matches.value_of("year").unwrap();
     let year = year.parse::<i32>()
@@ -398,6 +499,19 @@ pub fn config_show(path: &str) {
                    .unwrap_or_else(|| format!("{} not set", path)));
 }
 
+/// Command WHOAMI
+pub fn whoami() {
+    config_show("user/name");
+    show_profile();
+}
+
+/// Prints the active profile, if there is one
+fn show_profile() {
+    if let Some(ref profile) = CONFIG.profile {
+        println!("profile: {:?}", profile);
+    }
+}
+
 /// Command CONFIG --edit
 fn config_edit(editor: &Option<&str>) {
     let local = config::ConfigReader::path_home();
@@ -426,10 +540,11 @@ pub fn version() {
 
 /// Command DUES
 pub fn dues(matches: &ArgMatches) {
+    let use_cache = !matches.is_present("no-cache");
     let dues = if matches.is_present("wages") {
-        actions::open_wages()
+        actions::open_wages(use_cache)
     } else {
-        actions::open_payments()
+        actions::open_payments(use_cache)
     };
     if let Ok(dues) = dues {
         println!("{}", dues.postfix());
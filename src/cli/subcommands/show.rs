@@ -4,6 +4,7 @@ use rustc_serialize::json::ToJson;
 
 use clap::ArgMatches;
 
+use asciii::CONFIG;
 use asciii::BillType;
 use asciii::actions;
 use asciii::print;
@@ -15,7 +16,7 @@ use asciii::project::spec::events::HasEvents;
 
 use super::matches_to_search;
 
-use ::cli::execute;
+use ::cli::{execute, fail};
 use super::path;
 
 use std::fs;
@@ -46,8 +47,13 @@ pub fn show(m: &ArgMatches) {
     } else if m.is_present("dump"){ dump_yaml(dir, search_terms.as_slice())
     } else if m.is_present("json"){ show_json(dir, search_terms.as_slice())
     } else if m.is_present("ical"){ show_ical(dir, search_terms.as_slice())
+    } else if m.is_present("vcard"){ show_vcard(dir, search_terms.as_slice())
     } else if m.is_present("csv"){  show_csv( dir, search_terms.as_slice());
-    } else if m.is_present("template"){ show_template(search_terms[0]);
+    } else if m.is_present("template"){
+        match search_terms.first() {
+            Some(name) => show_template(name),
+            None => fail("--template needs the name of a template")
+        }
     } else { actions::simple_with_projects(dir,
                                            search_terms.as_slice(),
                                            |p|print::show_details(p,&bill_type))
@@ -79,6 +85,10 @@ fn show_ical(dir: StorageDir, search_terms: &[&str]) {
     actions::simple_with_projects(dir, &search_terms, |p| p.to_ical().print().unwrap());
 }
 
+fn show_vcard(dir: StorageDir, search_terms: &[&str]) {
+    print!("{}", execute(|| actions::vcards(dir, search_terms)));
+}
+
 fn show_detail(dir: StorageDir, search_terms: &[&str], detail: &str) {
     actions::simple_with_projects(dir, &search_terms, |p| {
         println!("{}",
@@ -98,6 +108,10 @@ fn show_json(_: StorageDir, _: &[&str]) {
 }
 
 pub fn show_path(matches: &ArgMatches) {
+    // stdout stays usable in `cd $(asciii path)`
+    if let Some(ref profile) = CONFIG.profile {
+        info!("profile: {}", profile);
+    }
     path(matches, |path| println!("{}", path.display()))
 }
 
@@ -0,0 +1,88 @@
+use std::path::Path;
+
+use clap::ArgMatches;
+use diff;
+
+use asciii::actions;
+use asciii::storage::TemplateComparison;
+
+use ::cli::execute;
+
+/// Lines of context around changes in `templates --diff`
+const CONTEXT: usize = 3;
+
+/// Command INIT
+pub fn init(matches: &ArgMatches) {
+    let path = matches.value_of("path").map(Path::new);
+    let (root, installed) = execute(|| actions::init_storage(path, matches.is_present("git")));
+
+    println!("storage ready in {}", root.display());
+    for template in &installed {
+        println!("installed {}", template.display());
+    }
+    if path.is_some() {
+        println!("set `path` and `dirs/storage` in your config (asciii config --edit) to use it");
+    }
+}
+
+/// Command TEMPLATES
+pub fn templates(matches: &ArgMatches) {
+    let comparisons = execute(actions::compare_templates);
+    let show_diff = matches.is_present("diff");
+
+    for comparison in &comparisons {
+        let state = if comparison.is_missing() {
+            "missing, run `asciii init` to install it"
+        } else if comparison.is_modified() {
+            "differs from the built-in version"
+        } else {
+            "same as built-in"
+        };
+        println!("{}: {}", comparison.path.display(), state);
+
+        if show_diff && comparison.is_modified() {
+            print_diff(comparison);
+        }
+    }
+}
+
+fn is_change(line: &diff::Result<&str>) -> bool {
+    match *line {
+        diff::Result::Both(..) => false,
+        _ => true
+    }
+}
+
+/// Prints the changes from the built-in template to the local one, like `diff -u`.
+fn print_diff(comparison: &TemplateComparison) {
+    let local = comparison.local.as_ref().map_or("", |l| l.as_str());
+    let lines = diff::lines(comparison.builtin.content, local);
+
+    println!("--- built-in/{}", comparison.builtin.file_name);
+    println!("+++ {}", comparison.path.display());
+
+    let mut last_printed = None;
+    // line numbers in the built-in and the local template
+    let (mut left, mut right) = (1, 1);
+    for (index, line) in lines.iter().enumerate() {
+        let start = index.saturating_sub(CONTEXT);
+        let end = (index + CONTEXT + 1).min(lines.len());
+        if lines[start..end].iter().any(|l| is_change(l)) {
+            if last_printed.map_or(true, |last| last + 1 < index) {
+                println!("@@ -{} +{} @@", left, right);
+            }
+            match *line {
+                diff::Result::Left(l)    => println!("-{}", l),
+                diff::Result::Right(r)   => println!("+{}", r),
+                diff::Result::Both(l, _) => println!(" {}", l)
+            }
+            last_printed = Some(index);
+        }
+
+        match *line {
+            diff::Result::Left(_)    => left += 1,
+            diff::Result::Right(_)   => right += 1,
+            diff::Result::Both(..)   => { left += 1; right += 1 }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use clap::ArgMatches;
+
+use asciii::util;
+use asciii::actions::setup_luigi_with_git;
+use asciii::storage::TrashItem;
+
+use ::cli::{execute, fail};
+
+/// Command TRASH
+pub fn trash(matches: &ArgMatches) {
+    match matches.subcommand() {
+        ("list",    _          ) => trash_list(),
+        ("restore", Some(sub_m)) => trash_restore(sub_m.value_of("item").unwrap()),
+        ("empty",   Some(sub_m)) => trash_empty(sub_m.is_present("yes")),
+        _                        => ()
+    }
+}
+
+/// Command TRASH LIST
+fn trash_list() {
+    let luigi = execute(setup_luigi_with_git);
+    for item in execute(|| luigi.list_trash()) {
+        println!("{id}  {deleted}  {origin}",
+                 id = item.id,
+                 deleted = item.deleted,
+                 origin = item.origin.strip_prefix(luigi.root_dir()).unwrap_or(&item.origin).display());
+    }
+}
+
+/// Command TRASH RESTORE
+///
+/// Accepts either the exact id or a part of the project folder name, as long as it is unique.
+fn trash_restore(term: &str) {
+    let luigi = execute(setup_luigi_with_git);
+    let items = execute(|| luigi.list_trash());
+
+    let matching = match items.iter().find(|item| item.id == term) {
+        Some(item) => vec![item],
+        None => items.iter()
+                     .filter(|item| item.origin.file_name()
+                                        .map_or(false, |name| name.to_string_lossy().contains(term)))
+                     .collect::<Vec<&TrashItem>>()
+    };
+
+    match matching.len() {
+        0 => fail(format!("nothing in the trash matches {:?}", term)),
+        1 => {
+            let restored = execute(|| luigi.restore_from_trash(matching[0]));
+            println!("restored {}", restored.display());
+        },
+        _ => {
+            for item in matching {
+                println!("{}", item.id);
+            }
+            fail(format!("{:?} is ambiguous, please use the id", term))
+        }
+    }
+}
+
+/// Command TRASH EMPTY
+fn trash_empty(yes: bool) {
+    let luigi = execute(setup_luigi_with_git);
+    let count = execute(|| luigi.list_trash()).len();
+    if count == 0 {
+        println!("the trash is already empty");
+        return;
+    }
+    if yes || util::really(&format!("you want me to remove {} deleted projects for good [y/N]", count)) {
+        for item in execute(|| luigi.empty_trash()) {
+            println!("removed {}", item.id);
+        }
+    }
+}
+
+/// Command UNDO
+pub fn undo() {
+    let luigi = execute(setup_luigi_with_git);
+    for step in execute(|| luigi.undo()) {
+        println!("undid {}: {} -> {}",
+                 step.operation,
+                 step.to.strip_prefix(luigi.root_dir()).unwrap_or(&step.to).display(),
+                 step.from.strip_prefix(luigi.root_dir()).unwrap_or(&step.from).display());
+    }
+}
@@ -2,6 +2,21 @@
 //!
 //! Looks for `DEFAULT_LOCATION` and patches unset fields from `DEFAULT_CONFIG`
 //!
+//! Several storages can be kept apart with named profiles,
+//! every field under `profiles/<name>/` overrides the field of the same name
+//! while that profile is active:
+//!
+//! ```yaml
+//! path: ~/asciii_projects
+//! profiles:
+//!   club:
+//!     path: ~/club_projects
+//!     user:
+//!       name: Club Treasurer
+//! ```
+//!
+//! The profile is chosen by `PROFILE_VAR` (`asciii --profile club` sets it too).
+//!
 
 #![warn(missing_docs,
         missing_copy_implementations,
@@ -13,18 +28,23 @@
 
 
 use std::path::{Path,PathBuf};
-use std::env::{home_dir,current_dir};
+use std::env::{self,home_dir,current_dir};
 use util::yaml;
 use util::yaml::{Yaml, YamlError};
 
 /// Name of the configfile
 pub const DEFAULT_LOCATION: &'static str = ".asciii.yml";
 
+/// Environment variable that selects the active profile
+pub const PROFILE_VAR: &'static str = "ASCIII_PROFILE";
+
 /// Looks for a configuration yaml in your `HOME_DIR`
 #[derive(Debug)]
 pub struct ConfigReader{
     /// Path of config file
     pub path: PathBuf,
+    /// Name of the active profile, if any
+    pub profile: Option<String>,
     defaults: Yaml,
     custom: Yaml,
     local: Yaml
@@ -45,6 +65,7 @@ impl ConfigReader{
 
         let config = Ok(ConfigReader{
             path: home_path.to_owned(),
+            profile: env::var(PROFILE_VAR).ok().and_then(|p| if p.is_empty() {None} else {Some(p)}),
             defaults: yaml::parse(&DEFAULT_CONFIG)?,
             custom: yaml::open(&home_path).unwrap_or(Yaml::Null),
             local:  yaml::open(&local_path).unwrap_or(Yaml::Null)
@@ -63,13 +84,47 @@ impl ConfigReader{
         config
     }
 
+    /// Names of all profiles defined in the local and user config.
+    pub fn profiles(&self) -> Vec<String> {
+        let mut profiles = [&self.local, &self.custom].iter()
+            .filter_map(|config| yaml::get_hash(config, "profiles"))
+            .flat_map(|profiles| profiles.keys())
+            .filter_map(|name| name.as_str().map(ToOwned::to_owned))
+            .collect::<Vec<String>>();
+        profiles.sort();
+        profiles.dedup();
+        profiles
+    }
+
+    /// Whether the active profile, if any, is defined.
+    pub fn profile_exists(&self) -> bool {
+        match self.profile {
+            Some(ref profile) => self.profiles().contains(profile),
+            None => true
+        }
+    }
+
+    /// Where `key` is found inside the active profile.
+    fn profile_path(&self, key:&str) -> Option<String> {
+        self.profile.as_ref().map(|profile| format!("profiles/{}/{}", profile, key))
+    }
+
+    /// Looks in the active profile first, then in local, user and default config.
+    fn lookup<'a, T, F>(&'a self, key:&str, get:F) -> Option<T>
+        where F: Fn(&'a Yaml, &str) -> Option<T>
+    {
+        let profiled = self.profile_path(key);
+        profiled.as_ref().and_then(|p| get(&self.local, p).or_else(||get(&self.custom, p)))
+            .or_else(||get(&self.local, key))
+            .or_else(||get(&self.custom, key))
+            .or_else(||get(&self.defaults, key))
+    }
+
     /// Returns whatever it finds in that position
     ///
     /// Supports simple path syntax: `top/middle/child/node`
     pub fn get(&self, path:&str) -> Option<&Yaml>{
-        yaml::get(&self.local, path)
-            .or_else(||yaml::get(&self.custom, path))
-            .or_else(||yaml::get(&self.defaults, path))
+        self.lookup(path, yaml::get)
     }
 
     /// Returns the first character.
@@ -83,9 +138,7 @@ impl ConfigReader{
 
     /// Returns the string in the position or an empty string
     pub fn get_str(&self, key:&str) -> Option<&str> {
-        yaml::get_str(&self.local, key)
-            .or_else(||yaml::get_str(&self.custom, key))
-            .or_else(||yaml::get_str(&self.defaults, key))
+        self.lookup(key, yaml::get_str)
             //.expect(&format!("Config file {} in field {} does not contain a string value", DEFAULT_LOCATION, key))
     }
 
@@ -105,17 +158,13 @@ impl ConfigReader{
     /// This panics if nothing is found.
     /// You should have a default config for everything that you use.
     pub fn get_to_string(&self, key:&str) -> Option<String>{
-        yaml::get_to_string(&self.local, key)
-            .or_else(||yaml::get_to_string(&self.custom, key))
-            .or_else(||yaml::get_to_string(&self.defaults, key))
+        self.lookup(key, yaml::get_to_string)
             //.expect(&format!("Config file {} in field {} does not contain a value", DEFAULT_LOCATION, key))
     }
 
     /// Tries to get the config field as float
     pub fn get_f64(&self, key:&str) -> Option<f64>{
-        yaml::get_f64(&self.local, key)
-            .or_else(||yaml::get_f64(&self.custom, key))
-            .or_else(||yaml::get_f64(&self.defaults, key))
+        self.lookup(key, yaml::get_f64)
             //.expect(&format!("Config file {} in field {} does not contain a value", DEFAULT_LOCATION, key))
     }
 
@@ -132,6 +181,31 @@ impl ConfigReader{
 
 }
 
+/// Profiles are looked up before the plain fields, and only while they are active.
+#[test]
+fn profile_overrides(){
+    let custom = yaml::parse("path: ~/projects\nprofiles:\n  club:\n    path: ~/club\n").unwrap();
+    let mut config = ConfigReader{
+        path: PathBuf::new(),
+        profile: None,
+        defaults: yaml::parse(&DEFAULT_CONFIG).unwrap(),
+        custom: custom,
+        local: Yaml::Null
+    };
+
+    assert_eq!(config.get_str("path"), Some("~/projects"));
+    assert_eq!(config.profiles(), vec!["club".to_owned()]);
+
+    config.profile = Some("club".into());
+    assert!(config.profile_exists());
+    assert_eq!(config.get_str("path"), Some("~/club"));
+    assert_eq!(config.get_str("dirs/storage"), Some("projects"));
+
+    config.profile = Some("nope".into());
+    assert!(!config.profile_exists());
+    assert_eq!(config.get_str("path"), Some("~/projects"));
+}
+
 /// Default configuration that will be used if a value is not set in yaml file at `DEFAULT_LOCATION`
 pub const DEFAULT_CONFIG: &'static str = include_str!("./default_config.yml");
 
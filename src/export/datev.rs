@@ -0,0 +1,276 @@
+//! Booking records in the DATEV format (`EXTF` Buchungsstapel) for the tax accountant.
+//!
+//! Every invoice becomes one booking per tax rate on the revenue account configured for that rate,
+//! every payment one booking on the bank account, both against the client's account.
+//! Accounts come from the `datev` section of the config, a project may name its own
+//! debitor account in `client/account`.
+//!
+//! DATEV expects Windows-1252, `to_csv()` encodes accordingly.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::*;
+
+use util::{yaml, cp1252};
+use project::Project;
+use project::spec::{IsProject, Invoicable, Redeemable};
+use project::error::Result;
+
+/// Accounts that bookings are made on.
+#[derive(Debug, Clone)]
+pub struct Accounts {
+    pub bank: u32,
+    /// Collective account for clients without `client/account`.
+    pub debitor: u32,
+    /// Revenue account by tax rate in percent, e.g. `"19"` → `8400`.
+    pub revenue: BTreeMap<String, u32>
+}
+
+impl Accounts {
+    pub fn from_config() -> Result<Accounts> {
+        let account = |key:&str| ::CONFIG.get_to_string(&format!("datev/{}", key))
+            .and_then(|a| a.parse().ok())
+            .ok_or_else(|| format!("Faulty config: datev/{} does not contain an account number", key));
+
+        let revenue = ::CONFIG.get("datev/revenue")
+            .and_then(|r| r.as_hash())
+            .ok_or("Faulty config: datev/revenue does not contain accounts by tax rate")?
+            .iter()
+            .filter_map(|(rate, account)| {
+                let rate = rate.as_str().map(ToOwned::to_owned).or_else(|| rate.as_i64().map(|r| r.to_string()));
+                let account = account.as_i64().map(|a| a as u32);
+                rate.and_then(|r| account.map(|a| (r, a)))
+            })
+            .collect();
+
+        Ok(Accounts {
+            bank: account("bank")?,
+            debitor: account("debitor")?,
+            revenue: revenue
+        })
+    }
+
+    /// Revenue account for a tax rate like `0.19`.
+    fn revenue(&self, tax:f64) -> Result<u32> {
+        let percent = format!("{}", (tax * 100.0).round() as i64);
+        self.revenue.get(&percent)
+            .cloned()
+            .ok_or_else(|| format!("no revenue account for {}% tax, add it to datev/revenue in your config", percent).into())
+    }
+}
+
+/// One line of the booking batch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Booking {
+    /// Always positive, see `debit`
+    pub amount: f64,
+    /// Whether `account` is debited (`S`) or credited (`H`)
+    pub debit: bool,
+    pub account: u32,
+    pub counter_account: u32,
+    pub date: Date<UTC>,
+    /// `InvoiceNumberLong`
+    pub document: String,
+    pub text: String
+}
+
+/// Bookings of the invoice and the payment of a project that happened between `from` and `to` (inclusive).
+pub fn bookings(project:&Project, accounts:&Accounts, from:Date<UTC>, to:Date<UTC>) -> Result<Vec<Booking>> {
+    let mut bookings = Vec::new();
+    if project.canceled() {
+        return Ok(bookings);
+    }
+    let (invoice_date, document) = match (project.invoice().date(), project.invoice().number_long_str()) {
+        (Some(date), Some(number)) => (date, number),
+        _ => return Ok(bookings)
+    };
+
+    let client_account = yaml::get_int(project.yaml(), "client/account").map_or(accounts.debitor, |a| a as u32);
+    let text = IsProject::name(project).unwrap_or("").chars().take(60).collect::<String>();
+    let (_, invoice) = project.bills()?;
+    let in_range = |date:Date<UTC>| from <= date && date <= to;
+
+    if in_range(invoice_date) {
+        for (tax, list) in invoice.iter() {
+            let amount = list.gross_sum().as_float() + list.tax_sum().as_float();
+            if amount == 0.0 { continue }
+            bookings.push(Booking {
+                amount: amount,
+                debit: false,
+                account: accounts.revenue(tax.into_inner())?,
+                counter_account: client_account,
+                date: invoice_date,
+                document: document.to_owned(),
+                text: text.to_owned()
+            });
+        }
+    }
+
+    if let Some(payed_date) = project.payed_date() {
+        if in_range(payed_date) {
+            bookings.push(Booking {
+                amount: invoice.net_total().as_float(),
+                debit: true,
+                account: accounts.bank,
+                counter_account: client_account,
+                date: payed_date,
+                document: document.to_owned(),
+                text: text.to_owned()
+            });
+        }
+    }
+
+    Ok(bookings)
+}
+
+/// A batch covers one fiscal year, which is taken to be the calendar year.
+pub fn check_range(from:Date<UTC>, to:Date<UTC>) -> Result<()> {
+    if from > to {
+        return Err(format!("{} is after {}", from.format("%d.%m.%Y"), to.format("%d.%m.%Y")).into());
+    }
+    if from.year() != to.year() {
+        return Err(format!("{} and {} are in different fiscal years, export each year on its own",
+                           from.format("%d.%m.%Y"), to.format("%d.%m.%Y")).into());
+    }
+    Ok(())
+}
+
+/// What goes into the first line of the file.
+#[derive(Debug, Clone)]
+pub struct Header {
+    /// Beraternummer
+    pub consultant: u32,
+    /// Mandantennummer
+    pub client: u32,
+    /// Sachkontenlänge
+    pub account_length: u32,
+    pub from: Date<UTC>,
+    pub to: Date<UTC>,
+    pub created: DateTime<UTC>
+}
+
+impl Header {
+    pub fn from_config(from:Date<UTC>, to:Date<UTC>) -> Header {
+        let number = |key:&str| ::CONFIG.get_to_string(&format!("datev/{}", key)).and_then(|n| n.parse().ok());
+        Header {
+            consultant: number("consultant").unwrap_or(0),
+            client: number("client").unwrap_or(0),
+            account_length: number("account_length").unwrap_or(4),
+            from: from,
+            to: to,
+            created: UTC::now()
+        }
+    }
+}
+
+const COLUMNS: [&'static str; 14] = [
+    "Umsatz (ohne Soll/Haben-Kz)", "Soll/Haben-Kennzeichen", "WKZ Umsatz", "Kurs", "Basis-Umsatz", "WKZ Basis-Umsatz",
+    "Konto", "Gegenkonto (ohne BU-Schlüssel)", "BU-Schlüssel", "Belegdatum", "Belegfeld 1", "Belegfeld 2", "Skonto", "Buchungstext"
+];
+
+/// Quotes a text field, DATEV doubles quotes inside.
+fn quote(text:&str) -> String {
+    format!("\"{}\"", text.replace('"', "\"\""))
+}
+
+/// Renders the booking batch, already encoded in Windows-1252.
+pub fn to_csv(header:&Header, bookings:&[Booking]) -> Vec<u8> {
+    let mut csv = String::new();
+    let fiscal_year_start = UTC.ymd(header.from.year(), 1, 1);
+
+    // format version 700, category 21 (Buchungsstapel) version 13
+    let _ = write!(csv, "\"EXTF\";700;21;\"Buchungsstapel\";13;{};;\"\";\"\";\"\";{};{};{};{};{};{};{};\"\";1;0;0;\"EUR\"\r\n",
+                   header.created.format("%Y%m%d%H%M%S000"),
+                   header.consultant,
+                   header.client,
+                   fiscal_year_start.format("%Y%m%d"),
+                   header.account_length,
+                   header.from.format("%Y%m%d"),
+                   header.to.format("%Y%m%d"),
+                   quote(&format!("asciii {} - {}", header.from.format("%d.%m.%Y"), header.to.format("%d.%m.%Y"))));
+    csv.push_str(&COLUMNS.join(";"));
+    csv.push_str("\r\n");
+
+    for booking in bookings {
+        let _ = write!(csv, "{};{};\"EUR\";;;;{};{};;{};{};;;{}\r\n",
+                       format!("{:.2}", booking.amount).replace('.', ","),
+                       quote(if booking.debit { "S" } else { "H" }),
+                       booking.account,
+                       booking.counter_account,
+                       booking.date.format("%d%m"),
+                       quote(&booking.document),
+                       quote(&booking.text));
+    }
+
+    cp1252::encode(&csv)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use chrono::*;
+
+    use super::*;
+    use project::Project;
+
+    fn accounts() -> Accounts {
+        Accounts {
+            bank: 1200,
+            debitor: 10000,
+            revenue: btreemap!{ String::from("19") => 8400, String::from("7") => 8300 }
+        }
+    }
+
+    #[test]
+    fn invoice_bookings(){
+        let project = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+        let bookings = bookings(&project, &accounts(), UTC.ymd(2016, 10, 1), UTC.ymd(2016, 12, 31)).unwrap();
+
+        // not payed yet, one booking per tax rate
+        assert_eq!(bookings.len(), 2);
+        assert!(bookings.iter().all(|b| !b.debit && b.counter_account == 10000 && b.document == "R2016-042"));
+        assert!(bookings.iter().any(|b| b.account == 8400 && (b.amount - 23.80).abs() < 0.001));
+        assert!(bookings.iter().any(|b| b.account == 8300 && (b.amount - 21.40).abs() < 0.001));
+
+        let outside = super::bookings(&project, &accounts(), UTC.ymd(2017, 1, 1), UTC.ymd(2017, 3, 31)).unwrap();
+        assert!(outside.is_empty());
+    }
+
+    #[test]
+    fn payment_booking(){
+        // invoiced 06.12.2014, payed 08.12.2014
+        let project = Project::open_file(Path::new("./tests/current.yml")).unwrap();
+        let bookings = bookings(&project, &accounts(), UTC.ymd(2014, 12, 7), UTC.ymd(2014, 12, 31)).unwrap();
+        assert_eq!(bookings.len(), 1);
+        assert!(bookings[0].debit);
+        assert_eq!(bookings[0].account, 1200);
+        assert_eq!(bookings[0].date, UTC.ymd(2014, 12, 8));
+    }
+
+    #[test]
+    fn range(){
+        assert!(check_range(UTC.ymd(2016, 10, 1), UTC.ymd(2016, 12, 31)).is_ok());
+        assert!(check_range(UTC.ymd(2016, 12, 31), UTC.ymd(2016, 10, 1)).is_err());
+        assert!(check_range(UTC.ymd(2016, 10, 1), UTC.ymd(2017, 3, 31)).is_err());
+    }
+
+    #[test]
+    fn csv(){
+        let header = Header {
+            consultant: 1001, client: 1, account_length: 4,
+            from: UTC.ymd(2016, 10, 1), to: UTC.ymd(2016, 12, 31),
+            created: UTC.ymd(2017, 1, 2).and_hms(10, 0, 0)
+        };
+        let booking = Booking {
+            amount: 1234.5, debit: false, account: 8400, counter_account: 10000,
+            date: UTC.ymd(2016, 12, 6), document: String::from("R2016-042"), text: String::from("Kaffee für \"alle\"")
+        };
+        let csv = to_csv(&header, &[booking]);
+        let lines = csv.split(|b| *b == b'\n').collect::<Vec<_>>();
+
+        assert_eq!(lines[0], &b"\"EXTF\";700;21;\"Buchungsstapel\";13;20170102100000000;;\"\";\"\";\"\";1001;1;20160101;4;20161001;20161231;\"asciii 01.10.2016 - 31.12.2016\";\"\";1;0;0;\"EUR\"\r"[..]);
+        assert_eq!(lines[2], &b"1234,50;\"H\";\"EUR\";;;;8400;10000;;0612;\"R2016-042\";;;\"Kaffee f\xfcr \"\"alle\"\"\"\r"[..]);
+    }
+}
@@ -0,0 +1,262 @@
+//! Plain-text accounting journals for ledger, hledger and beancount.
+//!
+//! Every invoice becomes a transaction from revenue (split per tax rate) and tax to receivables,
+//! every recorded payment one from receivables to the bank and every wage payout one from cash to wages.
+//! Account names come from the `journal` section of the config.
+//! Transactions are sorted by date, kind and document, so the output of two runs can be diffed.
+
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use chrono::*;
+
+use project::Project;
+use project::spec::{IsProject, IsClient, Invoicable, Redeemable, HasEmployees};
+use project::error::Result;
+
+/// Which dialect to write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Syntax {
+    /// Also understood by hledger.
+    Ledger,
+    Beancount
+}
+
+/// Account names the postings go to.
+#[derive(Debug, Clone)]
+pub struct Accounts {
+    pub receivable: String,
+    pub bank: String,
+    /// The tax rate in percent is appended, e.g. `Income:Catering:19`.
+    pub revenue: String,
+    /// The tax rate in percent is appended, e.g. `Liabilities:VAT:19`.
+    pub tax: String,
+    pub wages: String,
+    /// Where wages are payed from.
+    pub wages_from: String,
+    pub currency: String
+}
+
+impl Accounts {
+    pub fn from_config() -> Accounts {
+        let get = |key:&str, default:&str| ::CONFIG.get_to_string(&format!("journal/{}", key))
+            .unwrap_or_else(|| String::from(default));
+        Accounts {
+            receivable: get("receivable", "Assets:Receivables"),
+            bank:       get("bank",       "Assets:Bank"),
+            revenue:    get("revenue",    "Income:Catering"),
+            tax:        get("tax",        "Liabilities:VAT"),
+            wages:      get("wages",      "Expenses:Wages"),
+            wages_from: get("wages_from", "Assets:Cash"),
+            currency:   get("currency",   "EUR"),
+        }
+    }
+}
+
+/// Order of transactions on the same day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Kind {
+    Invoice,
+    Payment,
+    Wages
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Posting {
+    pub account: String,
+    /// In cents, so transactions balance exactly.
+    pub cents: i64
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transaction {
+    pub date: Date<UTC>,
+    pub kind: Kind,
+    /// Invoice number, or the project name if there is none.
+    pub code: String,
+    pub payee: String,
+    pub narration: String,
+    pub postings: Vec<Posting>
+}
+
+fn cents(value:f64) -> i64 {
+    (value * 100.0).round() as i64
+}
+
+/// All transactions of one project, canceled projects have none.
+pub fn transactions(project:&Project, accounts:&Accounts) -> Result<Vec<Transaction>> {
+    let mut transactions = Vec::new();
+    if project.canceled() {
+        return Ok(transactions);
+    }
+
+    let name = IsProject::name(project).unwrap_or("unnamed").to_owned();
+    let payee = project.client().full_name().unwrap_or_else(|| name.to_owned());
+    let code = project.invoice().number_long_str().unwrap_or_else(|| name.to_owned());
+
+    if let Some(date) = project.invoice().date() {
+        let (_, invoice) = project.bills()?;
+        let mut postings = Vec::new();
+        let mut total = 0;
+        for (tax, list) in invoice.iter() {
+            let percent = (tax.into_inner() * 100.0).round() as i64;
+            let (net, tax_sum) = (cents(list.gross_sum().as_float()), cents(list.tax_sum().as_float()));
+            postings.push(Posting { account: format!("{}:{}", accounts.revenue, percent), cents: -net });
+            if tax_sum != 0 {
+                postings.push(Posting { account: format!("{}:{}", accounts.tax, percent), cents: -tax_sum });
+            }
+            total += net + tax_sum;
+        }
+        postings.insert(0, Posting { account: accounts.receivable.to_owned(), cents: total });
+
+        transactions.push(Transaction {
+            date: date, kind: Kind::Invoice,
+            code: code.to_owned(), payee: payee.to_owned(),
+            narration: format!("Rechnung {}", name),
+            postings: postings
+        });
+
+        if let Some(payed) = project.payed_date() {
+            transactions.push(Transaction {
+                date: payed, kind: Kind::Payment,
+                code: code.to_owned(), payee: payee.to_owned(),
+                narration: format!("Zahlung {}", name),
+                postings: vec![
+                    Posting { account: accounts.bank.to_owned(),       cents: total },
+                    Posting { account: accounts.receivable.to_owned(), cents: -total },
+                ]
+            });
+        }
+    }
+
+    let hours = project.hours();
+    if let (Some(date), Some(salary), Some(employees)) = (hours.wages_date(), hours.salary(), hours.employees()) {
+        let wages = employees.iter()
+            .map(|&(_, time)| cents(time * salary.as_float()))
+            .fold(0, |sum, wage| sum + wage);
+        let who = employees.iter()
+            .map(|&(ref employee, time)| format!("{} {}h", employee, time))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if wages != 0 {
+            transactions.push(Transaction {
+                date: date, kind: Kind::Wages,
+                code: code.to_owned(), payee: who,
+                narration: format!("Löhne {}", name),
+                postings: vec![
+                    Posting { account: accounts.wages.to_owned(),      cents: wages },
+                    Posting { account: accounts.wages_from.to_owned(), cents: -wages },
+                ]
+            });
+        }
+    }
+
+    Ok(transactions)
+}
+
+/// Sorts so the same projects always give the same output.
+pub fn sort(transactions:&mut Vec<Transaction>) {
+    transactions.sort_by(|a, b| (a.date, a.kind, &a.code, &a.narration).cmp(&(b.date, b.kind, &b.code, &b.narration)));
+}
+
+fn amount(cents:i64, currency:&str) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02} {}", sign, cents.abs() / 100, cents.abs() % 100, currency)
+}
+
+fn quote(text:&str) -> String {
+    format!("\"{}\"", text.replace('"', "'"))
+}
+
+/// Renders sorted transactions, beancount gets `open` directives for every account in front.
+pub fn render(transactions:&[Transaction], syntax:Syntax, currency:&str) -> String {
+    let mut journal = String::new();
+
+    if syntax == Syntax::Beancount {
+        if let Some(first) = transactions.iter().map(|t| t.date).min() {
+            let accounts = transactions.iter()
+                .flat_map(|t| t.postings.iter().map(|p| p.account.as_str()))
+                .collect::<BTreeSet<_>>();
+            for account in accounts {
+                let _ = writeln!(journal, "{} open {} {}", first.format("%Y-%m-%d"), account, currency);
+            }
+            journal.push('\n');
+        }
+    }
+
+    for transaction in transactions {
+        let date = transaction.date.format("%Y-%m-%d");
+        let _ = match syntax {
+            Syntax::Ledger    => writeln!(journal, "{} * ({}) {} | {}", date, transaction.code, transaction.payee, transaction.narration),
+            Syntax::Beancount => writeln!(journal, "{} * {} {}\n  code: {}", date, quote(&transaction.payee),
+                                          quote(&transaction.narration), quote(&transaction.code)),
+        };
+        for posting in &transaction.postings {
+            let _ = writeln!(journal, "    {:<40}{:>16}", posting.account, amount(posting.cents, currency));
+        }
+        journal.push('\n');
+    }
+    journal
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+    use project::Project;
+
+    fn accounts() -> Accounts {
+        Accounts {
+            receivable: String::from("Assets:Receivables"),
+            bank:       String::from("Assets:Bank"),
+            revenue:    String::from("Income:Catering"),
+            tax:        String::from("Liabilities:VAT"),
+            wages:      String::from("Expenses:Wages"),
+            wages_from: String::from("Assets:Cash"),
+            currency:   String::from("EUR"),
+        }
+    }
+
+    #[test]
+    fn balanced(){
+        let project = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+        let transactions = transactions(&project, &accounts()).unwrap();
+
+        // invoice and wages, not payed yet
+        assert_eq!(transactions.iter().map(|t| t.kind).collect::<Vec<_>>(), vec![Kind::Invoice, Kind::Wages]);
+        for transaction in &transactions {
+            assert_eq!(transaction.postings.iter().map(|p| p.cents).fold(0, |a, b| a + b), 0);
+        }
+
+        let invoice = &transactions[0];
+        assert_eq!(invoice.postings[0], Posting { account: String::from("Assets:Receivables"), cents: 4520 });
+        assert!(invoice.postings.contains(&Posting { account: String::from("Income:Catering:19"), cents: -2000 }));
+        assert!(invoice.postings.contains(&Posting { account: String::from("Liabilities:VAT:7"), cents: -140 }));
+
+        // 2h at 8.50
+        assert_eq!(transactions[1].postings[0].cents, 1700);
+    }
+
+    #[test]
+    fn stable_output(){
+        let current = Project::open_file(Path::new("./tests/current.yml")).unwrap();
+        let other = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+
+        let mut forth = transactions(&current, &accounts()).unwrap();
+        forth.extend(transactions(&other, &accounts()).unwrap());
+        let mut back = transactions(&other, &accounts()).unwrap();
+        back.extend(transactions(&current, &accounts()).unwrap());
+        sort(&mut forth);
+        sort(&mut back);
+        assert_eq!(render(&forth, Syntax::Ledger, "EUR"), render(&back, Syntax::Ledger, "EUR"));
+
+        let ledger = render(&forth, Syntax::Ledger, "EUR");
+        assert!(ledger.starts_with("2014-12-06 * (R2014-041) Graf Zahl | Rechnung Party Hard\n"));
+        assert!(ledger.contains("2014-12-08 * (R2014-041) Graf Zahl | Zahlung Party Hard\n"));
+
+        let beancount = render(&forth, Syntax::Beancount, "EUR");
+        assert!(beancount.starts_with("2014-12-06 open Assets:Bank EUR\n"));
+        assert!(beancount.contains("2016-12-06 * \"Graf Zahl & Söhne\" \"Rechnung Party Hard\"\n  code: \"R2016-042\"\n"));
+    }
+}
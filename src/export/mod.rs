@@ -0,0 +1,7 @@
+//! Exports for bookkeeping.
+//!
+//! Each format turns the invoices and payments of a range of projects into records
+//! that the accountant's software can import.
+
+pub mod datev;
+pub mod journal;
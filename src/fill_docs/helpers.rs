@@ -0,0 +1,411 @@
+//! Handlebars helpers available in every document template.
+//!
+//! | helper | example | result |
+//! |--------|---------|--------|
+//! | `money` | `{{money net_total_value}}`, `{{money 1234.5 lang="en"}}` | `1.234,50 €`, `€1,234.50` |
+//! | `date` | `{{date invoice.date "%d. %B %Y"}}` | `06. Dezember 2016` |
+//! | `words` | `{{words net_total_value}}` | `zwölf 50/100` |
+//! | `eq`, `ne`, `lt`, `le`, `gt`, `ge` | `{{#gt amount 1}}…{{else}}…{{/gt}}` | |
+//! | `plural` | `{{plural amount "Kiste" "Kisten"}}` | `Kisten` |
+//! | `t` | `{{t "invoice"}}` | `Rechnung` |
+//! | `inc` | `{{inc @index}}` | `1` |
+//! | `count` | `{{count items}}` | `3` |
+//! | `raw` | `{{raw storage.dirs.template}}` | unescaped value |
+//!
+//! `money`, `date`, `words` and `t` take a `lang` parameter, the default is `defaults/lang` from the config.
+//! `money`, `plural` and `t` write text from their parameters or the config, it is escaped like any other value.
+//! The output of the remaining helpers is not escaped, `raw` is the only one that writes data unchanged.
+
+use std::cmp::Ordering;
+use std::fmt::Write;
+
+use chrono::*;
+use rustc_serialize::json::Json;
+use handlebars::{Context, Helper, HelperDef, Handlebars, RenderContext, RenderError, Renderable};
+
+use super::OutputFormat;
+
+/// Registers all helpers of this module, `format` is the one of the rendered template.
+pub fn register(handlebars: &mut Handlebars, format: OutputFormat) {
+    handlebars.register_helper("inc",    Box::new(inc_helper));
+    handlebars.register_helper("count",  Box::new(count_helper));
+    handlebars.register_helper("raw",    Box::new(raw_helper));
+    handlebars.register_helper("money",  Box::new(Escaped(format, money)));
+    handlebars.register_helper("date",   Box::new(date_helper));
+    handlebars.register_helper("words",  Box::new(words_helper));
+    handlebars.register_helper("plural", Box::new(Escaped(format, plural)));
+    handlebars.register_helper("t",      Box::new(Escaped(format, translate)));
+
+    handlebars.register_helper("eq", Box::new(Compare(&[Ordering::Equal])));
+    handlebars.register_helper("ne", Box::new(Compare(&[Ordering::Less, Ordering::Greater])));
+    handlebars.register_helper("lt", Box::new(Compare(&[Ordering::Less])));
+    handlebars.register_helper("le", Box::new(Compare(&[Ordering::Less, Ordering::Equal])));
+    handlebars.register_helper("gt", Box::new(Compare(&[Ordering::Greater])));
+    handlebars.register_helper("ge", Box::new(Compare(&[Ordering::Greater, Ordering::Equal])));
+}
+
+fn write(rc: &mut RenderContext, rendered: &str) -> Result<(), RenderError> {
+    rc.writer.write(rendered.as_bytes())?;
+    Ok(())
+}
+
+fn param<'a>(h: &'a Helper, index: usize) -> Result<&'a Json, RenderError> {
+    h.param(index)
+     .map(|p| p.value())
+     .ok_or_else(|| RenderError::new(&format!("{} needs at least {} parameters", h.name(), index + 1)))
+}
+
+/// Numbers, or strings like `"12,50€"` as produced by `currency_to_string()`.
+fn to_f64(value: &Json) -> Option<f64> {
+    match *value {
+        Json::String(ref s) => {
+            let number = s.chars()
+                .filter(|c| c.is_digit(10) || *c == ',' || *c == '.' || *c == '-')
+                .collect::<String>();
+            // a comma is the decimal separator, dots in front of it group thousands
+            let number = if number.contains(',') { number.replace('.', "").replace(',', ".") } else { number };
+            number.parse().ok()
+        },
+        ref other => other.as_f64()
+    }
+}
+
+fn to_string(value: &Json) -> String {
+    match *value {
+        Json::String(ref s) => s.to_owned(),
+        Json::Null => String::new(),
+        ref other => other.to_string()
+    }
+}
+
+fn number_param(h: &Helper, index: usize) -> Result<f64, RenderError> {
+    let value = param(h, index)?;
+    to_f64(value).ok_or_else(|| RenderError::new(&format!("{}: {} is not a number", h.name(), value)))
+}
+
+/// `lang="en"` or `defaults/lang`.
+fn lang(h: &Helper) -> String {
+    h.hash_get("lang")
+     .map(|l| to_string(l.value()))
+     .or_else(|| ::CONFIG.get_str("defaults/lang").map(ToOwned::to_owned))
+     .unwrap_or_else(|| String::from("de"))
+}
+
+fn inc_helper(_: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let index = number_param(h, 0)?;
+    write(rc, &format!("{}", index as u64 + 1))
+}
+
+fn count_helper(_: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let count = param(h, 0)?.as_array().map_or(0, |a| a.len());
+    write(rc, &count.to_string())
+}
+
+/// `{{raw value}}` writes `value` without escaping, for data that intentionally contains markup.
+fn raw_helper(_: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let rendered = to_string(param(h, 0)?);
+    write(rc, &rendered)
+}
+
+/// Groups the digits of `number` in threes.
+fn group_digits(number: u64, separator: &str) -> String {
+    let digits = number.to_string();
+    let mut grouped = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index) % 3 == 0 {
+            grouped.push_str(separator);
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+/// `1234.5` becomes `1.234,50 €` in german and `€1,234.50` in english.
+pub fn format_money(value: f64, lang: &str, symbol: &str) -> String {
+    let cents = (value.abs() * 100.0).round() as u64;
+    let sign = if value < 0.0 && cents > 0 { "-" } else { "" };
+    let (separator, decimal) = match lang {
+        "en" => (",", "."),
+        "fr" => (" ", ","),
+        "ch" => ("'", "."),
+        _    => (".", ",")
+    };
+    let number = format!("{}{}{:02}", group_digits(cents / 100, separator), decimal, cents % 100);
+    match lang {
+        "en" => format!("{}{}{}", sign, symbol, number),
+        _    => format!("{}{} {}", sign, number, symbol)
+    }
+}
+
+/// `{{money value [symbol] lang="de"}}`
+fn money(h: &Helper) -> Result<String, RenderError> {
+    let value = number_param(h, 0)?;
+    let symbol = h.param(1)
+        .map(|s| to_string(s.value()))
+        .or_else(|| ::CONFIG.get_str("currency").map(ToOwned::to_owned))
+        .unwrap_or_else(String::new);
+    Ok(format_money(value, &lang(h), &symbol))
+}
+
+const MONTHS_DE: [&'static str; 12] = ["Januar", "Februar", "März", "April", "Mai", "Juni",
+                                       "Juli", "August", "September", "Oktober", "November", "Dezember"];
+const WEEKDAYS_DE: [&'static str; 7] = ["Montag", "Dienstag", "Mittwoch", "Donnerstag", "Freitag", "Samstag", "Sonntag"];
+
+/// Understands `"24.12.2016"` and `"2016-12-24"`.
+pub fn parse_date(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date.trim(), "%d.%m.%Y")
+        .or_else(|_| NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d"))
+        .ok()
+}
+
+/// Formats like `strftime`, names of months and weekdays are translated for `lang="de"`.
+///
+/// Fails on specifiers `strftime` doesn't know, like `%Q`.
+pub fn format_date(date: NaiveDate, pattern: &str, lang: &str) -> Result<String, RenderError> {
+    let pattern = if lang == "de" {
+        let month = MONTHS_DE[date.month0() as usize];
+        let weekday = WEEKDAYS_DE[date.weekday().num_days_from_monday() as usize];
+        pattern.replace("%B", month)
+               .replace("%b", &month.chars().take(3).collect::<String>())
+               .replace("%A", weekday)
+               .replace("%a", &weekday.chars().take(2).collect::<String>())
+    } else {
+        pattern.to_owned()
+    };
+    let mut formatted = String::new();
+    write!(formatted, "{}", date.format(&pattern))
+        .map_err(|_| RenderError::new(&format!("date: {:?} is not a valid format", pattern)))?;
+    Ok(formatted)
+}
+
+/// `{{date value "%d.%m.%Y" lang="de"}}`
+fn date_helper(_: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let value = to_string(param(h, 0)?);
+    let pattern = h.param(1).map(|p| to_string(p.value())).unwrap_or_else(|| String::from("%d.%m.%Y"));
+    match parse_date(&value) {
+        Some(date) => write(rc, &format_date(date, &pattern, &lang(h))?),
+        None if value.is_empty() => Ok(()),
+        None => Err(RenderError::new(&format!("date: can't read {:?}", value)))
+    }
+}
+
+const ONES_DE: [&'static str; 20] = ["null", "eins", "zwei", "drei", "vier", "fünf", "sechs", "sieben", "acht", "neun",
+                                     "zehn", "elf", "zwölf", "dreizehn", "vierzehn", "fünfzehn", "sechzehn", "siebzehn", "achtzehn", "neunzehn"];
+const TENS_DE: [&'static str; 10] = ["", "", "zwanzig", "dreißig", "vierzig", "fünfzig", "sechzig", "siebzig", "achtzig", "neunzig"];
+
+const ONES_EN: [&'static str; 20] = ["zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+                                     "ten", "eleven", "twelve", "thirteen", "fourteen", "fifteen", "sixteen", "seventeen", "eighteen", "nineteen"];
+const TENS_EN: [&'static str; 10] = ["", "", "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety"];
+
+/// 1 to 999, `compound` if something like `tausend` follows, which turns a trailing "eins" into "ein".
+fn below_thousand_de(n: u64, compound: bool) -> String {
+    let one = |n: u64| if n == 1 { "ein" } else { ONES_DE[n as usize] };
+    let mut words = String::new();
+    if n >= 100 {
+        words.push_str(one(n / 100));
+        words.push_str("hundert");
+    }
+    let rest = n % 100;
+    if rest == 1 {
+        words.push_str(if compound { "ein" } else { "eins" });
+    } else if rest > 0 && rest < 20 {
+        words.push_str(ONES_DE[rest as usize]);
+    } else if rest >= 20 {
+        if rest % 10 > 0 {
+            words.push_str(one(rest % 10));
+            words.push_str("und");
+        }
+        words.push_str(TENS_DE[(rest / 10) as usize]);
+    }
+    words
+}
+
+fn below_thousand_en(n: u64) -> String {
+    let mut words = Vec::new();
+    if n >= 100 {
+        words.push(format!("{} hundred", ONES_EN[(n / 100) as usize]));
+    }
+    let rest = n % 100;
+    if rest > 0 && rest < 20 {
+        words.push(ONES_EN[rest as usize].to_owned());
+    } else if rest >= 20 {
+        words.push(match rest % 10 {
+            0 => TENS_EN[(rest / 10) as usize].to_owned(),
+            unit => format!("{}-{}", TENS_EN[(rest / 10) as usize], ONES_EN[unit as usize])
+        });
+    }
+    words.join(" ")
+}
+
+/// Spells out whole numbers below a billion, in german unless `lang` is `"en"`.
+pub fn number_to_words(n: u64, lang: &str) -> Result<String, RenderError> {
+    if n >= 1_000_000_000 {
+        return Err(RenderError::new(&format!("words: {} is too large, only numbers below a billion are spelled out", n)));
+    }
+    let (millions, thousands, rest) = (n / 1_000_000, n / 1000 % 1000, n % 1000);
+    if lang == "en" {
+        if n == 0 { return Ok(ONES_EN[0].to_owned()) }
+        let mut words = Vec::new();
+        if millions > 0  { words.push(format!("{} million", below_thousand_en(millions))) }
+        if thousands > 0 { words.push(format!("{} thousand", below_thousand_en(thousands))) }
+        if rest > 0      { words.push(below_thousand_en(rest)) }
+        Ok(words.join(" "))
+    } else {
+        if n == 0 { return Ok(ONES_DE[0].to_owned()) }
+        let mut words = String::new();
+        match millions {
+            0 => {},
+            1 => words.push_str("eine Million "),
+            _ => words.push_str(&format!("{} Millionen ", below_thousand_de(millions, true)))
+        }
+        if thousands > 0 {
+            words.push_str(&below_thousand_de(thousands, true));
+            words.push_str("tausend");
+        }
+        words.push_str(&below_thousand_de(rest, false));
+        Ok(words.trim().to_owned())
+    }
+}
+
+/// `{{words value lang="de"}}`, cents are appended like on a receipt: `zwölf 50/100`.
+fn words_helper(_: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+    let value = number_param(h, 0)?;
+    let cents = (value.abs() * 100.0).round() as u64;
+    let mut words = number_to_words(cents / 100, &lang(h))?;
+    if cents % 100 > 0 {
+        words.push_str(&format!(" {:02}/100", cents % 100));
+    }
+    if value < 0.0 && cents > 0 {
+        words = format!("minus {}", words);
+    }
+    write(rc, &words)
+}
+
+/// Helper that writes text, escaped for the `OutputFormat` of the template.
+struct Escaped(OutputFormat, fn(&Helper) -> Result<String, RenderError>);
+
+impl HelperDef for Escaped {
+    fn call(&self, _: &Context, h: &Helper, _: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+        let text = (self.1)(h)?;
+        write(rc, &self.0.escape(&text))
+    }
+}
+
+/// `{{plural amount "Kiste" "Kisten"}}`
+fn plural(h: &Helper) -> Result<String, RenderError> {
+    let count = number_param(h, 0)?;
+    let word = if count == 1.0 { param(h, 1)? } else { param(h, 2)? };
+    Ok(to_string(word))
+}
+
+/// `{{t "invoice"}}` looks up `translations/<lang>/invoice` in the config, falls back to the key itself.
+fn translate(h: &Helper) -> Result<String, RenderError> {
+    let key = to_string(param(h, 0)?);
+    Ok(::CONFIG.get_str(&format!("translations/{}/{}", lang(h), key))
+        .map(ToOwned::to_owned)
+        .unwrap_or(key))
+}
+
+/// Block helper that compares its two parameters, numerically if both are numbers.
+///
+/// Renders its block if the result is one of the contained orderings, otherwise the `{{else}}` block.
+struct Compare(&'static [Ordering]);
+
+impl HelperDef for Compare {
+    fn call(&self, c: &Context, h: &Helper, r: &Handlebars, rc: &mut RenderContext) -> Result<(), RenderError> {
+        let (left, right) = (param(h, 0)?, param(h, 1)?);
+        let ordering = match (left.as_f64(), right.as_f64()) {
+            (Some(l), Some(r)) => l.partial_cmp(&r).unwrap_or(Ordering::Less),
+            _ => to_string(left).cmp(&to_string(right))
+        };
+
+        let branch = if self.0.contains(&ordering) { h.template() } else { h.inverse() };
+        match branch {
+            Some(t) => t.render(c, r, rc),
+            None => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::File;
+    use std::io::{Read, Write};
+
+    use rustc_serialize::json::Json;
+    use handlebars::Handlebars;
+
+    use super::*;
+
+    #[test]
+    fn money(){
+        assert_eq!(format_money(1234.5, "de", "€"), "1.234,50 €");
+        assert_eq!(format_money(1234.5, "en", "€"), "€1,234.50");
+        assert_eq!(format_money(-0.5, "de", "€"), "-0,50 €");
+        assert_eq!(format_money(1234567.0, "ch", "CHF"), "1'234'567.00 CHF");
+    }
+
+    #[test]
+    fn words(){
+        assert_eq!(number_to_words(0, "de").unwrap(), "null");
+        assert_eq!(number_to_words(1, "de").unwrap(), "eins");
+        assert_eq!(number_to_words(21, "de").unwrap(), "einundzwanzig");
+        assert_eq!(number_to_words(101, "de").unwrap(), "einhunderteins");
+        assert_eq!(number_to_words(1234, "de").unwrap(), "eintausendzweihundertvierunddreißig");
+        assert_eq!(number_to_words(101000, "de").unwrap(), "einhunderteintausend");
+        assert_eq!(number_to_words(2000001, "de").unwrap(), "zwei Millionen eins");
+        assert_eq!(number_to_words(1234, "en").unwrap(), "one thousand two hundred thirty-four");
+        assert_eq!(number_to_words(1000001, "en").unwrap(), "one million one");
+        assert_eq!(number_to_words(999999999, "en").unwrap(), "nine hundred ninety-nine million nine hundred ninety-nine thousand nine hundred ninety-nine");
+        assert!(number_to_words(1000000000, "de").is_err());
+        assert!(number_to_words(1000000000, "en").is_err());
+    }
+
+    #[test]
+    fn dates(){
+        let date = parse_date("06.12.2016").unwrap();
+        assert_eq!(parse_date("2016-12-06"), Some(date));
+        assert_eq!(format_date(date, "%e. %B %Y", "de").unwrap(), " 6. Dezember 2016");
+        assert_eq!(format_date(date, "%A, %d.%m.", "de").unwrap(), "Dienstag, 06.12.");
+        assert_eq!(format_date(date, "%B %d, %Y", "en").unwrap(), "December 06, 2016");
+        assert!(format_date(date, "%d.%Q", "de").is_err());
+    }
+
+    /// Renders `tests/fixtures/NAME.hbs` with `tests/fixtures/NAME.json` and compares it to `NAME.snap`.
+    ///
+    /// Run with `UPDATE_SNAPSHOTS=1` to accept the current output.
+    fn snapshot(name: &str) {
+        let read = |path: &str| {
+            let mut content = String::new();
+            File::open(path).and_then(|mut f| f.read_to_string(&mut content)).map(|_| content)
+        };
+        let template = read(&format!("./tests/fixtures/{}.hbs", name)).unwrap();
+        let data = Json::from_str(&read(&format!("./tests/fixtures/{}.json", name)).unwrap()).unwrap();
+
+        let mut handlebars = Handlebars::new();
+        register(&mut handlebars, OutputFormat::Plain);
+        handlebars.register_template_string(name, template).unwrap();
+        let rendered = handlebars.render(name, &data).unwrap();
+
+        let snap_path = format!("./tests/fixtures/{}.snap", name);
+        if env::var("UPDATE_SNAPSHOTS").is_ok() {
+            File::create(&snap_path).unwrap().write_all(rendered.as_bytes()).unwrap();
+        }
+        assert_eq!(rendered, read(&snap_path).unwrap());
+    }
+
+    #[test]
+    fn snapshot_helpers(){
+        snapshot("helpers");
+    }
+
+    #[test]
+    fn escaped_helpers(){
+        let mut handlebars = Handlebars::new();
+        register(&mut handlebars, OutputFormat::Latex);
+        handlebars.register_template_string("latex", String::from(r#"{{plural 2 "Kiste" "50% Kisten & Co"}} {{t "R&D"}} {{money 5 "$" lang="en"}}"#)).unwrap();
+        let rendered = handlebars.render("latex", &Json::Null).unwrap();
+        assert_eq!(rendered, r"50\% Kisten \& Co R\&D \$5.00");
+    }
+}
@@ -0,0 +1,187 @@
+//! Fills export templates to create tex documents.
+//!
+//! Haven't decided on a templating engine yet, my own will probably not do.
+
+use std::path::Path;
+use rustc_serialize::json::{ToJson, Json};
+use handlebars::{RenderError, Handlebars, no_escape, html_escape};
+
+use util;
+use project::Project;
+use storage::Storage;
+
+pub mod helpers;
+
+/// Sets up an instance of `Storage`.
+/// TODO isn't this redundant
+fn setup_luigi() -> Storage<Project> {
+    let working = ::CONFIG.get_str("dirs/working")
+        .expect("Faulty config: dirs/working does not contain a value");
+    let archive = ::CONFIG.get_str("dirs/archive")
+        .expect("Faulty config: dirs/archive does not contain a value");
+    let templates = ::CONFIG.get_str("dirs/templates")
+        .expect("Faulty config: dirs/templates does not contain a value");
+    Storage::new(util::get_storage_path(), working, archive, templates).unwrap()
+}
+
+struct PackData<'a, T: 'a + ToJson> {
+    document: &'a T,
+    storage: Storage<Project>,
+    is_invoice:bool
+}
+
+
+impl<'a, T> ToJson for PackData<'a, T>
+    where T: ToJson
+{
+    fn to_json(&self) -> Json {
+        Json::Object(btreemap!{
+            String::from("document")   => self.document.to_json(),
+            String::from("storage")    => self.storage.to_json(),
+            String::from("is_invoice") => self.is_invoice.to_json()
+        })
+    }
+}
+
+fn pack_data<E: ToJson>(document: &E, is_invoice:bool) -> PackData<E> {
+    PackData {
+        document: document,
+        storage: setup_luigi(),
+        is_invoice:is_invoice
+    }
+}
+
+/// What a template produces, decides how interpolated values are escaped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Latex,
+    Html,
+    Markdown,
+    /// Anything else is not escaped at all.
+    Plain
+}
+
+impl OutputFormat {
+    /// Looks at the extension in front of `.hbs`, `document.tex.hbs` produces `Latex`.
+    pub fn from_template(template_path:&Path) -> OutputFormat {
+        let inner = template_path.file_stem()
+            .map(Path::new)
+            .and_then(|stem| stem.extension())
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_lowercase());
+
+        match inner.as_ref().map(|ext| ext.as_str()) {
+            Some("tex") | Some("latex") => OutputFormat::Latex,
+            Some("html") | Some("htm")  => OutputFormat::Html,
+            Some("md") | Some("markdown") => OutputFormat::Markdown,
+            _ => OutputFormat::Plain
+        }
+    }
+
+    pub fn escape(&self, data:&str) -> String {
+        match *self {
+            OutputFormat::Latex    => escape_latex(data),
+            OutputFormat::Html     => html_escape(data),
+            OutputFormat::Markdown => escape_markdown(data),
+            OutputFormat::Plain    => no_escape(data)
+        }
+    }
+}
+
+/// Makes arbitrary text safe to put into a LaTeX document, line breaks become `\newline`.
+pub fn escape_latex(data:&str) -> String {
+    let mut escaped = String::with_capacity(data.len());
+    for c in data.chars() {
+        match c {
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => { escaped.push('\\'); escaped.push(c); },
+            '\\' => escaped.push_str(r"\textbackslash{}"),
+            '~'  => escaped.push_str(r"\textasciitilde{}"),
+            '^'  => escaped.push_str(r"\textasciicircum{}"),
+            '<'  => escaped.push_str(r"\textless{}"),
+            '>'  => escaped.push_str(r"\textgreater{}"),
+            '\n' => escaped.push_str(r"\newline "),
+            '\r' => {},
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+/// Keeps text from being interpreted as Markdown or inline html.
+pub fn escape_markdown(data:&str) -> String {
+    let mut escaped = String::with_capacity(data.len());
+    for c in data.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '#' | '|' => { escaped.push('\\'); escaped.push(c); },
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            _ => escaped.push(c)
+        }
+    }
+    escaped
+}
+
+use super::BillType;
+
+/// Takes a `T:ToJson` and a template path and does it's thing.
+///
+/// Every interpolated value is escaped for the `OutputFormat` of the template,
+/// use `{{raw value}}` or `{{{value}}}` for values that contain markup on purpose.
+/// Numbers and dates can be formatted by the template with [`helpers`](helpers/index.html).
+/// LaTeX braces right next to an expression are written with whitespace control,
+/// like `\Datum { {{~invoice.date~}} }`, so they don't form a triple-stash.
+///
+/// Returns path to created file, potenially in a `tempdir`.
+// pub fn fill_template<E:ToJson>(document:E, template_file:&Path) -> PathBuf{
+pub fn fill_template<E: ToJson, P:AsRef<Path>>(document: &E, bill_type:&BillType, template_path: P) -> Result<String, RenderError> {
+
+    let mut handlebars = Handlebars::new();
+
+    let format = OutputFormat::from_template(template_path.as_ref());
+    handlebars.register_escape_fn(move |data| format.escape(data));
+    helpers::register(&mut handlebars, format);
+
+    handlebars.register_template_file("document", template_path).unwrap();
+
+    let packed = match *bill_type {
+        BillType::Offer => pack_data(document, false),
+        BillType::Invoice => pack_data(document, true)
+    };
+
+
+    handlebars.render("document", &packed)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+    use super::*;
+
+    #[test]
+    fn format_from_template(){
+        assert_eq!(OutputFormat::from_template(Path::new("templates/document.tex.hbs")), OutputFormat::Latex);
+        assert_eq!(OutputFormat::from_template(Path::new("document.html.hbs")), OutputFormat::Html);
+        assert_eq!(OutputFormat::from_template(Path::new("notes.md.hbs")), OutputFormat::Markdown);
+        assert_eq!(OutputFormat::from_template(Path::new("simple.hbs")), OutputFormat::Plain);
+    }
+
+    #[test]
+    fn latex(){
+        assert_eq!(escape_latex("Müller & Söhne"), r"Müller \& Söhne");
+        assert_eq!(escape_latex("50% Rabatt"), r"50\% Rabatt");
+        assert_eq!(escape_latex("a_b {c} #1 $2"), r"a\_b \{c\} \#1 \$2");
+        assert_eq!(escape_latex(r"C:\~^"), r"C:\textbackslash{}\textasciitilde{}\textasciicircum{}");
+        assert_eq!(escape_latex("<b>"), r"\textless{}b\textgreater{}");
+        assert_eq!(escape_latex("Hauptstr. 1\n01234 Dresden"), r"Hauptstr. 1\newline 01234 Dresden");
+    }
+
+    #[test]
+    fn markdown_and_html(){
+        assert_eq!(escape_markdown("*Kaffee* <3 & [Tee]"), r"\*Kaffee\* &lt;3 &amp; \[Tee\]");
+        assert_eq!(OutputFormat::Html.escape("Müller & <Söhne>"), "Müller &amp; &lt;Söhne&gt;");
+        assert_eq!(OutputFormat::Plain.escape("50% <Rabatt>"), "50% <Rabatt>");
+    }
+}
+
+
@@ -0,0 +1,358 @@
+//! iCalendar invitations (RFC 5545).
+//!
+//! Every `VEVENT` becomes a [`Booking`](struct.Booking.html), or, grouped by `UID`,
+//! all events of one `UID` become a single booking with several dates.
+//! Times with a `TZID` are taken as local time, UTC times (`…Z`) are converted to local time.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use chrono::*;
+
+use util::yaml;
+use super::{unfold, unescape, property};
+
+/// `DTSTART` or `DTEND`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Moment {
+    Day(NaiveDate),
+    Time(NaiveDateTime)
+}
+
+impl Moment {
+    fn parse(value:&str, utc:bool) -> Option<Moment> {
+        if value.len() == 8 {
+            return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(Moment::Day);
+        }
+        let time = match NaiveDateTime::parse_from_str(&value[..value.len().min(15)], "%Y%m%dT%H%M%S") {
+            Ok(time) => time,
+            Err(_) => return None
+        };
+        if utc {
+            Some(Moment::Time(UTC.from_utc_datetime(&time).with_timezone(&Local).naive_local()))
+        } else {
+            Some(Moment::Time(time))
+        }
+    }
+
+    fn date(&self) -> NaiveDate {
+        match *self {
+            Moment::Day(date) => date,
+            Moment::Time(time) => time.date()
+        }
+    }
+}
+
+/// One `VEVENT`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VEvent {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    pub start: Moment,
+    pub end: Option<Moment>
+}
+
+/// One entry of `event/dates`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct EventDate {
+    pub begin: NaiveDate,
+    pub end: Option<NaiveDate>,
+    pub times: Option<(NaiveTime, NaiveTime)>
+}
+
+impl<'a> From<&'a VEvent> for EventDate {
+    fn from(event:&VEvent) -> EventDate {
+        match (event.start, event.end) {
+            (Moment::Time(start), Some(Moment::Time(end))) => EventDate {
+                begin: start.date(),
+                end: if end.date() > start.date() { Some(end.date()) } else { None },
+                times: Some((start.time(), end.time()))
+            },
+            (Moment::Time(start), _) => EventDate {
+                begin: start.date(), end: None, times: Some((start.time(), start.time()))
+            },
+            (Moment::Day(start), end) => {
+                // the end of whole days is exclusive
+                let last = end.map(|end| end.date() - Duration::days(1));
+                EventDate {
+                    begin: start,
+                    end: last.and_then(|last| if last > start { Some(last) } else { None }),
+                    times: None
+                }
+            }
+        }
+    }
+}
+
+/// What becomes one project.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Booking {
+    pub summary: String,
+    pub location: Option<String>,
+    pub description: Option<String>,
+    /// Sorted, at least one.
+    pub dates: Vec<EventDate>
+}
+
+impl Booking {
+    fn from_events(events:&[&VEvent]) -> Booking {
+        let first = events[0];
+        let mut dates = events.iter().map(|&e| EventDate::from(e)).collect::<Vec<_>>();
+        dates.sort();
+        dates.dedup();
+        Booking {
+            summary: events.iter().filter_map(|e| e.summary.clone()).next().unwrap_or_else(|| String::from("unnamed")),
+            location: events.iter().filter_map(|e| e.location.clone()).next(),
+            description: first.description.clone(),
+            dates: dates
+        }
+    }
+
+    /// Values for `DESCRIPTION`, `LOCATION`, `DATE-EVENT`, `DATE-END`, `TIME-START`, `TIME-END` and `MORE-DATES`.
+    ///
+    /// Every date but the first goes into `MORE-DATES`, templates without it only get the first.
+    pub fn fill_data(&self) -> HashMap<&'static str, String> {
+        let mut data = HashMap::new();
+        if let Some(ref location) = self.location {
+            data.insert("LOCATION", yaml::quote(location));
+        }
+        if let Some(ref description) = self.description {
+            // inside a block scalar
+            data.insert("DESCRIPTION", description.lines().collect::<Vec<_>>().join("\n    "));
+        }
+
+        let first = &self.dates[0];
+        data.insert("DATE-EVENT", first.begin.format("%d.%m.%Y").to_string());
+        if let Some(end) = first.end {
+            data.insert("DATE-END", end.format("%d.%m.%Y").to_string());
+        }
+        if let Some((start, end)) = first.times {
+            data.insert("TIME-START", start.format("%H:%M").to_string());
+            data.insert("TIME-END", end.format("%H:%M").to_string());
+        }
+
+        let more = self.dates[1..].iter()
+            .flat_map(date_entry)
+            .collect::<Vec<_>>();
+        if !more.is_empty() {
+            data.insert("MORE-DATES", more.join("\n  "));
+        }
+        data
+    }
+}
+
+/// Lines of one list item in `event/dates`, as in the default template.
+fn date_entry(date:&EventDate) -> Vec<String> {
+    let mut lines = vec![format!("- begin: {}", date.begin.format("%d.%m.%Y"))];
+    if let Some(end) = date.end {
+        lines.push(format!("  end: {}", end.format("%d.%m.%Y")));
+    }
+    if let Some((start, end)) = date.times {
+        lines.push(String::from("  times:"));
+        lines.push(format!("  -  begin: \"{}\"", start.format("%H:%M")));
+        lines.push(format!("     end:   \"{}\"", end.format("%H:%M")));
+    }
+    lines
+}
+
+/// One booking per event, or per `UID` if `by_uid`, in the order of the file.
+pub fn bookings(events:&[VEvent], by_uid:bool) -> Vec<Booking> {
+    if !by_uid {
+        return events.iter().map(|e| Booking::from_events(&[e])).collect();
+    }
+    let mut groups:Vec<Vec<&VEvent>> = Vec::new();
+    for event in events {
+        let group = event.uid.as_ref()
+            .and_then(|uid| groups.iter().position(|g| g[0].uid.as_ref() == Some(uid)));
+        match group {
+            Some(index) => groups[index].push(event),
+            None => groups.push(vec![event])
+        }
+    }
+    groups.iter().map(|g| Booking::from_events(g)).collect()
+}
+
+/// Project names for `bookings`, the `SUMMARY` unless several bookings share it.
+///
+/// Recurring events tend to repeat their summary, those get their first date appended,
+/// like `Stammtisch 06.12.2016`, and a counter if even that is not enough.
+pub fn project_names(bookings:&[Booking]) -> Vec<String> {
+    let mut names:Vec<String> = Vec::new();
+    for booking in bookings {
+        let shared = bookings.iter().filter(|other| other.summary == booking.summary).count() > 1;
+        let name = if shared {
+            format!("{} {}", booking.summary, booking.dates[0].begin.format("%d.%m.%Y"))
+        } else {
+            booking.summary.to_owned()
+        };
+        let mut unique = name.to_owned();
+        let mut counter = 2;
+        while names.contains(&unique) {
+            unique = format!("{} {}", name, counter);
+            counter += 1;
+        }
+        names.push(unique);
+    }
+    names
+}
+
+/// Reads all `VEVENT`s, alarms and other nested components are skipped.
+pub fn parse(content:&str) -> Result<Vec<VEvent>, String> {
+    let mut events = Vec::new();
+    let mut current:Option<HashMap<String, (String, String)>> = None;
+    let mut nested = 0;
+
+    for line in unfold(content) {
+        let (name, parameters, value) = match property(&line) {
+            Some(property) => property,
+            None => continue
+        };
+        match (name.as_str(), value.to_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") => current = Some(HashMap::new()),
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            ("END", "VEVENT") => {
+                let properties = current.take().ok_or("END:VEVENT without BEGIN:VEVENT")?;
+                events.push(event(&properties)?);
+            },
+            ("END", _) if nested > 0 => nested -= 1,
+            _ => if let Some(ref mut properties) = current {
+                if nested == 0 && !properties.contains_key(&name) {
+                    properties.insert(name.to_owned(), (parameters.to_owned(), value.to_owned()));
+                }
+            }
+        }
+    }
+
+    if events.is_empty() {
+        return Err(String::from("no VEVENT found"));
+    }
+    Ok(events)
+}
+
+fn event(properties:&HashMap<String, (String, String)>) -> Result<VEvent, String> {
+    let text = |name:&str| properties.get(name)
+        .map(|&(_, ref value)| unescape(value).trim().to_owned())
+        .and_then(|value| if value.is_empty() { None } else { Some(value) });
+    let moment = |name:&str| properties.get(name)
+        .map(|&(ref parameters, ref value)| {
+            let utc = value.ends_with('Z') && !parameters.to_uppercase().contains("TZID");
+            Moment::parse(value.trim(), utc).ok_or_else(|| format!("can't read {} {:?}", name, value))
+        });
+
+    let start = match moment("DTSTART") {
+        Some(start) => start?,
+        None => return Err(format!("event {:?} has no DTSTART", text("SUMMARY").unwrap_or_else(String::new)))
+    };
+    let end = match moment("DTEND") {
+        Some(end) => Some(end?),
+        None => None
+    };
+
+    Ok(VEvent {
+        uid: text("UID"),
+        summary: text("SUMMARY"),
+        location: text("LOCATION"),
+        description: text("DESCRIPTION"),
+        start: start,
+        end: end
+    })
+}
+
+/// Reads the events of an `.ics` file.
+pub fn read_ics(path:&Path) -> Result<Vec<VEvent>, String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    parse(&content)
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::*;
+    use super::*;
+
+    static INVITE: &'static str = "BEGIN:VCALENDAR\r\n\
+VERSION:2.0\r\n\
+BEGIN:VEVENT\r\n\
+UID:party@example.com\r\n\
+SUMMARY:Party: Hard\r\n\
+LOCATION:Nöthnitzer Str. 46\\, Dresden\r\n\
+DESCRIPTION:Kaffee für alle\\nund Kuchen für die\r\n  Hälfte\r\n\
+DTSTART;TZID=Europe/Berlin:20161206T190000\r\n\
+DTEND;TZID=Europe/Berlin:20161206T230000\r\n\
+BEGIN:VALARM\r\n\
+DESCRIPTION:Reminder\r\n\
+END:VALARM\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:party@example.com\r\n\
+RECURRENCE-ID;TZID=Europe/Berlin:20161207T190000\r\n\
+DTSTART;TZID=Europe/Berlin:20161207T180000\r\n\
+DTEND;TZID=Europe/Berlin:20161207T220000\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:fair@example.com\r\n\
+SUMMARY:Messe\r\n\
+DTSTART;VALUE=DATE:20161210\r\n\
+DTEND;VALUE=DATE:20161212\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n";
+
+    #[test]
+    fn events(){
+        let events = parse(INVITE).unwrap();
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[0].summary, Some(String::from("Party: Hard")));
+        assert_eq!(events[0].location, Some(String::from("Nöthnitzer Str. 46, Dresden")));
+        assert_eq!(events[0].description, Some(String::from("Kaffee für alle\nund Kuchen für die Hälfte")));
+        assert_eq!(events[0].start, Moment::Time(NaiveDate::from_ymd(2016, 12, 6).and_hms(19, 0, 0)));
+        assert_eq!(events[2].start, Moment::Day(NaiveDate::from_ymd(2016, 12, 10)));
+        assert!(parse("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").is_err());
+    }
+
+    #[test]
+    fn grouping(){
+        let events = parse(INVITE).unwrap();
+        assert_eq!(bookings(&events, false).len(), 3);
+
+        let grouped = bookings(&events, true);
+        assert_eq!(grouped.len(), 2);
+        assert_eq!(grouped[0].summary, "Party: Hard");
+        assert_eq!(grouped[0].dates.len(), 2);
+        // whole days end the day before DTEND
+        assert_eq!(grouped[1].dates, vec![EventDate { begin: NaiveDate::from_ymd(2016, 12, 10),
+                                                      end: Some(NaiveDate::from_ymd(2016, 12, 11)),
+                                                      times: None }]);
+    }
+
+    #[test]
+    fn names_of_recurring_events(){
+        let booking = |summary:&str, day:u32| Booking {
+            summary: String::from(summary), location: None, description: None,
+            dates: vec![EventDate { begin: NaiveDate::from_ymd(2016, 12, day), end: None, times: None }]
+        };
+        let bookings = [booking("Stammtisch", 6), booking("Messe", 10), booking("Stammtisch", 13), booking("Stammtisch", 13)];
+        assert_eq!(project_names(&bookings), vec![String::from("Stammtisch 06.12.2016"),
+                                                  String::from("Messe"),
+                                                  String::from("Stammtisch 13.12.2016"),
+                                                  String::from("Stammtisch 13.12.2016 2")]);
+    }
+
+    #[test]
+    fn fill_data(){
+        let events = parse(INVITE).unwrap();
+        let data = bookings(&events, true)[0].fill_data();
+        assert_eq!(data["LOCATION"], "\"Nöthnitzer Str. 46, Dresden\"");
+        assert_eq!(data["DESCRIPTION"], "Kaffee für alle\n    und Kuchen für die Hälfte");
+        assert_eq!(data["DATE-EVENT"], "06.12.2016");
+        assert_eq!(data["TIME-START"], "19:00");
+        assert_eq!(data["TIME-END"], "23:00");
+        assert!(!data.contains_key("DATE-END"));
+        assert_eq!(data["MORE-DATES"], "- begin: 07.12.2016\n    times:\n    -  begin: \"18:00\"\n       end:   \"22:00\"");
+    }
+}
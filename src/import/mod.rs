@@ -0,0 +1,96 @@
+//! Reading what other programs know about a booking into new projects.
+//!
+//! Importers produce fill data for the keywords of project templates,
+//! see [`Templater`](../templater/struct.Templater.html).
+//! Both formats are line based text as in RFC 5545 and RFC 6350 and share the helpers below.
+
+pub mod ics;
+pub mod vcard;
+
+/// Joins folded lines, a line starting with a space or tab continues the one before.
+fn unfold(content:&str) -> Vec<String> {
+    let mut lines:Vec<String> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_right_matches('\r');
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push(line.to_owned());
+    }
+    lines
+}
+
+fn unescape(text:&str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' { unescaped.push(c); continue }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\')
+        }
+    }
+    unescaped
+}
+
+/// Splits `DTSTART;TZID="Europe/Berlin":20161206T190000` into name, parameters and value.
+fn property(line:&str) -> Option<(String, &str, &str)> {
+    let mut quoted = false;
+    let colon = match line.char_indices().find(|&(_, c)| { if c == '"' { quoted = !quoted } c == ':' && !quoted }) {
+        Some((colon, _)) => colon,
+        None => return None
+    };
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let (name, parameters) = match head.find(';') {
+        Some(semicolon) => (&head[..semicolon], &head[semicolon + 1..]),
+        None => (head, "")
+    };
+    Some((name.to_uppercase(), parameters, value))
+}
+
+/// Splits a structured value like `N` or `ADR` at unescaped `;` and unescapes the components.
+fn components(value:&str) -> Vec<String> {
+    let mut components = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                current.push(c);
+                current.extend(chars.next());
+            },
+            ';' => components.push(unescape(&::std::mem::replace(&mut current, String::new()))),
+            _ => current.push(c)
+        }
+    }
+    components.push(unescape(&current));
+    components
+}
+
+/// Counterpart to `unescape()`.
+fn escape(text:&str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace(';', "\\;")
+        .replace('\n', "\\n")
+}
+
+/// Breaks lines longer than 75 bytes, ending every line with CRLF.
+fn fold(line:&str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+    folded.push_str("\r\n");
+    folded
+}
@@ -0,0 +1,299 @@
+//! vCards (RFC 6350 and the older 3.0) of clients.
+//!
+//! `Contact::from_project()` and `to_vcard()` export what a project knows about its client,
+//! `parse()` and `fill_data()` go the other way for `asciii new --client-vcard`.
+//! A project has a single `client/email`, so further addresses of a vCard end up in a comment behind it.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use project::Project;
+use project::spec::IsClient;
+use project::xrechnung::PostalAddress;
+use util::yaml;
+use super::{unfold, unescape, property, components, escape, fold};
+
+/// What a project knows about its client.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Contact {
+    /// `Herr`, `Frau`, …
+    pub title: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// Preferred address first.
+    pub emails: Vec<String>,
+    /// As in `client/address`, one line per line.
+    pub address: Option<String>
+}
+
+fn non_empty(text:&str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() { None } else { Some(text.to_owned()) }
+}
+
+impl Contact {
+    /// `None` if the project names neither client nor email.
+    pub fn from_project(project:&Project) -> Option<Contact> {
+        let client = project.client();
+        let contact = Contact {
+            title: client.title().and_then(non_empty),
+            first_name: client.first_name().and_then(non_empty),
+            last_name: client.last_name().and_then(non_empty),
+            emails: client.email()
+                          .map(|emails| emails.split(|c:char| c == ',' || c == ';' || char::is_whitespace(c))
+                                              .filter_map(non_empty)
+                                              .collect())
+                          .unwrap_or_else(Vec::new),
+            address: client.address().and_then(non_empty)
+        };
+        if contact.first_name.is_none() && contact.last_name.is_none() && contact.emails.is_empty() {
+            None
+        } else {
+            Some(contact)
+        }
+    }
+
+    /// `"first last"`, or whatever there is of it.
+    pub fn full_name(&self) -> String {
+        self.first_name.iter().chain(self.last_name.iter())
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Whether both describe the same person, by email or else by name.
+    pub fn same_as(&self, other:&Contact) -> bool {
+        let same_email = self.emails.iter().any(|e| other.emails.iter().any(|o| o.to_lowercase() == e.to_lowercase()));
+        let same_name = !self.full_name().is_empty() && self.full_name().to_lowercase() == other.full_name().to_lowercase();
+        same_email || (same_name && (self.emails.is_empty() || other.emails.is_empty()))
+    }
+
+    /// Completes missing fields from `other`.
+    pub fn merge(&mut self, other:&Contact) {
+        if self.title.is_none()      { self.title = other.title.clone() }
+        if self.first_name.is_none() { self.first_name = other.first_name.clone() }
+        if self.last_name.is_none()  { self.last_name = other.last_name.clone() }
+        if self.address.is_none()    { self.address = other.address.clone() }
+        for email in &other.emails {
+            if !self.emails.iter().any(|e| e.to_lowercase() == email.to_lowercase()) {
+                self.emails.push(email.to_owned());
+            }
+        }
+    }
+
+    /// A vCard 3.0, which everything still reads.
+    pub fn to_vcard(&self) -> String {
+        let text = |value:&Option<String>| value.as_ref().map(|v| escape(v)).unwrap_or_else(String::new);
+        let mut lines = vec![
+            String::from("BEGIN:VCARD"),
+            String::from("VERSION:3.0"),
+            format!("N:{};{};;{};", text(&self.last_name), text(&self.first_name), text(&self.title)),
+            format!("FN:{}", escape(&self.full_name())),
+        ];
+        for (index, email) in self.emails.iter().enumerate() {
+            let preferred = if index == 0 { ",PREF" } else { "" };
+            lines.push(format!("EMAIL;TYPE=INTERNET{}:{}", preferred, escape(email)));
+        }
+        if let Some(ref address) = self.address {
+            match PostalAddress::parse(address) {
+                Some(postal) => lines.push(format!("ADR;TYPE=WORK:;;{};{};;{};",
+                                                   text(&postal.street), escape(&postal.city), escape(&postal.zip))),
+                None => lines.push(format!("ADR;TYPE=WORK:;;{};;;;", escape(address)))
+            }
+            lines.push(format!("LABEL;TYPE=WORK:{}", escape(address)));
+        }
+        lines.push(String::from("END:VCARD"));
+        lines.iter().map(|line| fold(line)).collect()
+    }
+
+    /// Values for `CLIENT-TITLE`, `CLIENT-FIRST-NAME`, `CLIENT-LAST-NAME`, `CLIENT-EMAIL` and `CLIENT-ADDRESS`.
+    pub fn fill_data(&self) -> HashMap<&'static str, String> {
+        let mut data = HashMap::new();
+        if let Some(ref title) = self.title {
+            data.insert("CLIENT-TITLE", yaml::quote(title));
+        }
+        if let Some(ref first_name) = self.first_name {
+            data.insert("CLIENT-FIRST-NAME", yaml::quote(first_name));
+        }
+        if let Some(ref last_name) = self.last_name {
+            data.insert("CLIENT-LAST-NAME", yaml::quote(last_name));
+        }
+        if let Some((first, others)) = self.emails.split_first() {
+            let email = if others.is_empty() {
+                yaml::quote(first)
+            } else {
+                format!("{} # also: {}", yaml::quote(first), others.join(", "))
+            };
+            data.insert("CLIENT-EMAIL", email);
+        }
+        if let Some(ref address) = self.address {
+            // inside a block scalar
+            data.insert("CLIENT-ADDRESS", address.lines().collect::<Vec<_>>().join("\n    "));
+        }
+        data
+    }
+}
+
+/// Whether the parameters of a property mark it as preferred, in 3.0 or 4.0 style.
+fn preferred(parameters:&str) -> bool {
+    let parameters = parameters.to_uppercase();
+    parameters.split(';').any(|p| p.starts_with("PREF") || (p.starts_with("TYPE=") && p.contains("PREF")))
+}
+
+/// Reads the first vCard in `content`.
+pub fn parse(content:&str) -> Result<Contact, String> {
+    let mut properties:Vec<(String, String, String)> = Vec::new();
+    let mut inside = false;
+    for line in unfold(content) {
+        let (name, parameters, value) = match property(&line) {
+            Some(property) => property,
+            None => continue
+        };
+        // grouped properties look like `item1.EMAIL`
+        let name = name.rsplit('.').next().unwrap_or("").to_owned();
+        match (name.as_str(), value.trim().to_uppercase().as_str()) {
+            ("BEGIN", "VCARD") => inside = true,
+            ("END", "VCARD") if inside => break,
+            _ if inside => properties.push((name.to_owned(), parameters.to_owned(), value.to_owned())),
+            _ => ()
+        }
+    }
+    if properties.is_empty() {
+        return Err(String::from("no vCard found"));
+    }
+
+    let get = |wanted:&str| properties.iter().filter(|&&(ref name, _, _)| name == wanted).collect::<Vec<_>>();
+    let mut contact = Contact::default();
+
+    if let Some(&&(_, _, ref value)) = get("N").first() {
+        let n = components(value);
+        let part = |i:usize| n.get(i).and_then(|c| non_empty(c));
+        contact.last_name = part(0);
+        contact.first_name = part(1);
+        contact.title = part(3);
+    }
+    if contact.first_name.is_none() && contact.last_name.is_none() {
+        if let Some(&&(_, _, ref value)) = get("FN").first() {
+            let full_name = unescape(value);
+            let mut words = full_name.trim().rsplitn(2, ' ');
+            contact.last_name = words.next().and_then(non_empty);
+            contact.first_name = words.next().and_then(non_empty);
+        }
+    }
+
+    let mut emails = get("EMAIL");
+    // stable, so the order of the file stays otherwise
+    emails.sort_by_key(|&&(_, ref parameters, _)| !preferred(parameters));
+    contact.emails = emails.iter().filter_map(|&&(_, _, ref value)| non_empty(&unescape(value))).collect();
+
+    // the business address, or the first one
+    let pick = |name:&str| {
+        let candidates = get(name);
+        candidates.iter()
+            .find(|&&&(_, ref parameters, _)| preferred(parameters) || parameters.to_uppercase().contains("WORK"))
+            .or_else(|| candidates.first())
+            .map(|&&(_, _, ref value)| value.to_owned())
+    };
+
+    // a formatted label is closest to `client/address`
+    if let Some(label) = pick("LABEL") {
+        contact.address = non_empty(&unescape(&label));
+    } else if let Some(value) = pick("ADR") {
+        // post office box, extended address, street, locality, region, postal code, country
+        let adr = components(&value);
+        let part = |i:usize| adr.get(i).and_then(|c| non_empty(c));
+        let organization = get("ORG").first().and_then(|&&(_, _, ref org)| components(org).into_iter().next()).and_then(|o| non_empty(&o));
+        let place = [part(5), part(3)].iter().filter_map(|p| p.clone()).collect::<Vec<_>>().join(" ");
+        let lines = organization.into_iter()
+            .chain(part(1))
+            .chain(part(0))
+            .chain(part(2).into_iter().flat_map(|street| street.lines().map(ToOwned::to_owned).collect::<Vec<_>>()))
+            .chain(non_empty(&place))
+            .chain(part(6))
+            .collect::<Vec<_>>();
+        contact.address = non_empty(&lines.join("\n"));
+    }
+
+    Ok(contact)
+}
+
+/// Reads the first vCard of a `.vcf` file.
+pub fn read_vcard(path:&Path) -> Result<Contact, String> {
+    let mut content = String::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_string(&mut content))
+        .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+    parse(&content)
+}
+
+/// Contacts of all `projects`, each person only once.
+pub fn contacts(projects:&[Project]) -> Vec<Contact> {
+    let mut contacts:Vec<Contact> = Vec::new();
+    for contact in projects.iter().filter_map(Contact::from_project) {
+        match contacts.iter().position(|c| c.same_as(&contact)) {
+            Some(index) => contacts[index].merge(&contact),
+            None => contacts.push(contact)
+        }
+    }
+    contacts
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+    use project::Project;
+    use storage::Storable;
+
+    static CARD: &'static str = "BEGIN:VCARD\r\n\
+VERSION:4.0\r\n\
+N:Zahl;Graf;;Herr;\r\n\
+FN:Graf Zahl\r\n\
+ORG:Zahl & Söhne\r\n\
+EMAIL;TYPE=home:graf@example.com\r\n\
+item1.EMAIL;TYPE=work;PREF=1:buchhaltung@zahl-und-soehne.example\r\n\
+ADR;TYPE=home:;;Am Schloss 1;Transsilvanien;;99999;\r\n\
+ADR;TYPE=work:;;Nummernhöllenstraße 666;Countilvania;;01234;\r\n\
+END:VCARD\r\n";
+
+    #[test]
+    fn parsing(){
+        let contact = parse(CARD).unwrap();
+        assert_eq!(contact.title, Some(String::from("Herr")));
+        assert_eq!(contact.first_name, Some(String::from("Graf")));
+        assert_eq!(contact.last_name, Some(String::from("Zahl")));
+        assert_eq!(contact.emails, vec![String::from("buchhaltung@zahl-und-soehne.example"), String::from("graf@example.com")]);
+        assert_eq!(contact.address, Some(String::from("Zahl & Söhne\nNummernhöllenstraße 666\n01234 Countilvania")));
+        assert!(parse("BEGIN:VCALENDAR\r\nEND:VCALENDAR\r\n").is_err());
+    }
+
+    #[test]
+    fn fill_data(){
+        let data = parse(CARD).unwrap().fill_data();
+        assert_eq!(data["CLIENT-LAST-NAME"], "Zahl");
+        assert_eq!(data["CLIENT-EMAIL"], "\"buchhaltung@zahl-und-soehne.example\" # also: graf@example.com");
+        assert_eq!(data["CLIENT-ADDRESS"], "Zahl & Söhne\n    Nummernhöllenstraße 666\n    01234 Countilvania");
+    }
+
+    #[test]
+    fn round_trip(){
+        let contact = parse(CARD).unwrap();
+        let vcard = contact.to_vcard();
+        assert!(vcard.starts_with("BEGIN:VCARD\r\nVERSION:3.0\r\nN:Zahl;Graf;;Herr;\r\n"));
+        assert!(vcard.contains("ADR;TYPE=WORK:;;Nummernhöllenstraße 666;Countilvania;;01234;\r\n"));
+        assert!(vcard.lines().all(|line| line.trim_right().len() <= 75));
+        assert_eq!(parse(&vcard).unwrap(), contact);
+    }
+
+    #[test]
+    fn deduplicated(){
+        let project = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+        let twice = vec![Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap(), project];
+        let contacts = contacts(&twice);
+        assert_eq!(contacts.len(), 1);
+        assert_eq!(contacts[0].last_name, Some(String::from("Zahl & Söhne")));
+    }
+}
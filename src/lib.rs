@@ -38,6 +38,9 @@ extern crate ordered_float;
 extern crate open;
 extern crate semver;
 extern crate term_size;
+extern crate num_cpus;
+extern crate tar;
+extern crate sha2;
 extern crate icalendar;
 #[macro_use] extern crate error_chain;
 #[macro_use] extern crate log;
@@ -58,11 +61,15 @@ pub mod project;
 pub mod storage;
 pub mod print;
 pub mod actions;
+pub mod export;
+pub mod bank;
+pub mod import;
 
 pub mod templater;
 
 #[cfg(feature="document_export")] extern crate rustc_serialize;
 #[cfg(feature="document_export")] extern crate handlebars;
+#[cfg(feature="document_export")] extern crate qrcode;
 #[cfg(feature="document_export")] pub mod fill_docs;
 
 pub use yaml_rust::Yaml;
@@ -95,3 +102,46 @@ impl ToString for BillType{
         }.to_owned()
     }
 }
+
+/// What `asciii make` produces.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocumentFormat{
+    /// A `.tex` file from a `.tex.hbs` template, converted by `convert/tool`.
+    Latex,
+    /// A standalone `.html` file from a `.html.hbs` template, print it from a browser.
+    Html,
+    /// An EN 16931 e-invoice (XRechnung, UBL syntax), no template involved.
+    XRechnung
+}
+
+impl DocumentFormat{
+    /// Extension of the templates for this format, e.g. `tex.hbs`, `None` if it doesn't use templates.
+    pub fn template_extension(&self) -> Option<String> {
+        match *self{
+            DocumentFormat::Latex     => Some(CONFIG.get_str("extensions/output_template").expect("Faulty default config").to_owned()),
+            DocumentFormat::Html      => Some(String::from("html.hbs")),
+            DocumentFormat::XRechnung => None
+        }
+    }
+
+    /// Extension of the filled template, e.g. `tex`.
+    pub fn output_extension(&self) -> String {
+        match *self{
+            DocumentFormat::Latex => CONFIG.get_str("extensions/output_file").expect("Faulty default config").to_owned(),
+            DocumentFormat::Html  => String::from("html"),
+            DocumentFormat::XRechnung => String::from("xml")
+        }
+    }
+}
+
+impl ::std::str::FromStr for DocumentFormat{
+    type Err = String;
+    fn from_str(name:&str) -> Result<DocumentFormat, String> {
+        match name.to_lowercase().as_str() {
+            "latex" | "tex" | "pdf" => Ok(DocumentFormat::Latex),
+            "html"                  => Ok(DocumentFormat::Html),
+            "xrechnung" | "ubl"     => Ok(DocumentFormat::XRechnung),
+            _ => Err(format!("unknown document format {:?}", name))
+        }
+    }
+}
@@ -6,6 +6,7 @@ extern crate chrono;
 extern crate term; // TODO consolidate term, ansi_term and terminal_size
 extern crate open;
 extern crate icalendar;
+extern crate diff;
 
 #[cfg(feature="shell")] extern crate rustyline;
 
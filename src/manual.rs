@@ -56,26 +56,69 @@
 //! 
 //! ```bash
 //! asciii help [COMMAND]               # Describe available commands or one specific command
+//! asciii init [PATH] [--git]          # Create the storage and install the built-in templates
 //! asciii list                         # List current Projects
 //! asciii show NAMES                   # Shows information about a project in different ways
+//! asciii templates [--diff]           # Compare your templates with the built-in ones, e.g. after an upgrade
 //! ```
 //! 
+//! The default project template (`default.tyml`) and document template (`document.tex.hbs`) are part of the binary.
+//! `init` only installs templates that are missing, it never overwrites your own.
+//! 
 //! ### Project Life-Cycle
 //! 
 //! 
 //! ```bash
 //! asciii new NAME                     # Creating a new project
+//! asciii new NAME --from OTHER        # ... as a copy of another project (or archive/YEAR/OTHER)
+//! asciii new --from-ics invite.ics    # ... one per event of a calendar invitation
+//! asciii new --from-ics invite.ics --by-uid  # ... one per UID, with all dates of its events
+//! asciii new NAME --client-vcard graf.vcf    # ... with title, name, email and address of the client from a vCard
+//! asciii show --vcard NAMES           # The clients of projects as vCards
+//! asciii show --vcard --year 2016     # ... of every project in 2016, each client only once
 //! asciii edit NAMES                   # Edit project
 //! asciii make NAME                    # Creates an Offer
 //! 
 //! asciii edit NAMES                   # Edit project
 //! asciii make NAME                    # Creates an Invoice
+//! asciii make --watch NAME            # ... and again every time you save
+//! asciii make --invoice --reissue WHY NAME  # ... again, although it changed since it was issued
+//! asciii make --format html NAME      # Standalone html document instead of LaTeX, print it from your browser
+//! asciii make --format xrechnung NAME # EN 16931 e-invoice for public-sector clients
 //! 
 //! asciii archive NAME                 # Move project to archive
 //! asciii unarchive YEAR NAME          # reopen an archived project
 //! asciii delete NAME                  # If you really have to
+//! asciii trash list                   # Deleted projects end up in the trash
+//! asciii trash restore NAME           # ... and can be brought back
+//! asciii trash empty                  # ... until you empty it
+//! asciii undo                         # Revert the last archive, unarchive or delete
+//! asciii fsck [--fix]                 # Check all projects for inconsistencies
+//! ```
+//! 
+//! ### Issued Invoices
+//! 
+//! Once `make` has produced an invoice, a fingerprint of the billed items, the totals and the client
+//! is recorded in the `issued:` section of the project file.
+//! If any of those change afterwards, `list` marks the invoice number with a `!`, `fsck` reports the project
+//! and `make` refuses to produce the invoice again.
+//! If the change is on purpose, pass a reason with `--reissue`, it is kept in `issued/reissued` along with the old fingerprint.
+//! 
+//! ### E-Invoices
+//! 
+//! `make --format xrechnung` writes the invoice as XRechnung (UBL) xml into `output_path`.
+//! Your own details come from the `seller` section of your config (name, address, `vat_id` or `tax_number`,
+//! contact, email, phone and `iban`), the client needs an address ending in a line like `01234 City`,
+//! the two letter code of its `country` and a `buyer_reference`, the *Leitweg-ID* public-sector clients hand out:
+//! 
+//! ```yaml
+//! client:
+//!   buyer_reference: 04011000-1234512345-06
+//!   country: DE
 //! ```
 //! 
+//! Anything missing is listed and nothing is written.
+//! 
 //! ### GIT Features
 //! 
 //! ```bash
@@ -84,11 +127,20 @@
 //! asciii pull / push
 //! asciii cleanup
 //! asciii status, log, diff, stash, pop
+//! asciii history NAME  # what changed in a project, e.g. "Kaffee sold 5 → 7"
 //! ```
 //! 
 //! These commands behave similar to the original git commands.
 //! The only difference is that you select projects just like you do with other ascii commands (see edit, display, offer, invoice).
-//! Commit uses -m (like in git) but unlike git does not (yet) open an editor if you leave out the message.
+//! Commit uses -m (like in git) and opens your `user/editor` if you leave out the message, `user/name` is used as author.
+//! Everything except pull, push, remote and cleanup works without a `git` executable.
+//!
+//! Set `git/auto_commit: true` in your config to have `new`, `set`, `archive`, `unarchive`, `delete` and `make` commit their changes right away,
+//! with messages like `archive R042 Sommerfest (2016)`.
+//! A command that touches several projects makes only one commit.
+//!
+//! Run `asciii merge-driver --install` once per storage, then `asciii pull` merges project files key by key instead of line by line.
+//! Only values that were changed differently on both sides conflict, in that case your version is kept and the conflicts are listed.
 //! 
 //! #### CAREFUL:
 //! These commands are meant as a convenience, they ARE NOT however a *complete* replacement for git!
@@ -122,6 +174,10 @@
 //! As some fields are computed you have to use a different syntax to access them,
 //! try for instance `asciii list -d ClientFullName`.
 //! For a full list run `asciii list --computed`.
+//!
+//! The fields needed by `list`, `csv`, `dues` and `calendar` are cached in `.asciii_cache.yml` in your storage path,
+//! so only projects that changed since are opened again.
+//! Pass `--no-cache` if you suspect the cache to be wrong, or just delete the file.
 //! 
 //! 
 //! ### Exporting
@@ -134,14 +190,75 @@
 //! ```
 //! 
 //! You can pipe the csv into column (`asciii csv | column -ts\;`) to display the table in you terminal.
+//!
+//! To hand over a complete year, including generated documents and the templates they use, create a bundle.
+//! It contains a manifest with checksums, `import-bundle` verifies it and never overwrites existing projects.
+//!
+//! ```bash
+//! asciii export-bundle --year 2016    # writes asciii-2016.tar
+//! asciii import-bundle asciii-2016.tar
+//! ```
+//! 
+//! For the tax accountant, `export datev` writes a DATEV booking batch (Windows-1252, `EXTF` header)
+//! with every invoice and payment dated within the range.
+//! Invoices are booked per tax rate on the revenue accounts in `datev/revenue` of your config,
+//! payments on `datev/bank`, both against `client/account` of the project or the collective `datev/debitor`.
+//! The document number is the long invoice number, e.g. `R2016-042`.
+//! 
+//! ```bash
+//! asciii export datev --from 01.01.2017 --to 31.03.2017   # writes EXTF_Buchungsstapel_20170101_20170331.csv
+//! ```
+//! 
+//! If you reconcile yourself, `export ledger` (also read by hledger) and `export beancount` print a journal
+//! with one transaction per invoice, payment and wage payout (`hours/wages_date`).
+//! The accounts are set in the `journal` section of your config, revenue and tax are split per tax rate,
+//! e.g. `Income:Catering:19`. The order is stable, so keep the output around and diff it.
 //! 
+//! ```bash
+//! asciii export ledger > asciii.journal
+//! hledger -f asciii.journal balance
+//! ```
+//! 
+//! ### Bank Statements
+//! `bank import` reads a bank statement, either CAMT.053 xml or the csv export of your online banking,
+//! and looks for credits that pay open invoices of the working directory.
+//! A credit matches if its reference contains the invoice number (`R042` or `R2016-042`),
+//! otherwise if its amount equals the total of exactly one open invoice.
+//! Every proposed match is shown with its evidence and recorded as `invoice/payed_date` once you confirm it,
+//! credits without a match are listed at the end.
+//! 
+//! ```bash
+//! asciii bank import umsaetze.csv
+//! asciii bank import statement.xml --yes   # record all proposed payments without asking
+//! ```
+//! 
+//! ### Profiles
+//!
+//! Keep several storages apart, e.g. private and club projects.
+//! Every field under `profiles/<name>/` in your `~/.asciii.yml` overrides the field of the same name while that profile is active.
+//!
+//! ```yaml
+//! path: ~/asciii_projects
+//! profiles:
+//!   club:
+//!     path: ~/club_projects
+//!     user:
+//!       name: Club Treasurer
+//! ```
+//!
+//! ```bash
+//! asciii --profile club list  # or
+//! ASCIII_PROFILE=club asciii list
+//! asciii --profile club path  # ~/club_projects/projects
+//! ```
+//!
 //! ### Miscellaneous
 //! 
 //! ```bash
 //! asciii path      # Return projects storage path
 //! asciii config -e # Edit configuration
 //! asciii templates # List or add templates
-//! asciii whoami    # Invoke settings --show user/name
+//! asciii whoami    # Show user/name and the active profile
 //! asciii version   # Display version
 //! ```
 //! 
@@ -252,6 +369,35 @@
 //!         └── Foobar3.yml
 //! ```
 //! 
+//! ### Document Templates
+//! Documents are rendered from handlebars templates like `document.tex.hbs`.
+//! The extension in front of `.hbs` decides how values are escaped: `tex` for LaTeX, `html` and `md` accordingly, anything else is left alone.
+//! A client called `"ABC GmbH & Co. KG"` or a product `"50% Rabatt"` therefore ends up in the document as written.
+//! If a value contains markup on purpose, write `{{raw value}}`.
+//! LaTeX braces around a value need a space, otherwise they'd form a `{{{triple-stash}}}`,
+//! `~` removes that space again: `\Datum { {{~invoice.date~}} }`.
+//! 
+//! `make --format html` uses `document.html.hbs` instead (or `--template NAME` for `NAME.html.hbs`)
+//! and writes the finished `.html` straight into `output_path`, no TeX installation needed.
+//! Keep the CSS inside the template's `<style>` so the file stands on its own.
+//! 
+//! Totals and prices are also available as plain numbers (`gross_total_value`, `price_value`, …)
+//! for the helpers that format them:
+//! 
+//! * `{{money invoice.gross_total_value}}` → `1.234,50 €`, `lang="en"` → `€1,234.50`
+//! * `{{date invoice.date "%d. %B %Y"}}` → `06. Dezember 2016`
+//! * `{{words invoice.gross_total_value}}` → `eintausendzweihundertvierunddreißig 50/100`
+//! * `{{#gt amount 1}}…{{else}}…{{/gt}}`, as well as `eq`, `ne`, `lt`, `le` and `ge`
+//! * `{{plural amount "Kiste" "Kisten"}}`
+//! * `{{t "invoice"}}` → `Rechnung`, from the `translations` section of the config
+//! 
+//! `money`, `date`, `words` and `t` follow `defaults/lang` unless given `lang="…"`.
+//! 
+//! If `seller/name` and `seller/iban` are set in your config, invoices carry a GiroCode (EPC069-12 SEPA QR code)
+//! that banking apps scan to prefill the transfer with your account, the gross total and the invoice number.
+//! Invoice templates find it in `payment`: `{{raw payment.qr_tex}}` is a LaTeX picture, `{{raw payment.qr_svg}}` an svg,
+//! next to `payment.iban`, `payment.bic`, `payment.amount` and `payment.reference`.
+//! 
 //! ## Aliases
 //! 
 //! // * `list`: `-l`, `l`, `ls`, `dir`
@@ -260,7 +406,7 @@
 //! // * `invoice`: `-l`
 //! // * `offer`: `-o`
 //! // * `settings`: `config`
-//! // * `log`: `history`
+//! // * `log`: `lg`, `hist`
 //! 
 //! ## Pro tips
 //! 
@@ -273,7 +419,6 @@
 //! 
 //! ## Known Issues
 //! 
-//! Some strings may cause problems when rendering latex, e.g.
-//! a client called `"ABC GmbH & Co. KG"`.
-//! The `"&"` causes latex to fail, `\&"` bugs the yaml parser but `"\\&"` will do the trick.
+//! Older document templates use `<` and `>` instead of braces around values,
+//! those are no longer replaced, compare yours with `asciii templates --diff`.
 //! asciii list -dCaterers -fCaterers:hendrik
@@ -16,7 +16,7 @@ use project::Project;
 use project::spec::{IsProject, Redeemable, Invoicable, HasEmployees};
 use project::spec::events::HasEvents;
 use project::error::SpecResult;
-use storage::Storable;
+use storage::{Storable, IndexEntry};
 use util::currency_to_string;
 
 /// Configuration for this list output.
@@ -71,16 +71,44 @@ fn result_to_cell(res: &SpecResult, bold:bool) -> Cell{
     }
 }
 
+/// `R042`, marked with a red `!` if the invoice was modified after it was issued.
+fn invoice_number_cell(number:Option<&str>, modified_since_issued:bool, use_colors:bool) -> Cell {
+    let number = number.unwrap_or("");
+    if modified_since_issued {
+        cell!(format!("{}!", number)).style_spec(if use_colors {"Fr"} else {""})
+    } else {
+        cell!(number)
+    }
+}
+
 /// create a Style string from the properties of a project
-fn project_to_style(project:&Project) -> &str{
+fn project_to_style(project:&Project) -> &'static str{
+    to_style(project.is_ready_for_invoice().is_ok(), project.canceled(), project.modified_date())
+}
+
+/// create a Style string from the cached properties of a project
+fn entry_to_style(entry:&IndexEntry) -> &'static str{
+    to_style(entry.get("ReadyForInvoice") == Some("true"),
+             entry.get("Canceled") == Some("true"),
+             entry_date(entry))
+}
+
+/// parses the `Date` field of an `IndexEntry`
+pub fn entry_date(entry:&IndexEntry) -> Option<Date<UTC>>{
+    entry.get("Date")
+         .and_then(|d| NaiveDate::parse_from_str(d, "%Y.%m.%d").ok())
+         .map(|d| Date::from_utc(d, UTC))
+}
+
+fn to_style(ready_for_invoice:bool, canceled:bool, date:Option<Date<UTC>>) -> &'static str{
     // can be send as invoice
-    if project.is_ready_for_invoice().is_ok(){
+    if ready_for_invoice{
         return "d"
     }
 
-    if let Some(date) = project.modified_date(){
+    if let Some(date) = date{
         let age = (Local::today() - date).num_days();
-        if project.canceled(){
+        if canceled{
             return ""
         }
         return match age{
@@ -103,7 +131,8 @@ pub fn path_rows(projects:&[Project], list_config:&ListConfig) -> Vec<Row>{
         .map(|project| {
             let row_style = if list_config.use_colors {project_to_style(project)}else{""};
             Row::new(vec![
-                     cell!(project.invoice().number_str().unwrap_or("".into())),
+                     invoice_number_cell(project.invoice().number_str().as_ref().map(|s| s.as_str()),
+                                         project.modified_since_issued(), list_config.use_colors),
                      cell!(project.short_desc()).style_spec(row_style),
                      cell!(project.file().display()),
 
@@ -130,7 +159,8 @@ pub fn simple_rows(projects:&[Project], list_config:&ListConfig) -> Vec<Row>{
                      .style_spec(row_style),
 
                      //cell!(project.manager()),
-                     cell!(project.invoice().number_str().unwrap_or("".into())),
+                     invoice_number_cell(project.invoice().number_str().as_ref().map(|s| s.as_str()),
+                                         project.modified_since_issued(), list_config.use_colors),
 
                      cell!(project.modified_date().map(|d|d.format("%d.%m.%Y").to_string()).unwrap_or("no_date".into())),
                      //cell!(project.file().display()),
@@ -139,6 +169,68 @@ pub fn simple_rows(projects:&[Project], list_config:&ListConfig) -> Vec<Row>{
     .collect()
 }
 
+/// Fields of a project that `simple_index_rows()`, `path_index_rows()` and `dynamic_index_rows()` need.
+pub const INDEX_FIELDS:[&'static str;7] = ["Name", "InvoiceNumber", "Date", "Canceled", "ReadyForInvoice", "SortIndex", "ModifiedSinceIssued"];
+
+fn entry_name(entry:&IndexEntry) -> String{
+    entry.get("Name")
+         .map(ToOwned::to_owned)
+         .unwrap_or_else(|| format!("unnamed: {:?}", entry.dir().file_name().and_then(|n| n.to_str()).unwrap_or("")))
+}
+
+/// Like `path_rows()`, but from the `IndexCache`.
+pub fn path_index_rows(entries:&[IndexEntry], list_config:&ListConfig) -> Vec<Row>{
+    entries
+        .iter()
+        .map(|entry| {
+            let row_style = if list_config.use_colors {entry_to_style(entry)}else{""};
+            Row::new(vec![
+                     invoice_number_cell(entry.get("InvoiceNumber"),
+                                         entry.get("ModifiedSinceIssued") == Some("true"), list_config.use_colors),
+                     cell!(entry_name(entry)).style_spec(row_style),
+                     cell!(entry.file().display()),
+            ])
+        })
+    .collect()
+}
+
+/// Like `simple_rows()`, but from the `IndexCache`.
+pub fn simple_index_rows(entries:&[IndexEntry], list_config:&ListConfig) -> Vec<Row>{
+    entries
+        .iter()
+        .map(|entry| {
+            let row_style = if list_config.use_colors {entry_to_style(entry)}else{""};
+            Row::new(vec![
+                     cell!(
+                         if entry.get("Canceled") == Some("true") {
+                             format!("X {name}", name=entry_name(entry))
+                         } else{
+                             entry_name(entry)
+                         })
+                     .style_spec(row_style),
+                     invoice_number_cell(entry.get("InvoiceNumber"),
+                                         entry.get("ModifiedSinceIssued") == Some("true"), list_config.use_colors),
+                     cell!(entry_date(entry).map(|d|d.format("%d.%m.%Y").to_string()).unwrap_or("no_date".into())),
+            ])
+        })
+    .collect()
+}
+
+/// Like `dynamic_rows()`, but from the `IndexCache`, therefore without errors.
+pub fn dynamic_index_rows(entries:&[IndexEntry], list_config:&ListConfig) -> Vec<Row>{
+    entries
+        .iter()
+        .map(|entry| {
+            let row_style = if list_config.use_colors {entry_to_style(entry)}else{""};
+            let cells = list_config.details.iter()
+                .flat_map(|details| details.iter())
+                .map(|d| cell!(entry.get(d).unwrap_or("")).style_spec(row_style))
+                .collect();
+            Row::new(cells)
+        })
+    .collect()
+}
+
 /// Triggered by `list --verbose`, usually you set this in your config under `list/verbose`.
 ///
 /// produces the rows used in `print_projects()`
@@ -184,8 +276,11 @@ pub fn verbose_rows(projects:&[Project], list_config:&ListConfig) -> Vec<Row>{
                 //cell!(project.index().unwrap_or(String::from(""))),
 
                 // R042
-                cell!(project.invoice().number_str().unwrap_or("".into()))
-                    .style_spec(row_style),
+                if project.modified_since_issued() {
+                    invoice_number_cell(project.invoice().number_str().as_ref().map(|s| s.as_str()), true, list_config.use_colors)
+                } else {
+                    cell!(project.invoice().number_str().unwrap_or("".into())).style_spec(row_style)
+                },
 
                 // Date
                 cell!(project.modified_date().unwrap_or(UTC::today()).format("%d.%m.%Y").to_string())
@@ -284,13 +379,18 @@ pub fn print_projects(rows:Vec<Row>){
 }
 
 /// Prints Projects as CSV
-pub fn print_csv_year(year:i32){
-    match ::actions::csv(year) {
+pub fn print_csv_year(year:i32, use_cache:bool){
+    match ::actions::csv(year, use_cache) {
         Ok(csv) => println!("{}", csv),
         Err(err) => println!("{}", err.description())
     }
 }
 
+/// Prints Projects as CSV, from the `IndexCache`
+pub fn print_index_csv(entries:&[IndexEntry]){
+    println!("{}", ::actions::index_to_csv(entries));
+}
+
 /// Prints Projects as CSV
 pub fn print_csv(projects:&[Project]){
     match ::actions::projects_to_csv(projects) {
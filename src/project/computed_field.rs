@@ -41,7 +41,20 @@ custom_derive! {
         Invalid,
 
         Format,
-        Dir
+        Dir,
+
+        /// `"true"` or `"false"`, used by the `IndexCache`
+        Canceled,
+        /// `"true"` if the client has payed
+        Payed,
+        /// `"true"` if an invoice can be produced
+        ReadyForInvoice,
+        /// `"true"` if the invoice changed after it was issued
+        ModifiedSinceIssued,
+        /// Overall cost as raw value, without currency symbol
+        FinalValue,
+        /// Wages as raw value, without currency symbol
+        WagesValue
     }
 }
 
@@ -60,7 +73,7 @@ impl ComputedField {
             ComputedField::OfferNumber       => project.offer().number(),
             ComputedField::InvoiceNumber     => project.invoice().number_str(),
             ComputedField::InvoiceNumberLong => project.invoice().number_long_str(),
-            ComputedField::Name              => Some(project.name().map(ToString::to_string).unwrap()), // TODO remove name() from `Storable`, storables only need a slug()
+            ComputedField::Name              => project.name().map(ToString::to_string), // TODO remove name() from `Storable`, storables only need a slug()
             ComputedField::Final             => project.sum_sold().map(|c| util::currency_to_string(&c)).ok(),
             ComputedField::Age               => project.age().map(|a| format!("{} days", a)),
 
@@ -78,7 +91,14 @@ impl ComputedField {
             ComputedField::Format            => project.format().map(|f|f.to_string()),
             ComputedField::Dir               => project.dir().parent()
                 .and_then(|d| d.strip_prefix(&storage).ok())
-                .map(|d| d.display().to_string())
+                .map(|d| d.display().to_string()),
+
+            ComputedField::Canceled          => Some(project.canceled().to_string()),
+            ComputedField::Payed             => Some(project.payed_by_client().to_string()),
+            ComputedField::ReadyForInvoice   => Some(project.is_ready_for_invoice().is_ok().to_string()),
+            ComputedField::ModifiedSinceIssued => Some(project.modified_since_issued().to_string()),
+            ComputedField::FinalValue        => project.sum_sold().map(|c| c.value().to_string()).ok(),
+            ComputedField::WagesValue        => project.wages().map(|c| c.value().to_string())
 
             // _ => None
         }
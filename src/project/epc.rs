@@ -0,0 +1,174 @@
+//! SEPA payment QR codes after EPC069-12, better known as *GiroCode*.
+//!
+//! Banking apps read name, IBAN, amount and reference from the code, so the client only has to confirm.
+//! Name, IBAN and BIC are those of the `seller` in the config, the amount is the gross total of the invoice
+//! and the reference its number.
+//! Documents get the code as a LaTeX `picture` (no packages needed) and as svg in `payment` of the document JSON.
+
+use qrcode::{QrCode, EcLevel};
+
+use super::Project;
+use super::spec::{Invoicable, Redeemable};
+use super::xrechnung::Seller;
+
+/// One credit transfer to be encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub name: String,
+    pub iban: String,
+    pub bic: String,
+    pub amount: f64,
+    pub reference: String
+}
+
+impl Transfer {
+    /// `None` unless the project has an invoice number and the seller an IBAN.
+    pub fn from_project(project:&Project, seller:&Seller) -> Option<Transfer> {
+        if seller.iban.is_empty() || seller.name.is_empty() {
+            return None;
+        }
+        let reference = try_some!(project.invoice().number_long_str());
+        let (_, invoice) = try_some!(project.bills().ok());
+        Some(Transfer {
+            name: seller.name.to_owned(),
+            iban: seller.iban.replace(' ', ""),
+            bic: seller.bic.replace(' ', ""),
+            amount: invoice.net_total().as_float(),
+            reference: reference
+        })
+    }
+
+    /// The text inside the code, one field per line.
+    pub fn payload(&self) -> String {
+        let name = self.name.chars().take(70).collect::<String>();
+        let amount = format!("EUR{:.2}", self.amount);
+        let reference = self.reference.chars().take(140).collect::<String>();
+        let fields: [&str; 11] = [
+            "BCD",        // service tag
+            "002",        // version, BIC is optional
+            "1",          // UTF-8
+            "SCT",        // SEPA credit transfer
+            &self.bic,
+            &name,
+            &self.iban,
+            &amount,
+            "",           // purpose
+            "",           // structured reference
+            &reference,
+        ];
+        fields.join("\n")
+    }
+
+    /// The modules of the code, row by row, `true` is dark.
+    pub fn modules(&self) -> Option<Modules> {
+        // EPC069-12 asks for error correction level M
+        let code = try_some!(QrCode::with_error_correction_level(self.payload().as_bytes(), EcLevel::M).ok());
+        Some(Modules { width: code.width(), dark: code.to_vec() })
+    }
+}
+
+/// Rendered QR code.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modules {
+    pub width: usize,
+    pub dark: Vec<bool>
+}
+
+/// Empty modules around the code, as the standard requires.
+const QUIET_ZONE: usize = 4;
+
+impl Modules {
+    /// Runs of dark modules per row as `(row, column, length)`.
+    fn runs(&self) -> Vec<(usize, usize, usize)> {
+        let mut runs = Vec::new();
+        for (row, modules) in self.dark.chunks(self.width).enumerate() {
+            let mut column = 0;
+            while column < self.width {
+                if !modules[column] { column += 1; continue }
+                let length = modules[column..].iter().take_while(|&&dark| dark).count();
+                runs.push((row, column, length));
+                column += length;
+            }
+        }
+        runs
+    }
+
+    /// LaTeX `picture` of `size` millimeters, including the quiet zone.
+    pub fn to_tex(&self, size:f64) -> String {
+        let total = self.width + 2 * QUIET_ZONE;
+        let mut tex = format!("{{\\setlength{{\\unitlength}}{{{:.4}mm}}\\begin{{picture}}({},{})\n", size / total as f64, total, total);
+        for (row, column, length) in self.runs() {
+            // the origin of a picture is at the bottom
+            tex.push_str(&format!("\\put({},{}){{\\rule{{{}\\unitlength}}{{1\\unitlength}}}}\n",
+                                  column + QUIET_ZONE, total - 1 - row - QUIET_ZONE, length));
+        }
+        tex.push_str("\\end{picture}}");
+        tex
+    }
+
+    /// Standalone svg of `size` millimeters, including the quiet zone.
+    pub fn to_svg(&self, size:f64) -> String {
+        let total = self.width + 2 * QUIET_ZONE;
+        let path = self.runs().iter()
+            .map(|&(row, column, length)| format!("M{} {}h{}v1h-{}z", column + QUIET_ZONE, row + QUIET_ZONE, length, length))
+            .collect::<String>();
+        format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}mm\" height=\"{size}mm\" viewBox=\"0 0 {total} {total}\" shape-rendering=\"crispEdges\">\
+                 <rect width=\"{total}\" height=\"{total}\" fill=\"#fff\"/><path d=\"{path}\" fill=\"#000\"/></svg>",
+                size = size, total = total, path = path)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::*;
+    use super::super::Project;
+    use super::super::xrechnung::Seller;
+
+    fn transfer() -> Transfer {
+        Transfer {
+            name: String::from("ascii Dresden"),
+            iban: String::from("DE02120300000000202051"),
+            bic: String::from("BYLADEM1001"),
+            amount: 45.2,
+            reference: String::from("R2016-042")
+        }
+    }
+
+    #[test]
+    fn payload(){
+        assert_eq!(transfer().payload(),
+                   "BCD\n002\n1\nSCT\nBYLADEM1001\nascii Dresden\nDE02120300000000202051\nEUR45.20\n\n\nR2016-042");
+    }
+
+    #[test]
+    fn from_project(){
+        let project = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+        let seller = Seller {
+            name: String::from("ascii Dresden"),
+            iban: String::from("DE02 1203 0000 0000 2020 51"),
+            bic: String::from("BYLADEM1001"),
+            .. Seller::default()
+        };
+        assert_eq!(Transfer::from_project(&project, &seller), Some(transfer()));
+        assert_eq!(Transfer::from_project(&project, &Seller::default()), None);
+    }
+
+    #[test]
+    fn rendering(){
+        let modules = transfer().modules().unwrap();
+        assert_eq!(modules.dark.len(), modules.width * modules.width);
+
+        // the finder pattern in the top left corner starts with a run of seven
+        let tex = modules.to_tex(30.0);
+        let total = modules.width + 8;
+        assert!(tex.contains(&format!("\\begin{{picture}}({},{})", total, total)));
+        assert!(tex.contains(&format!("\\put(4,{}){{\\rule{{7\\unitlength}}{{1\\unitlength}}}}", total - 5)));
+        assert!(tex.ends_with("\\end{picture}}"));
+
+        let svg = modules.to_svg(30.0);
+        assert!(svg.starts_with("<svg "));
+        assert!(svg.contains("M4 4h7v1h-7z"));
+    }
+}
@@ -25,6 +25,16 @@ error_chain!{
         CantDetermineTargetFile{
             description("Cannot determine target file name")
         }
+
+        ModifiedSinceIssued(project: String){
+            description("The invoice was modified since it was issued")
+            display("The invoice of {} was modified since it was issued, use --reissue <reason> if that is on purpose", project)
+        }
+
+        EInvoiceIncomplete(project: String, missing: String){
+            description("Mandatory fields of the e-invoice are missing")
+            display("Cannot create an e-invoice for {}, missing: {}", project, missing)
+        }
     }
 }
 
@@ -0,0 +1,110 @@
+//! Semantic differences between two revisions of a project, used by `asciii history`.
+//!
+//! Every revision is reduced to a flat list of facts (`invoice.number`, `Kaffee sold`, `total`, ...),
+//! comparing those lists gives changes a human can read, unlike a textual diff.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::collections::BTreeMap;
+
+use bill::Bill;
+
+use util;
+use super::Project;
+use super::product::Product;
+use super::spec::{IsProject, IsClient, Offerable, Invoicable, Redeemable};
+
+/// Facts about one revision of a project.
+pub type Snapshot = BTreeMap<String, String>;
+
+/// Suffixes of facts that count products.
+const QUANTITIES: [&'static str; 2] = [" sold", " offered"];
+
+/// Something that happened to a project between two revisions.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The first revision.
+    Created,
+    /// The project file was moved, e.g. by archiving.
+    Moved { from: PathBuf, to: PathBuf },
+    /// The revision can't be parsed, so it can't be compared.
+    Unreadable(String),
+    Set { key: String, value: String },
+    Removed { key: String, value: String },
+    Changed { key: String, from: String, to: String }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Change::Created => write!(f, "created"),
+            Change::Moved{ref from, ref to} => write!(f, "moved from {} to {}", from.display(), to.display()),
+            Change::Unreadable(ref reason) => write!(f, "can't be read: {}", reason),
+            Change::Set{ref key, ref value} => write!(f, "{} set to {}", key, value),
+            Change::Removed{ref key, ref value} => write!(f, "{} removed, was {}", key, value),
+            Change::Changed{ref key, ref from, ref to} =>
+                if QUANTITIES.iter().any(|q| key.ends_with(q)) {
+                    write!(f, "{} {} → {}", key, from, to)
+                } else {
+                    write!(f, "{} changed from {} to {}", key, from, to)
+                }
+        }
+    }
+}
+
+fn add_items(snapshot:&mut Snapshot, bill:&Bill<Product>, suffix:&str) {
+    for items in bill.items_by_tax.values() {
+        for item in items {
+            snapshot.insert(format!("{}{}", item.product.name, suffix), item.amount.to_string());
+        }
+    }
+}
+
+/// Reduces a project to the facts worth reporting.
+pub fn snapshot(project:&Project) -> Snapshot {
+    let mut snapshot = Snapshot::new();
+    {
+        let mut insert = |key:&str, value:Option<String>| {
+            if let Some(value) = value {
+                snapshot.insert(key.to_owned(), value);
+            }
+        };
+
+        insert("name",            project.name().map(ToOwned::to_owned));
+        insert("client",          project.client().full_name());
+        insert("responsible",     project.responsible().map(ToOwned::to_owned));
+        insert("offer.number",    project.offer().number());
+        insert("offer.date",      project.offer().date().map(|d| d.format("%d.%m.%Y").to_string()));
+        insert("invoice.number",  project.invoice().number().map(|n| n.to_string()));
+        insert("invoice.date",    project.invoice().date().map(|d| d.format("%d.%m.%Y").to_string()));
+        insert("payed_date",      project.payed_date().map(|d| d.format("%d.%m.%Y").to_string()));
+        insert("canceled",        if project.canceled() { Some("yes".to_owned()) } else { None });
+        insert("wages",           project.wages().map(|c| util::currency_to_string(&c)));
+        insert("total",           project.sum_sold().ok().map(|c| util::currency_to_string(&c)));
+    }
+
+    if let Ok((offer, invoice)) = project.bills() {
+        add_items(&mut snapshot, &offer, QUANTITIES[1]);
+        add_items(&mut snapshot, &invoice, QUANTITIES[0]);
+    }
+    snapshot
+}
+
+/// What changed from `old` to `new`, changed and removed facts first, then new ones.
+pub fn changes(old:&Snapshot, new:&Snapshot) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (key, value) in old {
+        match new.get(key) {
+            None => changes.push(Change::Removed{ key: key.to_owned(), value: value.to_owned() }),
+            Some(new_value) if new_value != value =>
+                changes.push(Change::Changed{ key: key.to_owned(), from: value.to_owned(), to: new_value.to_owned() }),
+            _ => {}
+        }
+    }
+    for (key, value) in new {
+        if !old.contains_key(key) {
+            changes.push(Change::Set{ key: key.to_owned(), value: value.to_owned() });
+        }
+    }
+    changes
+}
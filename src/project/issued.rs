@@ -0,0 +1,197 @@
+//! Fingerprints of issued invoices.
+//!
+//! Once `asciii make --invoice` has produced an invoice, a fingerprint of everything printed on it
+//! (billed items, totals and client) is recorded in the `issued` section of the project file.
+//! Projects whose current data no longer matches that fingerprint show up in `asciii list` and `asciii fsck`,
+//! and making the invoice again requires `--reissue <reason>`, which keeps the old fingerprint and the reason.
+//!
+//! ```yaml
+//! issued:
+//!   fingerprint: 9f86d081884c7d65…
+//!   date: 19.10.2016
+//!   reissued:
+//!     - date: 24.10.2016
+//!       reason: "wrong price for coffee"
+//!       fingerprint: 60303ae22b998861…
+//! ```
+
+use std::fmt::Write as FmtWrite;
+use std::fs::File;
+use std::io::Write;
+
+use chrono::*;
+use sha2::{Sha256, Digest};
+
+use util::yaml;
+use storage::Storable;
+use super::Project;
+use super::spec::{IsClient, Invoicable, Redeemable};
+use super::error::{ErrorKind, Result};
+
+/// One earlier fingerprint that was replaced by `--reissue`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reissue {
+    pub date: String,
+    pub reason: String,
+    pub fingerprint: String
+}
+
+/// Content of the `issued` section.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Issued {
+    pub fingerprint: String,
+    pub date: String,
+    pub reissued: Vec<Reissue>
+}
+
+impl Issued {
+    /// Reads the `issued` section, `None` if the invoice was never issued.
+    pub fn from_project(project:&Project) -> Option<Issued> {
+        let yaml = project.yaml();
+        let fingerprint = try_some!(yaml::get_string(yaml, "issued/fingerprint"));
+        let reissued = yaml::get(yaml, "issued/reissued")
+            .and_then(|r| r.as_vec())
+            .map(|entries| entries.iter()
+                 .map(|entry| Reissue {
+                     date:        yaml::get_to_string(entry, "date").unwrap_or_else(String::new),
+                     reason:      yaml::get_to_string(entry, "reason").unwrap_or_else(String::new),
+                     fingerprint: yaml::get_to_string(entry, "fingerprint").unwrap_or_else(String::new),
+                 })
+                 .collect())
+            .unwrap_or_else(Vec::new);
+
+        Some(Issued {
+            fingerprint: fingerprint,
+            date: yaml::get_to_string(yaml, "issued/date").unwrap_or_else(String::new),
+            reissued: reissued
+        })
+    }
+
+    fn to_yaml(&self) -> String {
+        let mut section = format!("issued:\n  fingerprint: {}\n  date: {}\n", self.fingerprint, self.date);
+        if !self.reissued.is_empty() {
+            section.push_str("  reissued:\n");
+            for reissue in &self.reissued {
+                section.push_str(&format!("    - date: {}\n      reason: {}\n      fingerprint: {}\n",
+                                          reissue.date, yaml::quote(&reissue.reason), reissue.fingerprint));
+            }
+        }
+        section
+    }
+}
+
+/// Hash over billed items, totals and client of the invoice.
+pub fn fingerprint(project:&Project) -> Result<String> {
+    let (_, invoice) = project.bills()?;
+    let mut facts = String::new();
+
+    for (tax, items) in &invoice.items_by_tax {
+        for item in items {
+            writeln!(&mut facts, "item\t{}\t{}\t{:.2}\t{:.4}",
+                     item.product.name, item.amount, item.product.price.as_float(), tax.into_inner())?;
+        }
+    }
+    writeln!(&mut facts, "net\t{:.2}", invoice.net_total().as_float())?;
+    writeln!(&mut facts, "gross\t{:.2}", invoice.gross_total().as_float())?;
+
+    let client = project.client();
+    writeln!(&mut facts, "client\t{}\t{}\t{}",
+             client.full_name().unwrap_or_else(String::new),
+             client.addressing().unwrap_or_else(String::new),
+             client.address().unwrap_or("").replace('\n', " "))?;
+    writeln!(&mut facts, "invoice\t{}", project.invoice().number_str().unwrap_or_else(String::new))?;
+
+    let mut hasher = Sha256::default();
+    hasher.input(facts.as_bytes());
+    Ok(hasher.result().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Whether the invoice was issued and its data changed since.
+pub fn modified_since_issued(project:&Project) -> bool {
+    match (Issued::from_project(project), fingerprint(project)) {
+        (Some(issued), Ok(current)) => issued.fingerprint != current,
+        (Some(_), Err(_)) => true,
+        (None, _) => false
+    }
+}
+
+/// Appends `section` to the document, in front of a closing `...` if there is one.
+fn append_section(content:&str, section:&str) -> String {
+    let trimmed = content.trim_right();
+    let (mut appended, end) = if trimmed == "..." || trimmed.ends_with("\n...") {
+        (trimmed[..trimmed.len() - 3].to_owned(), "...\n")
+    } else {
+        (content.to_owned(), "")
+    };
+    if !appended.ends_with("\n\n") { appended.push('\n'); }
+    appended.push_str(section);
+    if !end.is_empty() {
+        if !appended.ends_with('\n') { appended.push('\n'); }
+        appended.push('\n');
+        appended.push_str(end);
+    }
+    appended
+}
+
+/// Writes the current fingerprint into the project file.
+///
+/// Replaces a different one only if there is a `reason`, which is recorded together with the old fingerprint.
+/// Returns `false` if there was nothing to record.
+pub fn record(project:&Project, reason:Option<&str>) -> Result<bool> {
+    let current = fingerprint(project)?;
+    let today = Local::today().format("%d.%m.%Y").to_string();
+
+    let issued = match (Issued::from_project(project), reason) {
+        (None, _) => Issued { fingerprint: current, date: today, reissued: Vec::new() },
+        (Some(ref issued), None) if issued.fingerprint == current => return Ok(false),
+        (Some(_), None) => return Err(ErrorKind::ModifiedSinceIssued(project.short_desc()).into()),
+        (Some(mut issued), Some(reason)) => {
+            let previous = ::std::mem::replace(&mut issued.fingerprint, current);
+            issued.reissued.push(Reissue { date: today.to_owned(), reason: reason.to_owned(), fingerprint: previous });
+            issued.date = today;
+            issued
+        }
+    };
+
+    let content = yaml::replace_values(&project.file_content, &[("issued", None)]);
+    let content = append_section(&content, &issued.to_yaml());
+
+    // don't write anything broken
+    yaml::parse(&content)?;
+    let mut file = File::create(project.file())?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use util::yaml;
+
+    #[test]
+    fn reason_survives_yaml(){
+        let reason = "Kunde meint \"zu teuer\": Rabatt für Müller\\Söhne";
+        let issued = Issued {
+            fingerprint: String::from("60303ae22b998861"),
+            date: String::from("24.10.2016"),
+            reissued: vec![Reissue {
+                date: String::from("24.10.2016"),
+                reason: reason.to_owned(),
+                fingerprint: String::from("9f86d081884c7d65")
+            }]
+        };
+        let parsed = yaml::parse(&issued.to_yaml()).unwrap();
+        assert_eq!(yaml::get_to_string(&parsed, "issued/reissued/0/reason"), Some(reason.to_owned()));
+    }
+
+    #[test]
+    fn section_before_document_end(){
+        let appended = append_section("name: foo\n\n...\n", "issued:\n  date: 24.10.2016\n");
+        assert_eq!(appended, "name: foo\n\nissued:\n  date: 24.10.2016\n\n...\n");
+        assert!(yaml::parse(&appended).is_ok());
+
+        let appended = append_section("name: foo\n", "issued:\n  date: 24.10.2016\n");
+        assert_eq!(appended, "name: foo\n\nissued:\n  date: 24.10.2016\n");
+    }
+}
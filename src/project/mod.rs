@@ -16,7 +16,7 @@ use tempdir::TempDir;
 use slug;
 
 use bill::{Bill, Currency};
-//use semver::Version;
+use semver::Version;
 
 use super::BillType;
 use util;
@@ -39,6 +39,11 @@ pub mod product;
 pub mod spec;
 
 pub mod error;
+pub mod history;
+pub mod issued;
+pub mod xrechnung;
+#[cfg(feature="document_export")]
+pub mod epc;
 mod computed_field;
 
 #[cfg(feature="document_export")]
@@ -97,6 +102,19 @@ impl Project {
         )
     }
 
+    /// Parses a project from content that does not have to be on disk, e.g. an old revision.
+    ///
+    /// `file_path` is only used to locate the project.
+    pub fn from_content(file_path:&Path, file_content:String) -> StorageResult<Project> {
+        Ok(Project{
+            file_path: file_path.to_owned(),
+            _temp_dir: None,
+            git_status: None,
+            yaml: yaml::parse(&file_content)?,
+            file_content: file_content,
+        })
+    }
+
     /// either `"canceled"` or `""`
     pub fn canceled_string(&self) -> &'static str{
         if self.canceled(){"canceled"}
@@ -336,6 +354,100 @@ impl Project {
         }
     }
 
+    /// Records the payment of the invoice in the project file.
+    ///
+    /// Sets `invoice/payed_date`, adding it below `invoice:` if the file has no such line yet.
+    pub fn set_payed_date(&self, date:Date<UTC>) -> Result<()> {
+        let date = date.format("%d.%m.%Y").to_string();
+        let replaced = yaml::replace_values(&self.file_content, &[("invoice/payed_date", Some(date.to_owned()))]);
+
+        let content = if replaced.lines().any(|l| l.trim_left().starts_with("payed_date:") && l.contains(date.as_str())) {
+            replaced
+        } else {
+            let mut lines = self.file_content.lines().map(ToOwned::to_owned).collect::<Vec<_>>();
+            let invoice = lines.iter()
+                               .position(|l| l.trim_right() == "invoice:")
+                               .ok_or("project has no invoice section")?;
+            lines.insert(invoice + 1, format!("  payed_date: {}", date));
+            lines.join("\n") + "\n"
+        };
+
+        yaml::parse(&content)?;
+        let mut file = File::create(self.file())?;
+        file.write_all(content.as_bytes())?;
+        file.sync_all()?;
+        Ok(())
+    }
+
+    /// Values every freshly created project is filled with.
+    fn default_fill(project_name:&str, template_name:&str) -> HashMap<&'static str, String> {
+        let event_date = (Local::today() + Duration::days(14)).format("%d.%m.%Y").to_string();
+        let created_date = Local::today().format("%d.%m.%Y").to_string();
+
+        hashmap!{
+            "TEMPLATE"      => template_name.to_owned(),
+            "PROJECT-NAME"  => project_name.to_owned(),
+            "DATE-EVENT"    => event_date,
+            "DATE-CREATED"  => created_date,
+            "TAX"           => ::CONFIG.get_to_string("defaults/tax")
+                .expect("Faulty config: field defaults/tax does not contain a value"),
+            "SALARY"        => ::CONFIG.get_to_string("defaults/salary")
+                .expect("Faulty config: field defaults/salary does not contain a value"),
+            "MANAGER"       => ::CONFIG.get_str("user/name").unwrap_or("").to_string(),
+            "DATE-END"      => String::new(),
+            "TIME-START"    => String::new(),
+            "TIME-END"      => String::new(),
+            "MORE-DATES"    => String::new(),
+            "LOCATION"      => String::new(),
+            "CLIENT-TITLE"      => String::new(),
+            "CLIENT-FIRST-NAME" => String::new(),
+            "CLIENT-LAST-NAME"  => String::new(),
+            "CLIENT-EMAIL"      => String::new(),
+            "CLIENT-ADDRESS"    => String::from("Nöthnitzerstraße 46\n    01187 Dresden"),
+            "VERSION"       => ::VERSION.to_string(),
+        }
+    }
+
+    /// Fills a template first with `fill` then with `default_fill` and stores the result in a tempfile.
+    fn from_filled_template(project_name:&str, mut template:Templater, fill:&HashMap<&str,String>, default_fill:&HashMap<&str,String>) -> StorageResult<Project> {
+        // fills the template
+        let filled = template
+            .fill_in_data(fill).fix()
+            .fill_in_data(default_fill)
+            .finalize()
+            .filled;
+
+        debug!("remaining template fields: {:#?}", filled.list_keywords());
+
+        // generates a temp file
+        let temp_dir  = TempDir::new(project_name).unwrap();
+        let temp_file = temp_dir.path().join(slug::slugify(project_name) + "." + Self::file_extension());
+
+        // write into a file
+        let mut file = File::create(&temp_file)?;
+        file.write_all(filled.as_bytes())?;
+        file.sync_all()?;
+
+        let yaml = match yaml::parse(&filled){
+            Ok(y) => y,
+            Err(e) => {
+                error!("The created document is no valid yaml. SORRY!\n{}\n\n{}",
+                       filled.lines().enumerate().map(|(n,l)| format!("{:>3}. {}\n",n,l)).collect::<String>(), //line numbers :D
+                       e.description());
+                return Err(e.into())
+            }
+        };
+
+        // project now lives in the temp_file
+        Ok(Project{
+            file_path: temp_file,
+            _temp_dir: Some(temp_dir),
+            git_status: None,
+            file_content: filled,
+            yaml: yaml
+        })
+    }
+
     pub fn our_bad(&self) -> Option<Duration> {
         let event   = try_some!(self.event_date());
         let invoice = self.invoice().date().unwrap_or_else(UTC::today);
@@ -428,62 +540,54 @@ impl Storable for Project {
     fn file_extension() -> &'static str {PROJECT_FILE_EXTENSION}
     fn from_template(project_name:&str,template:&Path, fill: &HashMap<&str,String>) -> StorageResult<Project> {
         let template_name = template.file_stem().unwrap().to_str().unwrap();
+        let template = Templater::from_file(template)?;
+        Self::from_filled_template(project_name, template, fill, &Self::default_fill(project_name, template_name))
+    }
 
-        let event_date = (Local::today() + Duration::days(14)).format("%d.%m.%Y").to_string();
-        let created_date = Local::today().format("%d.%m.%Y").to_string();
-
-        // fill template with these values
-        let default_fill = hashmap!{
-            "TEMPLATE"      => template_name.to_owned(),
-            "PROJECT-NAME"  => project_name.to_owned(),
-            "DATE-EVENT"    => event_date,
-            "DATE-CREATED"  => created_date,
-            "TAX"           => ::CONFIG.get_to_string("defaults/tax")
-                .expect("Faulty config: field defaults/tax does not contain a value"),
-            "SALARY"        => ::CONFIG.get_to_string("defaults/salary")
-                .expect("Faulty config: field defaults/salary does not contain a value"),
-            "MANAGER"       => ::CONFIG.get_str("user/name").unwrap_or("").to_string(),
-            "TIME-START"    => String::new(),
-            "TIME-END"      => String::new(),
-            "VERSION"       => ::VERSION.to_string(),
-        };
-
-        // fills the template
-        let filled = Templater::from_file(template)?
-            .fill_in_data(&fill).fix()
-            .fill_in_data(&default_fill)
-            .finalize()
-            .filled;
+    fn from_existing(project_name:&str, source:&Project, fill: &HashMap<&str,String>) -> StorageResult<Project> {
+        if source.format().map_or(true, |format| format < Version::parse("3.0.0").unwrap()) {
+            warn!("{:?} uses an old format, not everything might be reset", source.short_desc());
+        }
 
-        debug!("remaining template fields: {:#?}", filled.list_keywords());
+        let s = |s:&str| Some(String::from(s));
+        let mut replacements = vec![
+            ("event/name",            s("##PROJECT-NAME##")),
+            ("event/dates/begin",     s("##DATE-EVENT##")),
+            ("event/dates/end",       None),
+            ("offer/date",            s("##DATE-CREATED##")),
+            ("offer/appendix",        s("1")),
+            ("invoice/number",        s("##INVOICE-NUMBER##")),
+            ("invoice/date",          s("##INVOICE-DATE##")),
+            ("invoice/payed_date",    s("##PAYED-DATE##")),
+            ("invoice/official",      s("")),
+            ("products/*/sold",       None),
+            ("products/*/returned",   None),
+            ("hours/wages_date",      s("")),
+            ("canceled",              None),
+            ("created",               s("##DATE-CREATED##")),
+            ("meta/invoicer_version", s("##VERSION##")),
+            // the copy has not been issued
+            ("issued",                None),
+        ];
+        if fill.contains_key("DESCRIPTION") {
+            replacements.push(("event/description", s("|\n    ##DESCRIPTION##")));
+        }
+        let reset = yaml::replace_values(&source.file_content, &replacements);
 
-        // generates a temp file
-        let temp_dir  = TempDir::new(project_name).unwrap();
-        let temp_file = temp_dir.path().join(slug::slugify(project_name) + "." + Self::file_extension());
+        let template_name = source.get_str("meta.template").unwrap_or("").to_owned();
+        let default_fill = Self::default_fill(project_name, &template_name);
 
-        // write into a file
-        let mut file = File::create(&temp_file)?;
-        file.write_all(filled.as_bytes())?;
-        file.sync_all()?;
+        let project = Self::from_filled_template(project_name, Templater::new(&reset), fill, &default_fill)?;
 
-        let yaml = match yaml::parse(&filled){
-            Ok(y) => y,
-            Err(e) => {
-                error!("The created document is no valid yaml. SORRY!\n{}\n\n{}",
-                       filled.lines().enumerate().map(|(n,l)| format!("{:>3}. {}\n",n,l)).collect::<String>(), //line numbers :D
-                       e.description());
-                return Err(e.into())
-            }
-        };
+        // the copy must be as good as a freshly created project
+        if let Err(errors) = project.validate() {
+            return Err(StorageErrorKind::InvalidProject(errors.join(", ")).into());
+        }
+        if let Err(error) = project.bills() {
+            return Err(StorageErrorKind::InvalidProject(error.to_string()).into());
+        }
 
-        // project now lives in the temp_file
-        Ok(Project{
-            file_path: temp_file,
-            _temp_dir: Some(temp_dir),
-            git_status: None,
-            file_content: filled,
-            yaml: yaml
-        })
+        Ok(project)
     }
 
     fn prefix(&self) -> Option<String>{
@@ -523,6 +627,29 @@ impl Storable for Project {
 
     }
 
+    /// Like `get()`, additionally provides the events of this project as `"Ical"`.
+    fn field(&self, key:&str) -> Option<String> {
+        if key == "Ical" {
+            let calendar = self.to_ical().to_string();
+            let events = calendar.lines()
+                .skip_while(|line| !line.starts_with("BEGIN:VEVENT"))
+                .collect::<Vec<_>>();
+            let end = events.iter().rposition(|line| line.starts_with("END:VEVENT")).map_or(0, |i| i + 1);
+            Some(events[..end].iter().map(|line| format!("{}\r\n", line)).collect())
+        } else {
+            self.get(key)
+        }
+    }
+
+    fn template_names(&self) -> Vec<String> {
+        // `asciii make` renders with the document template unless told otherwise
+        let mut names = vec![String::from("document")];
+        if let Some(template) = yaml::get_str(self.yaml(), "meta/template") {
+            names.insert(0, template.to_owned());
+        }
+        names
+    }
+
     fn file(&self) -> PathBuf{ self.file_path.to_owned() } // TODO reconsider returning PathBuf at all
     fn set_file(&mut self, new_file:&Path){ self.file_path = new_file.to_owned(); }
 
@@ -555,13 +682,12 @@ impl Storable for Project {
                                     let mut content = String::new();
                                     file.read_to_string(&mut content).map(|_| content)
                                 })?;
-        Ok(Project{
-            file_path: file_path.to_owned(),
-            _temp_dir: None,
-            git_status: None,
-            yaml: yaml::parse(&file_content)?,
-            file_content: file_content,
-        })
+        Project::from_content(file_path, file_content)
+    }
+
+    /// Compares the fingerprint recorded by `make --invoice`, see [`issued`](issued/index.html).
+    fn modified_since_issued(&self) -> bool {
+        issued::modified_since_issued(self)
     }
 
     /// Checks against a certain key-val pair.
@@ -751,6 +877,7 @@ mod test {
     use ::project::spec::*;
     use ::project::Project;
     use ::storage::Storable;
+    use ::util::yaml;
 
     #[test]
     fn compare_basics(){
@@ -789,4 +916,149 @@ mod test {
         assert_eq!(old_project.client().address(),
                    new_project.client().address());
     }
+
+    #[test]
+    fn from_existing(){
+        let original = Project::open_file(Path::new("./tests/current.yml")).unwrap();
+        let copy = Project::from_existing("Party Harder", &original, &hashmap!{
+            "DATE-EVENT" => String::from("01.05.2017")
+        }).unwrap();
+
+        assert_eq!(copy.name(), Some("Party Harder"));
+        assert_eq!(copy.event_date(), yaml::parse_dmy_date("01.05.2017"));
+        assert_eq!(copy.invoice().number(), None);
+        assert_eq!(copy.payed_date(), None);
+        assert_eq!(copy.hours().wages_date(), None);
+        assert!(copy.empty_fields().contains(&String::from("INVOICE-NUMBER")));
+
+        // the client and products stay the same
+        assert_eq!(original.client().full_name(), copy.client().full_name());
+        assert_eq!(original.client().address(), copy.client().address());
+        assert_eq!(original.responsible(), copy.responsible());
+        assert_eq!(original.hours().employees_string(), copy.hours().employees_string());
+
+        // but nothing has been sold yet
+        let (original_offer, _) = original.bills().unwrap();
+        let (offer, invoice) = copy.bills().unwrap();
+        assert_eq!(original_offer.net_total(), offer.net_total());
+        assert_eq!(offer.net_total(), invoice.net_total());
+    }
+
+    #[test]
+    fn from_existing_issued(){
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use tempdir::TempDir;
+        use ::project::issued::{self, Issued};
+
+        let mut content = String::new();
+        File::open("./tests/current.yml").unwrap().read_to_string(&mut content).unwrap();
+        let dir = TempDir::new("issued_copy").unwrap();
+        let path = dir.path().join("current.yml");
+        File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+        assert!(issued::record(&Project::open_file(&path).unwrap(), None).unwrap());
+
+        let original = Project::open_file(&path).unwrap();
+        assert!(Issued::from_project(&original).is_some());
+        let copy = Project::from_existing("Party Harder", &original, &::std::collections::HashMap::new()).unwrap();
+
+        // the copy can be made like a fresh project
+        assert!(Issued::from_project(&copy).is_none());
+        assert!(!copy.modified_since_issued());
+        assert!(issued::record(&copy, None).unwrap());
+    }
+
+    #[test]
+    fn semantic_history(){
+        use std::fs::File;
+        use std::io::Read;
+        use ::project::history::{self, Change};
+
+        let path = Path::new("./tests/current.yml");
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+        let changed_content = content.replace("*kaffee: { amount: 5 }", "*kaffee: { amount: 7 }");
+        assert!(changed_content != content);
+
+        let old = history::snapshot(&Project::from_content(path, content).unwrap());
+        let new = history::snapshot(&Project::from_content(path, changed_content).unwrap());
+        assert!(history::changes(&old, &old).is_empty());
+
+        let changes = history::changes(&old, &new);
+        assert!(changes.iter().any(|c| match *c {
+            Change::Changed{ref key, ref from, ref to} => key.ends_with(" sold") && from == "5" && to == "7",
+            _ => false
+        }));
+        assert!(changes.iter().any(|c| match *c {
+            Change::Changed{ref key, ..} => key == "total",
+            _ => false
+        }));
+    }
+
+    #[test]
+    fn issued_fingerprint(){
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use tempdir::TempDir;
+        use ::project::issued::{self, Issued};
+
+        let mut content = String::new();
+        File::open("./tests/current.yml").unwrap().read_to_string(&mut content).unwrap();
+        let dir = TempDir::new("issued").unwrap();
+        let path = dir.path().join("current.yml");
+        File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+
+        let project = Project::open_file(&path).unwrap();
+        assert!(Issued::from_project(&project).is_none());
+        assert!(!project.modified_since_issued());
+        assert!(issued::record(&project, None).unwrap());
+
+        // current.yml ends with `...`, the section has to go in front of it
+        let mut recorded = String::new();
+        File::open(&path).unwrap().read_to_string(&mut recorded).unwrap();
+        assert!(recorded.ends_with("\n...\n"));
+
+        let project = Project::open_file(&path).unwrap();
+        assert_eq!(Issued::from_project(&project).unwrap().fingerprint, issued::fingerprint(&project).unwrap());
+        assert!(!project.modified_since_issued());
+        assert!(!issued::record(&project, None).unwrap());
+
+        let mut issued_content = String::new();
+        File::open(&path).unwrap().read_to_string(&mut issued_content).unwrap();
+        let changed = issued_content.replace("*kaffee: { amount: 5 }", "*kaffee: { amount: 7 }");
+        File::create(&path).unwrap().write_all(changed.as_bytes()).unwrap();
+
+        let project = Project::open_file(&path).unwrap();
+        assert!(project.modified_since_issued());
+        assert!(issued::record(&project, None).is_err());
+        assert!(issued::record(&project, Some("more coffee")).unwrap());
+
+        let project = Project::open_file(&path).unwrap();
+        assert!(!project.modified_since_issued());
+        let reissued = Issued::from_project(&project).unwrap().reissued;
+        assert_eq!(reissued.len(), 1);
+        assert_eq!(reissued[0].reason, "more coffee");
+    }
+
+    #[test]
+    fn set_payed_date(){
+        use std::fs::File;
+        use std::io::{Read, Write};
+        use chrono::*;
+        use tempdir::TempDir;
+
+        let dir = TempDir::new("payed").unwrap();
+        // xrechnung.yml has no payed_date yet, current.yml has one
+        for name in &["xrechnung.yml", "current.yml"] {
+            let mut content = String::new();
+            File::open(Path::new("./tests").join(name)).unwrap().read_to_string(&mut content).unwrap();
+            let path = dir.path().join(name);
+            File::create(&path).unwrap().write_all(content.as_bytes()).unwrap();
+
+            let project = Project::open_file(&path).unwrap();
+            project.set_payed_date(UTC.ymd(2016, 12, 20)).unwrap();
+            let project = Project::open_file(&path).unwrap();
+            assert_eq!(project.payed_date(), Some(UTC.ymd(2016, 12, 20)));
+        }
+    }
 }
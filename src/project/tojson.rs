@@ -8,6 +8,8 @@ use std::error::Error;
 
 use super::Project;
 use super::product::Product;
+use super::epc::Transfer;
+use super::xrechnung::Seller;
 use util::currency_to_string;
 
 fn opt_to_json<T: ::std::fmt::Display>(opt:Option<T>) -> Json{
@@ -25,12 +27,40 @@ fn itemlist_to_json(tax:&Tax, list: &ItemList<Product>) -> Json {
     let map = btreemap!{
         s("tax_value")   => (tax.into_inner()*100.0).to_json(),
         s("gross_sum")   => currency_to_string(&gross_sum).to_json(),
+        s("gross_sum_value") => gross_sum.as_float().to_json(),
         s("tax_sum") => currency_to_string(&tax_sum).to_json(),
+        s("tax_sum_value") => tax_sum.as_float().to_json(),
         s("has_tax")  => (tax.into_inner() > 0f64).to_json()
     };
     map.to_json()
 }
 
+/// Size of the payment QR code in mm.
+const QR_SIZE: f64 = 30.0;
+
+impl Project {
+    /// Bank details and GiroCode, `null` if the config has no `seller/iban`.
+    ///
+    /// Not part of `to_json()`, drawing the QR code is only worth it for invoices.
+    pub fn payment_to_json(&self) -> Json {
+        let transfer = match Transfer::from_project(self, &Seller::from_config()) {
+            Some(transfer) => transfer,
+            None => return Json::Null
+        };
+        let modules = transfer.modules();
+        btreemap!{
+            s("name")      => transfer.name.to_json(),
+            s("iban")      => transfer.iban.to_json(),
+            s("bic")       => transfer.bic.to_json(),
+            s("amount")    => transfer.amount.to_json(),
+            s("reference") => transfer.reference.to_json(),
+            s("epc")       => transfer.payload().to_json(),
+            s("qr_tex")    => modules.as_ref().map(|m| m.to_tex(QR_SIZE)).to_json(),
+            s("qr_svg")    => modules.as_ref().map(|m| m.to_svg(QR_SIZE)).to_json(),
+        }.to_json()
+    }
+}
+
 fn taxes_by_tax_to_json(bill: &Bill<Product>) -> Json {
     bill.iter()
         .map(|(tax, list)| { itemlist_to_json(tax, list) })
@@ -51,9 +81,11 @@ impl ToJson for Project{
         let item_to_json = |item:&BillItem<Product>, tax:OrderedFloat<f64>| btreemap!{
             s("name") => item.product.name.to_json(),
             s("price") => currency_to_string(&item.product.price).to_json(),
+            s("price_value") => item.product.price.as_float().to_json(),
             s("unit") => item.product.unit.unwrap_or_else(||"").to_json(),
             s("amount") => item.amount.to_json(),
             s("cost") => currency_to_string(&item.gross()).to_json(),
+            s("cost_value") => item.gross().as_float().to_json(),
             s("tax") => tax.into_inner().to_json()
         }.to_json();
 
@@ -104,6 +136,8 @@ impl ToJson for Project{
                 s("sums")         => taxes_by_tax_to_json(&offer),
                 s("net_total")    => currency_to_string(&offer.net_total()).to_json(),
                 s("gross_total")  => currency_to_string(&offer.gross_total()).to_json(),
+                s("net_total_value")   => offer.net_total().as_float().to_json(),
+                s("gross_total_value") => offer.gross_total().as_float().to_json(),
             }.to_json(),
 
             s("invoice") => btreemap!{
@@ -114,6 +148,8 @@ impl ToJson for Project{
                 s("sums")        => taxes_by_tax_to_json(&invoice),
                 s("net_total")   => currency_to_string(&invoice.net_total()).to_json(),
                 s("gross_total") => currency_to_string(&invoice.gross_total()).to_json(),
+                s("net_total_value")   => invoice.net_total().as_float().to_json(),
+                s("gross_total_value") => invoice.gross_total().as_float().to_json(),
             }.to_json(),
 
             s("hours") => btreemap!{
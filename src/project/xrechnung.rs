@@ -0,0 +1,393 @@
+//! Structured e-invoices after EN 16931, in the UBL syntax of the german XRechnung.
+//!
+//! Everything about the buyer comes from the project, including the `client/buyer_reference`
+//! (the *Leitweg-ID* of public-sector clients). Everything about the seller comes from the `seller`
+//! section of the config. `validate()` lists what is missing before anything is written.
+
+use std::fmt::Write;
+
+use chrono::*;
+use bill::Currency;
+
+use util::yaml;
+use super::Project;
+use super::spec::{IsClient, IsProject, Invoicable};
+use super::error::{ErrorList, SpecResult, Result};
+
+const CUSTOMIZATION_ID: &'static str = "urn:cen.eu:en16931:2017#compliant#urn:xeinkauf.de:kosit:xrechnung_3.0";
+const PROFILE_ID: &'static str = "urn:fdc:peppol.eu:2017:poacc:billing:01:1.0";
+
+/// The party that issues the invoice, usually read from the `seller` section of the config.
+#[derive(Debug, Clone, Default)]
+pub struct Seller {
+    pub name: String,
+    pub street: String,
+    pub zip: String,
+    pub city: String,
+    /// ISO 3166-1 code, `DE` if empty
+    pub country: String,
+    /// Umsatzsteuer-Identifikationsnummer, e.g. `DE123456789`
+    pub vat_id: String,
+    /// Steuernummer, only used if there is no `vat_id`
+    pub tax_number: String,
+    pub contact: String,
+    pub email: String,
+    pub phone: String,
+    pub iban: String,
+    pub bic: String
+}
+
+impl Seller {
+    pub fn from_config() -> Seller {
+        let get = |key:&str| ::CONFIG.get_to_string(&format!("seller/{}", key)).unwrap_or_else(String::new);
+        Seller {
+            name:       get("name"),
+            street:     get("street"),
+            zip:        get("zip"),
+            city:       get("city"),
+            country:    get("country"),
+            vat_id:     get("vat_id"),
+            tax_number: get("tax_number"),
+            contact:    get("contact"),
+            email:      get("email"),
+            phone:      get("phone"),
+            iban:       get("iban"),
+            bic:        get("bic"),
+        }
+    }
+
+    fn country(&self) -> &str {
+        if self.country.is_empty() { "DE" } else { &self.country }
+    }
+}
+
+/// Street, zip code and city of an address like
+///
+/// ```text
+/// Graf Zahl
+/// Nummernhöllenstraße 666
+/// 01234 Countilvania
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostalAddress {
+    pub street: Option<String>,
+    pub zip: String,
+    pub city: String
+}
+
+impl PostalAddress {
+    /// The last line that starts with a zip code decides, the line above it is the street.
+    pub fn parse(address:&str) -> Option<PostalAddress> {
+        let lines = address.lines().map(str::trim).filter(|l| !l.is_empty()).collect::<Vec<_>>();
+        let index = try_some!(lines.iter().rposition(|line| line.chars().next().map_or(false, |c| c.is_digit(10))));
+        let mut parts = lines[index].splitn(2, ' ');
+        let zip = try_some!(parts.next()).to_owned();
+        let city = try_some!(parts.next()).trim().to_owned();
+        let street = if index > 0 { Some(lines[index - 1].to_owned()) } else { None };
+        Some(PostalAddress { street: street, zip: zip, city: city })
+    }
+}
+
+fn buyer_reference(project:&Project) -> Option<String> {
+    yaml::get_to_string(project.yaml(), "client/buyer_reference")
+}
+
+fn buyer_country(project:&Project) -> Option<String> {
+    yaml::get_to_string(project.yaml(), "client/country")
+}
+
+/// Checks the fields EN 16931 and XRechnung insist on.
+pub fn validate(project:&Project, seller:&Seller) -> SpecResult {
+    let mut errors = ErrorList::new();
+    {
+        let mut require = |present:bool, what:&str| if !present { errors.push(what) };
+
+        require(!seller.name.is_empty(),   "seller/name");
+        require(!seller.street.is_empty(), "seller/street");
+        require(!seller.zip.is_empty(),    "seller/zip");
+        require(!seller.city.is_empty(),   "seller/city");
+        require(!seller.vat_id.is_empty() || !seller.tax_number.is_empty(), "seller/vat_id or seller/tax_number");
+        require(!seller.contact.is_empty(), "seller/contact");
+        require(!seller.email.is_empty(),   "seller/email");
+        require(!seller.phone.is_empty(),   "seller/phone");
+        require(!seller.iban.is_empty(),    "seller/iban");
+
+        require(project.invoice().number_long_str().is_some(), "invoice/number");
+        require(project.invoice().date().is_some(),            "invoice/date");
+        require(buyer_reference(project).map_or(false, |r| !r.is_empty()), "client/buyer_reference");
+        require(project.client().full_name().is_some(),        "client/first_name, client/last_name");
+        require(project.client().email().is_some(),            "client/email");
+        require(buyer_country(project).map_or(false, |c| !c.is_empty()), "client/country");
+        require(project.client().address().and_then(PostalAddress::parse).is_some(),
+                "client/address (with a line like \"01234 City\")");
+    }
+
+    if errors.is_empty() { Ok(()) } else { Err(errors) }
+}
+
+fn escape_xml(text:&str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn amount(value:&Currency) -> String {
+    format!("{:.2}", value.as_float())
+}
+
+fn iso_date(date:Option<Date<UTC>>) -> String {
+    date.map(|d| d.format("%Y-%m-%d").to_string()).unwrap_or_else(String::new)
+}
+
+/// `S` for standard rated, `Z` for zero rated items.
+fn tax_category(tax:f64) -> &'static str {
+    if tax > 0.0 { "S" } else { "Z" }
+}
+
+fn write_tax_category(xml:&mut String, element:&str, tax:f64) -> Result<()> {
+    writeln!(xml, "    <cac:{}>", element)?;
+    writeln!(xml, "      <cbc:ID>{}</cbc:ID>", tax_category(tax))?;
+    writeln!(xml, "      <cbc:Percent>{:.2}</cbc:Percent>", tax * 100.0)?;
+    writeln!(xml, "      <cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme>")?;
+    writeln!(xml, "    </cac:{}>", element)?;
+    Ok(())
+}
+
+fn write_address(xml:&mut String, street:Option<&str>, zip:&str, city:&str, country:&str) -> Result<()> {
+    writeln!(xml, "      <cac:PostalAddress>")?;
+    if let Some(street) = street {
+        writeln!(xml, "        <cbc:StreetName>{}</cbc:StreetName>", escape_xml(street))?;
+    }
+    writeln!(xml, "        <cbc:CityName>{}</cbc:CityName>", escape_xml(city))?;
+    writeln!(xml, "        <cbc:PostalZone>{}</cbc:PostalZone>", escape_xml(zip))?;
+    writeln!(xml, "        <cac:Country><cbc:IdentificationCode>{}</cbc:IdentificationCode></cac:Country>", country)?;
+    writeln!(xml, "      </cac:PostalAddress>")?;
+    Ok(())
+}
+
+/// Renders the invoice of `project` as an XRechnung (UBL) document.
+///
+/// Call `validate()` first, missing fields are simply left out here.
+pub fn to_xml(project:&Project, seller:&Seller) -> Result<String> {
+    let (_, invoice) = project.bills()?;
+    let mut xml = String::new();
+
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(xml, r#"<ubl:Invoice xmlns:ubl="urn:oasis:names:specification:ubl:schema:xsd:Invoice-2""#)?;
+    writeln!(xml, r#"    xmlns:cac="urn:oasis:names:specification:ubl:schema:xsd:CommonAggregateComponents-2""#)?;
+    writeln!(xml, r#"    xmlns:cbc="urn:oasis:names:specification:ubl:schema:xsd:CommonBasicComponents-2">"#)?;
+    writeln!(xml, "  <cbc:CustomizationID>{}</cbc:CustomizationID>", CUSTOMIZATION_ID)?;
+    writeln!(xml, "  <cbc:ProfileID>{}</cbc:ProfileID>", PROFILE_ID)?;
+    writeln!(xml, "  <cbc:ID>{}</cbc:ID>", escape_xml(&project.invoice().number_long_str().unwrap_or_else(String::new)))?;
+    writeln!(xml, "  <cbc:IssueDate>{}</cbc:IssueDate>", iso_date(project.invoice().date()))?;
+    writeln!(xml, "  <cbc:InvoiceTypeCode>380</cbc:InvoiceTypeCode>")?;
+    if let Some(name) = IsProject::name(project) {
+        writeln!(xml, "  <cbc:Note>{}</cbc:Note>", escape_xml(name))?;
+    }
+    writeln!(xml, "  <cbc:DocumentCurrencyCode>EUR</cbc:DocumentCurrencyCode>")?;
+    writeln!(xml, "  <cbc:BuyerReference>{}</cbc:BuyerReference>", escape_xml(&buyer_reference(project).unwrap_or_else(String::new)))?;
+
+    // seller
+    writeln!(xml, "  <cac:AccountingSupplierParty>")?;
+    writeln!(xml, "    <cac:Party>")?;
+    writeln!(xml, "      <cbc:EndpointID schemeID=\"EM\">{}</cbc:EndpointID>", escape_xml(&seller.email))?;
+    write_address(&mut xml, Some(&seller.street), &seller.zip, &seller.city, seller.country())?;
+    if !seller.vat_id.is_empty() {
+        writeln!(xml, "      <cac:PartyTaxScheme>")?;
+        writeln!(xml, "        <cbc:CompanyID>{}</cbc:CompanyID>", escape_xml(&seller.vat_id))?;
+        writeln!(xml, "        <cac:TaxScheme><cbc:ID>VAT</cbc:ID></cac:TaxScheme>")?;
+        writeln!(xml, "      </cac:PartyTaxScheme>")?;
+    } else if !seller.tax_number.is_empty() {
+        writeln!(xml, "      <cac:PartyTaxScheme>")?;
+        writeln!(xml, "        <cbc:CompanyID>{}</cbc:CompanyID>", escape_xml(&seller.tax_number))?;
+        writeln!(xml, "        <cac:TaxScheme><cbc:ID>FC</cbc:ID></cac:TaxScheme>")?;
+        writeln!(xml, "      </cac:PartyTaxScheme>")?;
+    }
+    writeln!(xml, "      <cac:PartyLegalEntity><cbc:RegistrationName>{}</cbc:RegistrationName></cac:PartyLegalEntity>", escape_xml(&seller.name))?;
+    writeln!(xml, "      <cac:Contact>")?;
+    writeln!(xml, "        <cbc:Name>{}</cbc:Name>", escape_xml(&seller.contact))?;
+    writeln!(xml, "        <cbc:Telephone>{}</cbc:Telephone>", escape_xml(&seller.phone))?;
+    writeln!(xml, "        <cbc:ElectronicMail>{}</cbc:ElectronicMail>", escape_xml(&seller.email))?;
+    writeln!(xml, "      </cac:Contact>")?;
+    writeln!(xml, "    </cac:Party>")?;
+    writeln!(xml, "  </cac:AccountingSupplierParty>")?;
+
+    // buyer
+    let client = project.client();
+    let address = client.address().and_then(PostalAddress::parse);
+    let country = buyer_country(project).unwrap_or_else(String::new);
+    let country = if country.is_empty() { "DE" } else { &country };
+    writeln!(xml, "  <cac:AccountingCustomerParty>")?;
+    writeln!(xml, "    <cac:Party>")?;
+    if let Some(email) = client.email() {
+        writeln!(xml, "      <cbc:EndpointID schemeID=\"EM\">{}</cbc:EndpointID>", escape_xml(email))?;
+    }
+    if let Some(ref address) = address {
+        write_address(&mut xml, address.street.as_ref().map(|s| s.as_str()), &address.zip, &address.city, country)?;
+    }
+    writeln!(xml, "      <cac:PartyLegalEntity><cbc:RegistrationName>{}</cbc:RegistrationName></cac:PartyLegalEntity>",
+             escape_xml(&client.full_name().unwrap_or_else(String::new)))?;
+    writeln!(xml, "    </cac:Party>")?;
+    writeln!(xml, "  </cac:AccountingCustomerParty>")?;
+
+    if let Some(event_date) = project.event_date() {
+        writeln!(xml, "  <cac:Delivery><cbc:ActualDeliveryDate>{}</cbc:ActualDeliveryDate></cac:Delivery>", iso_date(Some(event_date)))?;
+    }
+
+    // SEPA credit transfer
+    writeln!(xml, "  <cac:PaymentMeans>")?;
+    writeln!(xml, "    <cbc:PaymentMeansCode>58</cbc:PaymentMeansCode>")?;
+    writeln!(xml, "    <cac:PayeeFinancialAccount>")?;
+    writeln!(xml, "      <cbc:ID>{}</cbc:ID>", escape_xml(&seller.iban.replace(' ', "")))?;
+    writeln!(xml, "      <cbc:Name>{}</cbc:Name>", escape_xml(&seller.name))?;
+    if !seller.bic.is_empty() {
+        writeln!(xml, "      <cac:FinancialInstitutionBranch><cbc:ID>{}</cbc:ID></cac:FinancialInstitutionBranch>", escape_xml(&seller.bic))?;
+    }
+    writeln!(xml, "    </cac:PayeeFinancialAccount>")?;
+    writeln!(xml, "  </cac:PaymentMeans>")?;
+
+    // one subtotal per tax rate
+    writeln!(xml, "  <cac:TaxTotal>")?;
+    writeln!(xml, "    <cbc:TaxAmount currencyID=\"EUR\">{}</cbc:TaxAmount>", format!("{:.2}", invoice.net_total().as_float() - invoice.gross_total().as_float()))?;
+    for (tax, list) in invoice.iter() {
+        writeln!(xml, "    <cac:TaxSubtotal>")?;
+        writeln!(xml, "      <cbc:TaxableAmount currencyID=\"EUR\">{}</cbc:TaxableAmount>", amount(&list.gross_sum()))?;
+        writeln!(xml, "      <cbc:TaxAmount currencyID=\"EUR\">{}</cbc:TaxAmount>", amount(&list.tax_sum()))?;
+        write_tax_category(&mut xml, "TaxCategory", tax.into_inner())?;
+        writeln!(xml, "    </cac:TaxSubtotal>")?;
+    }
+    writeln!(xml, "  </cac:TaxTotal>")?;
+
+    // `gross` is without tax, `net` includes it
+    writeln!(xml, "  <cac:LegalMonetaryTotal>")?;
+    writeln!(xml, "    <cbc:LineExtensionAmount currencyID=\"EUR\">{}</cbc:LineExtensionAmount>", amount(&invoice.gross_total()))?;
+    writeln!(xml, "    <cbc:TaxExclusiveAmount currencyID=\"EUR\">{}</cbc:TaxExclusiveAmount>", amount(&invoice.gross_total()))?;
+    writeln!(xml, "    <cbc:TaxInclusiveAmount currencyID=\"EUR\">{}</cbc:TaxInclusiveAmount>", amount(&invoice.net_total()))?;
+    writeln!(xml, "    <cbc:PayableAmount currencyID=\"EUR\">{}</cbc:PayableAmount>", amount(&invoice.net_total()))?;
+    writeln!(xml, "  </cac:LegalMonetaryTotal>")?;
+
+    let mut line = 0;
+    for (tax, list) in invoice.iter() {
+        for item in list.iter() {
+            line += 1;
+            writeln!(xml, "  <cac:InvoiceLine>")?;
+            writeln!(xml, "    <cbc:ID>{}</cbc:ID>", line)?;
+            writeln!(xml, "    <cbc:InvoicedQuantity unitCode=\"C62\">{}</cbc:InvoicedQuantity>", item.amount)?;
+            writeln!(xml, "    <cbc:LineExtensionAmount currencyID=\"EUR\">{}</cbc:LineExtensionAmount>", amount(&item.gross()))?;
+            writeln!(xml, "    <cac:Item>")?;
+            match item.product.unit {
+                Some(unit) => writeln!(xml, "      <cbc:Name>{} ({})</cbc:Name>", escape_xml(item.product.name), escape_xml(unit))?,
+                None       => writeln!(xml, "      <cbc:Name>{}</cbc:Name>", escape_xml(item.product.name))?
+            }
+            write_tax_category(&mut xml, "ClassifiedTaxCategory", tax.into_inner())?;
+            writeln!(xml, "    </cac:Item>")?;
+            writeln!(xml, "    <cac:Price><cbc:PriceAmount currencyID=\"EUR\">{}</cbc:PriceAmount></cac:Price>", amount(&item.product.price))?;
+            writeln!(xml, "  </cac:InvoiceLine>")?;
+        }
+    }
+
+    writeln!(xml, "</ubl:Invoice>")?;
+    Ok(xml)
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+    use std::io::Read;
+    use std::path::Path;
+
+    use super::*;
+    use super::super::Project;
+
+    fn seller() -> Seller {
+        Seller {
+            name:    String::from("ascii Dresden"),
+            street:  String::from("Nöthnitzer Straße 46"),
+            zip:     String::from("01187"),
+            city:    String::from("Dresden"),
+            vat_id:  String::from("DE123456789"),
+            contact: String::from("Hendrik Sollich"),
+            email:   String::from("kontakt@example.com"),
+            phone:   String::from("+49 351 123456"),
+            iban:    String::from("DE02 1203 0000 0000 2020 51"),
+            .. Seller::default()
+        }
+    }
+
+    #[test]
+    fn postal_address(){
+        let address = PostalAddress::parse("Graf Zahl\nNummernhöllenstraße 666\n01234 Countilvania\n").unwrap();
+        assert_eq!(address.street, Some(String::from("Nummernhöllenstraße 666")));
+        assert_eq!(address.zip, "01234");
+        assert_eq!(address.city, "Countilvania");
+        assert_eq!(PostalAddress::parse("Graf Zahl\nCountilvania"), None);
+    }
+
+    #[test]
+    fn mandatory_fields(){
+        // has neither buyer_reference nor country
+        let project = Project::open_file(Path::new("./tests/current.yml")).unwrap();
+        let errors = validate(&project, &seller()).unwrap_err();
+        assert_eq!(errors.errors, vec![String::from("client/buyer_reference"), String::from("client/country")]);
+
+        let project = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+        assert!(validate(&project, &seller()).is_ok());
+
+        let errors = validate(&project, &Seller::default()).unwrap_err();
+        assert!(errors.errors.contains(&String::from("seller/vat_id or seller/tax_number")));
+        assert!(errors.errors.contains(&String::from("seller/iban")));
+    }
+
+    #[test]
+    fn client_email_required(){
+        let path = Path::new("./tests/xrechnung.yml");
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+        let project = Project::from_content(path, content.replace("  email: this.man@example.com\n", "")).unwrap();
+        let errors = validate(&project, &seller()).unwrap_err();
+        assert_eq!(errors.errors, vec![String::from("client/email")]);
+    }
+
+    #[test]
+    fn client_country(){
+        let path = Path::new("./tests/xrechnung.yml");
+        let mut content = String::new();
+        File::open(path).unwrap().read_to_string(&mut content).unwrap();
+
+        let project = Project::from_content(path, content.replace("  country: DE\n", "")).unwrap();
+        let errors = validate(&project, &seller()).unwrap_err();
+        assert_eq!(errors.errors, vec![String::from("client/country")]);
+        // seller and buyer
+        let xml = to_xml(&project, &seller()).unwrap();
+        assert_eq!(xml.matches("<cbc:IdentificationCode>DE</cbc:IdentificationCode>").count(), 2);
+
+        let project = Project::from_content(path, content.replace("  country: DE\n", "  country: AT\n")).unwrap();
+        assert!(validate(&project, &seller()).is_ok());
+        assert!(to_xml(&project, &seller()).unwrap().contains("<cbc:IdentificationCode>AT</cbc:IdentificationCode>"));
+    }
+
+    #[test]
+    fn xml(){
+        let project = Project::open_file(Path::new("./tests/xrechnung.yml")).unwrap();
+        let xml = to_xml(&project, &seller()).unwrap();
+
+        assert!(xml.contains("<cbc:ID>R2016-042</cbc:ID>"));
+        assert!(xml.contains("<cbc:IssueDate>2016-12-06</cbc:IssueDate>"));
+        assert!(xml.contains("<cbc:BuyerReference>04011000-1234512345-06</cbc:BuyerReference>"));
+        assert!(xml.contains("<cbc:CompanyID>DE123456789</cbc:CompanyID>"));
+        assert!(xml.contains("<cbc:ID>DE02120300000000202051</cbc:ID>"));
+        assert!(xml.contains("<cbc:StreetName>Nummernhöllenstraße 666</cbc:StreetName>"));
+        assert!(xml.contains("<cbc:RegistrationName>Graf Zahl &amp; Söhne</cbc:RegistrationName>"));
+
+        // 5 × 2.00 + 4 × 2.50 at 19%, 20 × 1.00 at 7%
+        assert!(xml.contains("<cbc:TaxableAmount currencyID=\"EUR\">20.00</cbc:TaxableAmount>"));
+        assert!(xml.contains("<cbc:TaxAmount currencyID=\"EUR\">3.80</cbc:TaxAmount>"));
+        assert!(xml.contains("<cbc:TaxAmount currencyID=\"EUR\">1.40</cbc:TaxAmount>"));
+        assert!(xml.contains("<cbc:TaxAmount currencyID=\"EUR\">5.20</cbc:TaxAmount>"));
+        assert!(xml.contains("<cbc:TaxExclusiveAmount currencyID=\"EUR\">40.00</cbc:TaxExclusiveAmount>"));
+        assert!(xml.contains("<cbc:PayableAmount currencyID=\"EUR\">45.20</cbc:PayableAmount>"));
+        assert_eq!(xml.matches("<cac:InvoiceLine>").count(), 3);
+    }
+}
@@ -0,0 +1,111 @@
+//! Commits that are made automatically after a command changed projects.
+//!
+//! A command collects every project it touched into one `AutoCommit`,
+//! so archiving five projects results in one commit, not five.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::{Storage, Storable, StorageResult, ErrorKind};
+use super::repo::LogEntry;
+
+/// Describes a project in a commit message, like `R042 Sommerfest (2016)`.
+pub fn describe<L:Storable>(project:&L) -> String {
+    let mut description = String::new();
+    if let Some(prefix) = project.prefix() {
+        description.push_str(&prefix);
+        description.push(' ');
+    }
+    description.push_str(&project.short_desc());
+    if let Some(year) = project.year() {
+        let _ = write!(description, " ({})", year);
+    }
+    description
+}
+
+/// Everything one command changed.
+#[derive(Debug, Clone)]
+pub struct AutoCommit {
+    operation: String,
+    projects: Vec<String>,
+    paths: Vec<PathBuf>
+}
+
+impl AutoCommit {
+    /// `operation` is the first word of the message, e.g. `"archive"`.
+    pub fn new(operation:&str) -> AutoCommit {
+        AutoCommit {
+            operation: operation.to_owned(),
+            projects: Vec::new(),
+            paths: Vec::new()
+        }
+    }
+
+    /// Adds a project and the paths it changed, old locations of moved folders included.
+    pub fn touch<L:Storable>(&mut self, project:&L, paths:&[PathBuf]) {
+        let description = describe(project);
+        if !self.projects.contains(&description) {
+            self.projects.push(description);
+        }
+        for path in paths {
+            if !self.paths.contains(path) {
+                self.paths.push(path.to_owned());
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    /// Everything that goes into the commit.
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// `archive R042 Sommerfest (2016)`, or a summary line followed by every project.
+    pub fn message(&self) -> String {
+        match self.projects.len() {
+            0 => self.operation.to_owned(),
+            1 => format!("{} {}", self.operation, self.projects[0]),
+            n => format!("{} {} projects\n\n{}\n", self.operation, n, self.projects.join("\n"))
+        }
+    }
+}
+
+impl<L:Storable> Storage<L> {
+
+    /// Stages and commits everything `commit` touched.
+    ///
+    /// Does nothing if this storage has no repository or nothing actually changed.
+    /// Refuses if something else is staged already, it would end up in the same commit.
+    pub fn auto_commit(&self, commit:&AutoCommit) -> StorageResult<Option<LogEntry>> {
+        let repo = match self.repository() {
+            Some(repo) => repo,
+            None => return Ok(None)
+        };
+        if commit.is_empty() {
+            return Ok(None);
+        }
+
+        let ours = commit.paths.iter()
+            .map(|path| path.strip_prefix(&repo.workdir).unwrap_or(path).to_owned())
+            .collect::<Vec<_>>();
+        let belongs = |path:&Path| ours.iter().any(|our| path.starts_with(our));
+
+        let foreign = repo.status()?.into_iter()
+            .filter(|entry| entry.status.is_staged() && !belongs(&entry.path))
+            .map(|entry| entry.path.display().to_string())
+            .collect::<Vec<_>>();
+        if !foreign.is_empty() {
+            return Err(ErrorKind::ForeignChangesStaged(commit.message(), foreign.join(", ")).into());
+        }
+
+        repo.add(&commit.paths)?;
+        if !repo.status()?.iter().any(|entry| entry.status.is_staged() && belongs(&entry.path)) {
+            debug!("nothing to commit for {:?}", commit.message());
+            return Ok(None);
+        }
+        Ok(Some(repo.commit(&commit.message())?))
+    }
+}
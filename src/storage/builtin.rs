@@ -0,0 +1,86 @@
+//! Templates that are compiled into the binary, so a fresh storage can be set up by `asciii init`.
+//!
+//! After an upgrade `asciii templates --diff` shows how the local copies differ from the built-in ones.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use super::{Storage, Storable, StorageResult};
+
+/// A template file that ships with asciii.
+#[derive(Debug)]
+pub struct BuiltinTemplate {
+    /// Name of the file inside the templates directory.
+    pub file_name: &'static str,
+    pub content: &'static str
+}
+
+/// The default project template and the default document templates.
+pub static BUILTIN_TEMPLATES: [BuiltinTemplate; 3] = [
+    BuiltinTemplate { file_name: "default.tyml",      content: include_str!("../../templates/default.tyml") },
+    BuiltinTemplate { file_name: "document.tex.hbs",  content: include_str!("../../templates/document.tex.hbs") },
+    BuiltinTemplate { file_name: "document.html.hbs", content: include_str!("../../templates/document.html.hbs") },
+];
+
+/// How a local template relates to its built-in version.
+#[derive(Debug)]
+pub struct TemplateComparison {
+    pub builtin: &'static BuiltinTemplate,
+    pub path: PathBuf,
+    /// Content of the local copy, `None` if there is none.
+    pub local: Option<String>
+}
+
+impl TemplateComparison {
+    pub fn is_missing(&self) -> bool {
+        self.local.is_none()
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.local.as_ref().map_or(false, |local| local != self.builtin.content)
+    }
+}
+
+impl<L:Storable> Storage<L> {
+
+    /// Writes the built-in templates that are not yet in `templates_dir()`, never overwrites.
+    ///
+    /// Returns the paths of the installed templates.
+    pub fn install_templates(&self) -> StorageResult<Vec<PathBuf>> {
+        if !self.templates_dir().exists() {
+            fs::create_dir_all(self.templates_dir())?;
+        }
+
+        let mut installed = Vec::new();
+        for template in &BUILTIN_TEMPLATES {
+            let path = self.templates_dir().join(template.file_name);
+            if path.exists() { continue }
+
+            let mut file = File::create(&path)?;
+            file.write_all(template.content.as_bytes())?;
+            file.sync_all()?;
+            installed.push(path);
+        }
+
+        self.stage(&installed);
+        Ok(installed)
+    }
+
+    /// Compares every built-in template with the file of the same name in `templates_dir()`.
+    pub fn compare_templates(&self) -> StorageResult<Vec<TemplateComparison>> {
+        let mut comparisons = Vec::new();
+        for template in &BUILTIN_TEMPLATES {
+            let path = self.templates_dir().join(template.file_name);
+            let local = if path.exists() {
+                let mut content = String::new();
+                File::open(&path)?.read_to_string(&mut content)?;
+                Some(content)
+            } else {
+                None
+            };
+            comparisons.push(TemplateComparison { builtin: template, path: path, local: local });
+        }
+        Ok(comparisons)
+    }
+}
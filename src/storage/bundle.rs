@@ -0,0 +1,239 @@
+//! Portable snapshots of one year, used by `asciii export-bundle` and `asciii import-bundle`.
+//!
+//! A bundle is a tar archive, paths inside are relative to the storage root:
+//!
+//! ```bash
+//! manifest.yml                               # sha256 of every other file
+//! archive/2016/R042_sommerfest/sommerfest.yml
+//! archive/2016/R042_sommerfest/R042 sommerfest 2016-07-12.pdf
+//! templates/default.tyml
+//! templates/document.tex.hbs
+//! ...
+//! ```
+//!
+//! Only the templates the bundled projects name are included.
+
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf, Component};
+use std::collections::{BTreeMap, BTreeSet};
+
+use sha2::{Sha256, Digest};
+use tar;
+use yaml_rust::Yaml;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::emitter::{YamlEmitter, EmitError};
+
+use util::yaml;
+use super::{Storage, Storable, StorageDir, StorageResult, ErrorKind, Year};
+use super::list_path_content;
+
+/// Name of the manifest inside a bundle.
+pub const MANIFEST_FILE: &'static str = "manifest.yml";
+
+/// What `Storage::import_bundle()` did.
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// Project folders and templates that were added.
+    pub imported: Vec<PathBuf>,
+    /// Project folders and templates that already existed and were left alone.
+    pub skipped: Vec<PathBuf>
+}
+
+fn sha256(content:&[u8]) -> String {
+    let mut hasher = Sha256::default();
+    hasher.input(content);
+    hasher.result().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn read_file(path:&Path) -> StorageResult<Vec<u8>> {
+    let mut content = Vec::new();
+    File::open(path)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+fn corrupt<T>(reason:String) -> StorageResult<T> {
+    Err(ErrorKind::BundleCorrupt(reason).into())
+}
+
+/// Only plain relative paths may be unpacked, nothing like `../../.bashrc`.
+fn is_safe(path:&Path) -> bool {
+    path.components().all(|c| match c { Component::Normal(_) => true, _ => false })
+}
+
+/// The folder a bundled file belongs to: `archive/<year>/<project>`, `working/<project>` or `templates/<file>`.
+fn unit_of(path:&Path, archive:&Path, working:&Path, templates:&Path) -> Option<PathBuf> {
+    let components = path.components().collect::<Vec<_>>();
+    let depth = if path.starts_with(archive) { archive.components().count() + 2 }
+           else if path.starts_with(working) { working.components().count() + 1 }
+           else if path.starts_with(templates) { templates.components().count() + 1 }
+           else { return None };
+    if components.len() < depth { return None }
+    Some(components[..depth].iter().map(|c| c.as_os_str()).collect())
+}
+
+/// Templates are named without any extension, `document.tex.hbs` is `document`.
+fn template_name(path:&Path) -> Option<&str> {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .and_then(|name| name.split('.').next())
+}
+
+impl<L:Storable> Storage<L> {
+
+    fn relative<'a>(&self, path:&'a Path) -> &'a Path {
+        path.strip_prefix(self.root_dir()).unwrap_or(path)
+    }
+
+    /// Packs all projects of `year`, archived or not, together with their templates into a tar archive at `target`.
+    ///
+    /// `documents` may add files that live outside of the project folder, like generated PDFs,
+    /// they are bundled as if they were inside it.
+    /// Returns the bundled paths.
+    pub fn export_bundle<F>(&self, year:Year, target:&Path, documents:F) -> StorageResult<Vec<PathBuf>>
+        where F: Fn(&L) -> Vec<PathBuf>
+    {
+        let mut files = BTreeMap::new();
+        let mut template_names = BTreeSet::new();
+
+        for project in self.open_projects(StorageDir::Year(year))?.iter() {
+            template_names.extend(project.template_names());
+            let dir = project.dir();
+            for file in list_path_content(&dir)? {
+                if file.is_file() {
+                    files.insert(self.relative(&file).to_owned(), file.to_owned());
+                }
+            }
+            for document in documents(project) {
+                if let Some(name) = document.file_name() {
+                    files.insert(self.relative(&dir).join(name), document.to_owned());
+                }
+            }
+        }
+
+        for template in list_path_content(self.templates_dir())? {
+            let used = template_name(&template).map_or(false, |name| template_names.contains(name));
+            if used && template.is_file() {
+                files.insert(self.relative(&template).to_owned(), template.to_owned());
+            }
+        }
+
+        let s = |s:&str| Yaml::String(s.to_owned());
+        let mut checksums = YamlHash::new();
+        let mut contents = Vec::new();
+        for (name, source) in &files {
+            let content = read_file(source)?;
+            checksums.insert(s(&name.display().to_string()), s(&sha256(&content)));
+            contents.push((name, content));
+        }
+
+        let mut manifest = YamlHash::new();
+        manifest.insert(s("asciii"), s(env!("CARGO_PKG_VERSION")));
+        manifest.insert(s("year"), Yaml::Integer(year as i64));
+        manifest.insert(s("files"), Yaml::Hash(checksums));
+        let mut manifest_content = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut manifest_content);
+            if let Err(EmitError::FmtError(err)) = emitter.dump(&Yaml::Hash(manifest)) {
+                return Err(err.into());
+            }
+        }
+
+        let mut builder = tar::Builder::new(File::create(target)?);
+        {
+            let mut append = |name:&Path, content:&[u8]| -> StorageResult<()> {
+                let mut header = tar::Header::new_gnu();
+                header.set_path(name)?;
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder.append(&header, content)?;
+                Ok(())
+            };
+
+            append(Path::new(MANIFEST_FILE), manifest_content.as_bytes())?;
+            for &(name, ref content) in &contents {
+                append(name, content)?;
+            }
+        }
+        builder.into_inner()?.sync_all()?;
+
+        Ok(files.keys().cloned().collect())
+    }
+
+    /// Verifies a bundle against its manifest and adds its projects and templates to this storage.
+    ///
+    /// Project folders and templates that already exist are never overwritten.
+    pub fn import_bundle(&self, bundle:&Path) -> StorageResult<ImportReport> {
+        let mut archive = tar::Archive::new(File::open(bundle)?);
+
+        let mut files = BTreeMap::new();
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            if !is_safe(&path) {
+                return corrupt(format!("refusing to unpack {}", path.display()));
+            }
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content)?;
+            files.insert(path, content);
+        }
+
+        let manifest = match files.remove(Path::new(MANIFEST_FILE)) {
+            Some(manifest) => manifest,
+            None => return corrupt(format!("{} is missing", MANIFEST_FILE))
+        };
+        let manifest = yaml::parse(&String::from_utf8_lossy(&manifest))?;
+        let checksums = match yaml::get_hash(&manifest, "files") {
+            Some(checksums) => checksums,
+            None => return corrupt(format!("{} lists no files", MANIFEST_FILE))
+        };
+
+        for (path, content) in &files {
+            let expected = checksums.get(&Yaml::String(path.display().to_string())).and_then(|c| c.as_str());
+            match expected {
+                Some(expected) if expected == sha256(content) => {},
+                Some(_) => return corrupt(format!("checksum of {} does not match", path.display())),
+                None => return corrupt(format!("{} is not listed in the manifest", path.display()))
+            }
+        }
+        if checksums.len() != files.len() {
+            return corrupt(format!("{} files are listed, but {} are contained", checksums.len(), files.len()));
+        }
+
+        // group by project folder or template, so existing projects are skipped as a whole
+        let archive_dir = self.relative(self.archive_dir()).to_owned();
+        let working_dir = self.relative(self.working_dir()).to_owned();
+        let templates_dir = self.relative(self.templates_dir()).to_owned();
+        let mut units: BTreeMap<PathBuf, Vec<(&PathBuf, &Vec<u8>)>> = BTreeMap::new();
+        for (path, content) in &files {
+            match unit_of(path, &archive_dir, &working_dir, &templates_dir) {
+                Some(unit) => units.entry(unit).or_insert_with(Vec::new).push((path, content)),
+                None => warn!("ignoring {}, it does not belong to a project", path.display())
+            }
+        }
+
+        let mut report = ImportReport::default();
+        for (unit, unit_files) in units {
+            if self.root_dir().join(&unit).exists() {
+                report.skipped.push(unit);
+                continue;
+            }
+            for (path, content) in unit_files {
+                let target = self.root_dir().join(path);
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                File::create(&target)?.write_all(content)?;
+            }
+            report.imported.push(unit);
+        }
+
+        let imported = report.imported.iter().map(|unit| self.root_dir().join(unit)).collect::<Vec<_>>();
+        if !imported.is_empty() {
+            self.stage(&imported);
+        }
+
+        Ok(report)
+    }
+}
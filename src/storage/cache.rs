@@ -0,0 +1,201 @@
+//! Persistent index of frequently listed project fields.
+//!
+//! Opening every project of every archive on each `list` takes a while,
+//! so the fields that are needed for listing are kept in a cache file in the storage root.
+//! Each entry is keyed by the path of the project file and remembers its modification time,
+//! entries of files that changed since are recomputed automatically.
+//!
+//! The cache file can be deleted at any time, it will be recreated on the next run.
+//! It only applies to the local checkout, so it is listed in `.git/info/exclude` and never committed.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+use std::time::UNIX_EPOCH;
+
+use yaml_rust::Yaml;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::emitter::{YamlEmitter, EmitError};
+
+use util::yaml;
+use super::StorageResult;
+use super::repo;
+
+/// Name of the cache file inside the storage root.
+pub const CACHE_FILE: &'static str = ".asciii_cache.yml";
+
+/// Fields of one project, as returned by [`Storage::open_index()`](../struct.Storage.html#method.open_index).
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    file: PathBuf,
+    fields: BTreeMap<String, Option<String>>
+}
+
+impl IndexEntry {
+    /// Path to the project file.
+    pub fn file(&self) -> &Path {
+        &self.file
+    }
+
+    /// Path to the project folder.
+    pub fn dir(&self) -> &Path {
+        self.file.parent().unwrap_or(&self.file)
+    }
+
+    /// Value of a field, `None` if the project does not contain it or it was not requested.
+    pub fn get(&self, field:&str) -> Option<&str> {
+        self.fields.get(field)
+                   .and_then(|value| value.as_ref())
+                   .map(|value| value.as_str())
+    }
+
+    /// Checks against a certain key-val pair, like `Storable::matches_filter()`.
+    pub fn matches_filter(&self, key: &str, val: &str) -> bool {
+        self.get(key).map_or(false, |c| c.to_lowercase().contains(&val.to_lowercase()))
+    }
+
+    fn contains_all(&self, fields:&[&str]) -> bool {
+        fields.iter().all(|field| self.fields.contains_key(*field))
+    }
+}
+
+/// The cache file, maps project files to their modification time and fields.
+#[derive(Debug)]
+pub struct IndexCache {
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, (String, IndexEntry)>,
+    changed: bool
+}
+
+/// Modification time as string, precise enough to notice every save.
+fn modification_time(file:&Path) -> Option<String> {
+    let modified = try_some!(fs::metadata(file).and_then(|m| m.modified()).ok());
+    let since_epoch = try_some!(modified.duration_since(UNIX_EPOCH).ok());
+    Some(format!("{}.{:09}", since_epoch.as_secs(), since_epoch.subsec_nanos()))
+}
+
+impl IndexCache {
+
+    /// An empty cache for `root`, ignoring whatever was stored before.
+    pub fn empty(root:&Path) -> IndexCache {
+        IndexCache {
+            path: root.join(CACHE_FILE),
+            entries: BTreeMap::new(),
+            changed: false
+        }
+    }
+
+    /// Reads the cache file in `root`, starts with an empty cache if there is none or it is broken.
+    pub fn open(root:&Path) -> IndexCache {
+        let mut cache = IndexCache::empty(root);
+        let path = cache.path.to_owned();
+
+        let content = match yaml::open(&path) {
+            Ok(content) => content,
+            Err(err) => {
+                debug!("no usable cache at {}: {}", path.display(), err);
+                return cache;
+            }
+        };
+
+        // a cache of another version might contain outdated computations
+        if yaml::get_str(&content, "version") != Some(env!("CARGO_PKG_VERSION")) {
+            debug!("ignoring cache of another version");
+            return cache;
+        }
+
+        if let Some(projects) = yaml::get_hash(&content, "projects") {
+            for (file, entry) in projects {
+                let file = match file.as_str() { Some(file) => PathBuf::from(file), None => continue };
+                let mtime = match yaml::get_str(entry, "mtime") { Some(mtime) => mtime.to_owned(), None => continue };
+                let fields = yaml::get_hash(entry, "fields")
+                    .map(|fields| fields.iter()
+                                        .filter_map(|(k, v)| k.as_str().map(|k| (k.to_owned(), v.as_str().map(ToOwned::to_owned))))
+                                        .collect())
+                    .unwrap_or_else(BTreeMap::new);
+                cache.entries.insert(file.to_owned(), (mtime, IndexEntry{ file: file, fields: fields }));
+            }
+        }
+
+        cache
+    }
+
+    /// Returns the cached entry if the file has not changed since and all `fields` are known.
+    pub fn get(&self, file:&Path, fields:&[&str]) -> Option<&IndexEntry> {
+        let &(ref mtime, ref entry) = try_some!(self.entries.get(file));
+        if Some(mtime) == modification_time(file).as_ref() && entry.contains_all(fields) {
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Stores fields of a project file, replacing what was known before.
+    pub fn insert<F>(&mut self, file:&Path, fields:&[&str], get_field:F) -> IndexEntry
+        where F: Fn(&str) -> Option<String>
+    {
+        let entry = IndexEntry {
+            file: file.to_owned(),
+            fields: fields.iter().map(|field| (field.to_string(), get_field(field))).collect()
+        };
+
+        if let Some(mtime) = modification_time(file) {
+            self.entries.insert(file.to_owned(), (mtime, entry.clone()));
+            self.changed = true;
+        }
+        entry
+    }
+
+    /// Writes the cache file if anything changed, forgets files that no longer exist.
+    pub fn save(&mut self) -> StorageResult<()> {
+        let before = self.entries.len();
+        self.entries = ::std::mem::replace(&mut self.entries, BTreeMap::new())
+            .into_iter()
+            .filter(|&(ref file, _)| file.exists())
+            .collect();
+
+        if !self.changed && before == self.entries.len() {
+            return Ok(());
+        }
+
+        let s = |s:&str| Yaml::String(s.to_owned());
+        let mut projects = YamlHash::new();
+        for (file, &(ref mtime, ref entry)) in &self.entries {
+            let mut fields = YamlHash::new();
+            for (key, value) in &entry.fields {
+                fields.insert(s(key), value.as_ref().map_or(Yaml::Null, |v| s(v)));
+            }
+            let mut cached = YamlHash::new();
+            cached.insert(s("mtime"), s(mtime));
+            cached.insert(s("fields"), Yaml::Hash(fields));
+            projects.insert(s(&file.display().to_string()), Yaml::Hash(cached));
+        }
+
+        let mut document = YamlHash::new();
+        document.insert(s("version"), s(env!("CARGO_PKG_VERSION")));
+        document.insert(s("projects"), Yaml::Hash(projects));
+
+        let mut buf = String::new();
+        {
+            let mut emitter = YamlEmitter::new(&mut buf);
+            // all keys are strings, so only formatting can fail
+            if let Err(EmitError::FmtError(err)) = emitter.dump(&Yaml::Hash(document)) {
+                return Err(err.into());
+            }
+        }
+
+        if let Some(root) = self.path.parent() {
+            if let Err(err) = repo::exclude(root, CACHE_FILE) {
+                warn!("could not keep {} out of git: {}", CACHE_FILE, err);
+            }
+        }
+
+        trace!("writing cache {}", self.path.display());
+        let mut file = File::create(&self.path)?;
+        file.write_all(buf.as_bytes())?;
+        file.sync_all()?;
+        self.changed = false;
+        Ok(())
+    }
+}
@@ -52,11 +52,26 @@ error_chain!{
         InvalidDirStructure {
             description("The filestructure under storage path does not correspond with the configuration.")
         }
+        InvalidProject(reason:String) {
+            description("The project is not valid.")
+            display("The project is not valid: {}", reason)
+        }
         TemplateNotFound {
             description("The described template file does not exist.")
         }
         GitProcessFailed {
             description("Calling `git` failed")
         }
+        NothingToUndo {
+            description("There is nothing to undo.")
+        }
+        ForeignChangesStaged(message:String, paths:String) {
+            description("Other changes are staged, they would end up in an automatic commit.")
+            display("Not committing {:?}, other changes are staged: {} (commit or unstage them first)", message, paths)
+        }
+        BundleCorrupt(reason:String) {
+            description("The bundle does not match its manifest.")
+            display("The bundle does not match its manifest: {}", reason)
+        }
     }
 }
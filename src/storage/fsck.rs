@@ -0,0 +1,224 @@
+//! Consistency checks for the whole storage, used by `asciii fsck`.
+//!
+//! Unlike [`Storage::health_check()`](../struct.Storage.html#method.health_check),
+//! which only checks whether the directories exist, this looks into every project folder.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::fmt;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::collections::BTreeMap;
+
+use regex::Regex;
+
+use templater::IsKeyword;
+use super::{Storage, Storable, StorageDir, StorageResult, ErrorKind, Year};
+use super::list_path_content;
+use super::journal::Operation;
+
+/// Something that is wrong with a project folder.
+#[derive(Debug, Clone)]
+pub enum Issue {
+    /// The project is archived under a different year than `Storable::year()` says.
+    WrongYear { dir: PathBuf, archived: Year, year: Year },
+    /// The `R###_` prefix of an archived folder does not match the invoice number.
+    WrongPrefix { dir: PathBuf, found: Option<String>, expected: Option<String> },
+    /// Several projects of the same year share one invoice number.
+    DuplicateInvoiceNumber { number: String, year: Year, dirs: Vec<PathBuf> },
+    /// There is more than one project file in the folder, only the first one is ever opened.
+    MultipleProjectFiles { dir: PathBuf, files: Vec<PathBuf> },
+    /// There is no project file in the folder.
+    EmptyDir { dir: PathBuf, completely_empty: bool },
+    /// The project file still contains `##KEYWORDS##` from the template.
+    Placeholders { file: PathBuf, keywords: Vec<String> },
+    /// The project file can't be opened at all.
+    Unreadable { dir: PathBuf, reason: String },
+    /// The data changed after the invoice was issued, see `asciii make --reissue`.
+    ModifiedSinceIssued { file: PathBuf }
+}
+
+impl Issue {
+    /// Whether `Storage::fix()` can take care of this without losing anything.
+    pub fn is_fixable(&self) -> bool {
+        match *self {
+            Issue::WrongYear{..} | Issue::WrongPrefix{..} => true,
+            Issue::EmptyDir{completely_empty, ..} => completely_empty,
+            _ => false
+        }
+    }
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Issue::WrongYear{ref dir, archived, year} =>
+                write!(f, "{}: archived in {}, but belongs to {}", dir.display(), archived, year),
+            Issue::WrongPrefix{ref dir, ref found, ref expected} =>
+                write!(f, "{}: prefix is {}, but invoice number is {}", dir.display(),
+                       found.as_ref().map_or("missing", |s| s.as_str()),
+                       expected.as_ref().map_or("missing", |s| s.as_str())),
+            Issue::DuplicateInvoiceNumber{ref number, year, ref dirs} =>
+                write!(f, "{} is used {} times in {}: {}", number, dirs.len(), year,
+                       dirs.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", ")),
+            Issue::MultipleProjectFiles{ref dir, ref files} =>
+                write!(f, "{}: contains {} project files", dir.display(), files.len()),
+            Issue::EmptyDir{ref dir, completely_empty} =>
+                write!(f, "{}: {}", dir.display(), if completely_empty {"empty folder"} else {"no project file"}),
+            Issue::Placeholders{ref file, ref keywords} =>
+                write!(f, "{}: unfilled placeholders {}", file.display(),
+                       keywords.iter().map(|k| format!("##{}##", k)).collect::<Vec<_>>().join(", ")),
+            Issue::Unreadable{ref dir, ref reason} =>
+                write!(f, "{}: can't be opened ({})", dir.display(), reason),
+            Issue::ModifiedSinceIssued{ref file} =>
+                write!(f, "{}: modified since the invoice was issued", file.display()),
+        }
+    }
+}
+
+fn folder_name(dir:&Path) -> String {
+    dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(String::new)
+}
+
+lazy_static!{
+    /// Invoice number prefix of archived project folders.
+    static ref PREFIX: Regex = Regex::new(r"^(R\d+)_(.*)$").expect("broken regex");
+}
+
+/// Splits `R042_sommerfest` into `(Some("R042"), "sommerfest")`.
+fn split_prefix(name:&str) -> (Option<String>, String) {
+    match PREFIX.captures(name) {
+        Some(caps) => (caps.at(1).map(ToOwned::to_owned), caps.at(2).unwrap_or("").to_owned()),
+        None => (None, name.to_owned())
+    }
+}
+
+fn placeholders(file:&Path) -> StorageResult<Vec<String>> {
+    let mut content = String::new();
+    File::open(file)?.read_to_string(&mut content)?;
+    let mut keywords = content.list_keywords();
+    keywords.retain(|k| !k.is_empty());
+    keywords.sort();
+    keywords.dedup();
+    Ok(keywords)
+}
+
+impl<L:Storable> Storage<L> {
+
+    /// Looks for inconsistencies in all project folders, working and archived.
+    pub fn check(&self) -> StorageResult<Vec<Issue>> {
+        let mut issues = Vec::new();
+        let mut invoice_numbers: BTreeMap<(Year, String), Vec<PathBuf>> = BTreeMap::new();
+
+        let mut folders = Vec::new();
+        for year in self.list_years()? {
+            for dir in self.list_project_folders(StorageDir::Archive(year))? {
+                folders.push((dir, Some(year)));
+            }
+        }
+        for dir in self.list_project_folders(StorageDir::Working)? {
+            folders.push((dir, None));
+        }
+
+        for (dir, archived) in folders {
+            if !dir.is_dir() { continue }
+
+            let content = list_path_content(&dir)?;
+            let files = content.iter()
+                .filter(|f| f.extension().unwrap_or_else(|| OsStr::new("")) == L::file_extension())
+                .cloned()
+                .collect::<Vec<PathBuf>>();
+
+            if files.is_empty() {
+                issues.push(Issue::EmptyDir{ dir: dir.to_owned(), completely_empty: content.is_empty() });
+                continue;
+            }
+            if files.len() > 1 {
+                issues.push(Issue::MultipleProjectFiles{ dir: dir.to_owned(), files: files.to_owned() });
+            }
+
+            let keywords = placeholders(&files[0])?;
+            if !keywords.is_empty() {
+                issues.push(Issue::Placeholders{ file: files[0].to_owned(), keywords: keywords });
+            }
+
+            let project = match L::open(&dir) {
+                Ok(project) => project,
+                Err(err) => {
+                    issues.push(Issue::Unreadable{ dir: dir.to_owned(), reason: err.to_string() });
+                    continue;
+                }
+            };
+
+            if project.modified_since_issued() {
+                issues.push(Issue::ModifiedSinceIssued{ file: files[0].to_owned() });
+            }
+
+            if let Some(archived) = archived {
+                if let Some(year) = project.year() {
+                    if year != archived {
+                        issues.push(Issue::WrongYear{ dir: dir.to_owned(), archived: archived, year: year });
+                    }
+                }
+
+                let (found, _) = split_prefix(&folder_name(&dir));
+                let expected = project.prefix();
+                if found != expected {
+                    issues.push(Issue::WrongPrefix{ dir: dir.to_owned(), found: found, expected: expected });
+                }
+            }
+
+            if let (Some(year), Some(number)) = (project.year(), project.prefix()) {
+                invoice_numbers.entry((year, number)).or_insert_with(Vec::new).push(dir.to_owned());
+            }
+        }
+
+        for ((year, number), dirs) in invoice_numbers {
+            if dirs.len() > 1 {
+                issues.push(Issue::DuplicateInvoiceNumber{ number: number, year: year, dirs: dirs });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    /// Fixes an issue found by `check()`, if it `is_fixable()`.
+    ///
+    /// Moved folders are journaled like `archive`, so `undo()` can revert them.
+    /// Returns the paths that changed.
+    pub fn fix(&self, issue:&Issue) -> StorageResult<Vec<PathBuf>> {
+        let moved = match *issue {
+            Issue::WrongYear{ref dir, year, ..} => {
+                let target = self.create_archive(year)?.join(folder_name(dir));
+                if target.exists() { return Err(ErrorKind::ProjectDirExists.into()) }
+                fs::rename(dir, &target)?;
+                self.record(Operation::Archive, dir, &target);
+                vec![dir.to_owned(), target]
+            },
+
+            Issue::WrongPrefix{ref dir, ref expected, ..} => {
+                let (_, name) = split_prefix(&folder_name(dir));
+                let new_name = match *expected {
+                    Some(ref prefix) => format!("{}_{}", prefix, name),
+                    None => name
+                };
+                let target = dir.with_file_name(new_name);
+                if target.exists() { return Err(ErrorKind::ProjectDirExists.into()) }
+                fs::rename(dir, &target)?;
+                self.record(Operation::Archive, dir, &target);
+                vec![dir.to_owned(), target]
+            },
+
+            Issue::EmptyDir{ref dir, completely_empty: true} => {
+                // only succeeds if it is still empty
+                fs::remove_dir(dir)?;
+                vec![dir.to_owned()]
+            },
+
+            _ => return Ok(Vec::new())
+        };
+
+        self.stage(&moved);
+        Ok(moved)
+    }
+}
@@ -0,0 +1,176 @@
+//! Records which project folders were moved, so that the last operation can be undone.
+//!
+//! Every line of the journal file describes one move:
+//!
+//! ```text
+//! <session>	<operation>	<from>	<to>
+//! ```
+//!
+//! Paths are relative to the storage root.
+//! All moves of one run of asciii share the same session, `undo` reverts all of them at once.
+//! The journal is listed in `.git/info/exclude`, it is never committed.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::fmt;
+
+use chrono::*;
+
+use super::StorageResult;
+use super::repo;
+
+/// Name of the journal file inside the storage root.
+pub const JOURNAL_FILE: &'static str = ".asciii_journal";
+
+lazy_static!{
+    /// Identifies all moves made by this run.
+    static ref SESSION: String = UTC::now().format("%Y%m%dT%H%M%S%.f").to_string();
+}
+
+/// Kinds of operations that can be undone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Archive,
+    Unarchive,
+    Delete
+}
+
+impl Operation {
+    fn from_str(s:&str) -> Option<Operation> {
+        match s {
+            "archive"   => Some(Operation::Archive),
+            "unarchive" => Some(Operation::Unarchive),
+            "delete"    => Some(Operation::Delete),
+            _ => None
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Operation::Archive   => write!(f, "archive"),
+            Operation::Unarchive => write!(f, "unarchive"),
+            Operation::Delete    => write!(f, "delete")
+        }
+    }
+}
+
+/// One recorded move of a project folder.
+#[derive(Debug, Clone)]
+pub struct Move {
+    pub operation: Operation,
+    pub from: PathBuf,
+    pub to: PathBuf
+}
+
+/// The journal file in the storage root.
+#[derive(Debug)]
+pub struct Journal {
+    root: PathBuf,
+    path: PathBuf
+}
+
+impl Journal {
+    pub fn new(root:&Path) -> Journal {
+        Journal {
+            root: root.to_owned(),
+            path: root.join(JOURNAL_FILE)
+        }
+    }
+
+    fn relative<'a>(&self, path:&'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    /// Appends a move to the journal.
+    pub fn record(&self, operation:Operation, from:&Path, to:&Path) -> StorageResult<()> {
+        trace!("journaling {} {} -> {}", operation, from.display(), to.display());
+        repo::exclude(&self.root, JOURNAL_FILE)?;
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        writeln!(file, "{}\t{}\t{}\t{}",
+                 *SESSION, operation,
+                 self.relative(from).display(),
+                 self.relative(to).display())?;
+        Ok(())
+    }
+
+    fn lines(&self) -> StorageResult<Vec<String>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let mut content = String::new();
+        File::open(&self.path)?.read_to_string(&mut content)?;
+        Ok(content.lines().filter(|l| !l.trim().is_empty()).map(ToOwned::to_owned).collect())
+    }
+
+    fn last_session(lines:&[String]) -> Option<&str> {
+        lines.last().and_then(|line| line.split('\t').next())
+    }
+
+    /// All moves of the most recent session, in the order they were made.
+    pub fn last(&self) -> StorageResult<Vec<Move>> {
+        let lines = self.lines()?;
+        let session = match Self::last_session(&lines) { Some(session) => session, None => return Ok(Vec::new()) };
+
+        Ok(lines.iter()
+           .map(|line| line.split('\t').collect::<Vec<_>>())
+           .filter(|fields| fields.len() == 4 && fields[0] == session)
+           .filter_map(|fields| Operation::from_str(fields[1]).map(|operation| Move {
+               operation: operation,
+               from: self.root.join(fields[2]),
+               to: self.root.join(fields[3])
+           }))
+           .collect())
+    }
+
+    /// Removes the most recent session from the journal, after it was undone.
+    pub fn forget_last(&self) -> StorageResult<()> {
+        let lines = self.lines()?;
+        let session = match Self::last_session(&lines) { Some(session) => session.to_owned(), None => return Ok(()) };
+        self.retain(lines, |fields| fields[0] != session)
+    }
+
+    /// Removes single moves of the most recent session, after only they were undone.
+    pub fn forget(&self, moves:&[Move]) -> StorageResult<()> {
+        let lines = self.lines()?;
+        let session = match Self::last_session(&lines) { Some(session) => session.to_owned(), None => return Ok(()) };
+        let forgotten = moves.iter()
+                             .map(|step| (self.relative(&step.from).to_owned(), self.relative(&step.to).to_owned()))
+                             .collect::<Vec<_>>();
+        self.retain(lines, |fields| fields[0] != session
+                    || !forgotten.iter().any(|&(ref from, ref to)| Path::new(fields[2]) == from && Path::new(fields[3]) == to))
+    }
+
+    /// Removes every move that ended up inside one of `dirs`, once they are gone from there.
+    pub fn forget_moves_into(&self, dirs:&[PathBuf]) -> StorageResult<()> {
+        let lines = self.lines()?;
+        let dirs = dirs.iter().map(|dir| self.relative(dir).to_owned()).collect::<Vec<_>>();
+        self.retain(lines, |fields| !dirs.iter().any(|dir| Path::new(fields[3]).starts_with(dir)))
+    }
+
+    /// Rewrites the journal with only the lines `keep` accepts, removes it if none are left.
+    fn retain<F>(&self, lines:Vec<String>, keep:F) -> StorageResult<()>
+        where F: Fn(&[&str]) -> bool
+    {
+        let remaining = lines.into_iter()
+                             .filter(|line| {
+                                 let fields = line.split('\t').collect::<Vec<_>>();
+                                 fields.len() != 4 || keep(&fields)
+                             })
+                             .collect::<Vec<_>>();
+
+        if remaining.is_empty() {
+            if self.path.exists() {
+                fs::remove_file(&self.path)?;
+            }
+        } else {
+            let mut file = File::create(&self.path)?;
+            for line in remaining {
+                writeln!(file, "{}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -41,6 +41,21 @@ pub mod error;
 pub use self::error::{StorageError,ErrorKind};
 pub mod storable;
 pub use self::storable::Storable;
+pub mod cache;
+pub use self::cache::IndexEntry;
+mod pool;
+pub mod journal;
+use self::journal::{Journal, Operation};
+pub mod trash;
+pub use self::trash::TrashItem;
+pub mod fsck;
+pub use self::fsck::Issue;
+pub mod bundle;
+pub use self::bundle::ImportReport;
+pub mod autocommit;
+pub use self::autocommit::AutoCommit;
+pub mod builtin;
+pub use self::builtin::{BUILTIN_TEMPLATES, TemplateComparison};
 
 #[cfg(feature="document_export")]
 mod tojson;
@@ -295,6 +310,34 @@ impl<L:Storable> Storage<L> {
                name = project_name,
                tmpl = template_name
                );
+        let project_dir = self.new_project_dir(project_name)?;
+        let template_path = self.get_template_file(template_name)?;
+
+        trace!("crating project using concrete Project implementation of from_template");
+        let project = L::from_template(&project_name, &template_path, &fill_data)?;
+
+        self.store_new_project(project, &project_dir)
+    }
+
+    /// Takes an existing project and stores a copy of it in the working directory,
+    /// in a new project directory according to it's name.
+    ///
+    /// Everything that is specific to the original event is reset by `Storable::from_existing()`.
+    pub fn create_project_from(&self, project_name:&str, source:&L, fill_data:&HashMap<&str, String>) -> StorageResult<L> {
+        debug!("creating a project\n name: {name}\n from: {source}",
+               name = project_name,
+               source = source.dir().display()
+               );
+        let project_dir = self.new_project_dir(project_name)?;
+
+        trace!("crating project using concrete Project implementation of from_existing");
+        let project = L::from_existing(&project_name, source, &fill_data)?;
+
+        self.store_new_project(project, &project_dir)
+    }
+
+    /// Path of the directory a new project would be created in, fails if it is taken.
+    fn new_project_dir(&self, project_name:&str) -> StorageResult<PathBuf> {
         if !self.working_dir().exists(){
             error!("working directory does not exist");
             return Err(ErrorKind::NoWorkingDir.into())
@@ -307,14 +350,13 @@ impl<L:Storable> Storage<L> {
         }
 
         trace!("created project will be called {:?}", slugged_name);
+        Ok(project_dir)
+    }
 
-        let target_file  = project_dir
-            .join(&(slugged_name + "." + L::file_extension()));
-
-        let template_path = self.get_template_file(template_name)?;
-
-        trace!("crating project using concrete Project implementation of from_template");
-        let mut project = L::from_template(&project_name, &template_path, &fill_data)?;
+    /// Moves a freshly created project out of its tempfile into `project_dir`.
+    fn store_new_project(&self, mut project:L, project_dir:&Path) -> StorageResult<L> {
+        let slugged_name = project_dir.file_name().and_then(OsStr::to_str).ok_or(ErrorKind::BadProjectFileName)?;
+        let target_file  = project_dir.join(&(slugged_name.to_owned() + "." + L::file_extension()));
 
         // TODO Hand of creation entirely to Storable implementation
         //      Storage it self should only concern itself with Project folders!
@@ -382,14 +424,13 @@ impl<L:Storable> Storage<L> {
         let target = archive.join(&name_in_archive);
 
         fs::rename(&project_folder, &target)?;
+        self.record(Operation::Archive, &project_folder, &target);
         info!("succesfully archived {:?} to {:?}", project.short_desc() ,target);
 
         moved_files.push(project.dir());
         moved_files.push(target);
 
-        if let Some(repo) = self.repository() {
-            repo.add(&moved_files);
-        }
+        self.stage(&moved_files);
 
         Ok(moved_files)
     }
@@ -423,9 +464,7 @@ impl<L:Storable> Storage<L> {
             }
         };
 
-        if let Some(repo) = self.repository() {
-            repo.add(&moved_files);
-        }
+        self.stage(&moved_files);
 
         Ok(moved_files)
     }
@@ -434,16 +473,113 @@ impl<L:Storable> Storage<L> {
         where F: Fn() -> bool
     {
         debug!("deleting {}", project.dir().display());
-        project.delete_project_dir_if(confirmed)?;
+        if confirmed() {
+            let trashed = trash::put(self.root_dir(), &project.dir())?;
+            self.record(Operation::Delete, &project.dir(), &trashed);
+        }
         if let Some(ref repo) = self.repository {
-            if !repo.add(&[project.dir()]).success() {
-                debug!("adding {} to git", project.dir().display());
-                return Err(ErrorKind::GitProcessFailed.into());
+            debug!("adding {} to git", project.dir().display());
+            repo.add(&[project.dir()])?;
+        }
+        Ok(())
+    }
+
+
+    /// Lists deleted projects, oldest first.
+    pub fn list_trash(&self) -> StorageResult<Vec<TrashItem>> {
+        trash::list(self.root_dir())
+    }
+
+    /// Moves a deleted project back to where it was deleted from.
+    pub fn restore_from_trash(&self, item:&TrashItem) -> StorageResult<PathBuf> {
+        let restored = trash::restore(item)?;
+        self.forget_moves_into(&[item.item_dir().to_owned()]);
+        self.stage(&[restored.to_owned()]);
+        Ok(restored)
+    }
+
+    /// Irrevocably removes everything from the trash.
+    pub fn empty_trash(&self) -> StorageResult<Vec<TrashItem>> {
+        let items = self.list_trash()?;
+        for item in &items {
+            trash::remove(item)?;
+        }
+        self.forget_moves_into(&items.iter().map(|item| item.item_dir().to_owned()).collect::<Vec<_>>());
+        Ok(items)
+    }
+
+    /// Reverts the moves of the last `archive`, `unarchive` or `delete`.
+    ///
+    /// Returns the moves that were undone.
+    pub fn undo(&self) -> StorageResult<Vec<journal::Move>> {
+        let journal = Journal::new(self.root_dir());
+        let moves = journal.last()?;
+        if moves.is_empty() {
+            return Err(ErrorKind::NothingToUndo.into());
+        }
+
+        let mut moved_files = Vec::new();
+        let mut undone = Vec::new();
+        for step in moves.iter().rev() {
+            debug!("undoing {}: {} -> {}", step.operation, step.to.display(), step.from.display());
+            if let Err(err) = Self::undo_step(step) {
+                // the steps undone so far must not be undone again
+                if let Err(journal_err) = journal.forget(&undone) {
+                    warn!("could not write journal: {}", journal_err);
+                }
+                self.stage(&moved_files);
+                return Err(err);
+            }
+            undone.push(step.to_owned());
+            moved_files.push(step.to.to_owned());
+            moved_files.push(step.from.to_owned());
+        }
+        journal.forget_last()?;
+
+        self.stage(&moved_files);
+        Ok(moves)
+    }
+
+    /// Moves one project folder back to where it was before.
+    fn undo_step(step:&journal::Move) -> StorageResult<()> {
+        if !step.to.exists() { return Err(ErrorKind::ProjectDoesNotExist.into()) }
+        if step.from.exists() { return Err(ErrorKind::ProjectDirExists.into()) }
+        if let Some(parent) = step.from.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(&step.to, &step.from)?;
+
+        if step.operation == Operation::Delete {
+            // also remove the metadata
+            if let Some(item_dir) = step.to.parent() {
+                fs::remove_dir_all(item_dir)?;
             }
         }
         Ok(())
     }
 
+    /// Drops journal entries of projects that left the trash, they can not be undone anymore.
+    fn forget_moves_into(&self, dirs:&[PathBuf]) {
+        if let Err(err) = Journal::new(self.root_dir()).forget_moves_into(dirs) {
+            warn!("could not write journal: {}", err);
+        }
+    }
+
+    /// Stages moved or changed paths, if this storage has a repository.
+    fn stage(&self, paths:&[PathBuf]) {
+        if let Some(repo) = self.repository() {
+            if let Err(err) = repo.add(paths) {
+                warn!("could not add to git: {}", err);
+            }
+        }
+    }
+
+    /// Writes a move into the journal, so it can be undone.
+    fn record(&self, operation:Operation, from:&Path, to:&Path) {
+        if let Err(err) = Journal::new(self.root_dir()).record(operation, from, to) {
+            warn!("could not write journal: {}", err);
+        }
+    }
 
     /// Moves projects found through `search_terms` from the `year` back to the `Working` directory.
     ///
@@ -459,9 +595,7 @@ impl<L:Storable> Storage<L> {
             moved_files.push(unarchive_target);
         };
 
-        if let Some(repo) = self.repository() {
-            repo.add(&moved_files);
-        }
+        self.stage(&moved_files);
 
         Ok(moved_files)
     }
@@ -494,6 +628,7 @@ impl<L:Storable> Storage<L> {
 
         if child_of_archive && !archive_itself && parent_is_num{
             fs::rename(&archived_dir, &target)?;
+            self.record(Operation::Unarchive, archived_dir, &target);
         }else{
             error!("moving out of archive failed");
             return Err(ErrorKind::InvalidDirStructure.into());
@@ -522,10 +657,20 @@ impl<L:Storable> Storage<L> {
     /// Matches StorageDir's content against multiple terms and returns matching projects.
     /// TODO add search_multiple_projects_deep
     pub fn search_projects_any(&self, dir:StorageDir, search_terms:&[&str]) -> StorageResult<Vec<L>> {
+        // open everything only once, then pick matches in the order of the terms
+        let mut projects = self.open_projects(dir)?
+            .into_iter()
+            .map(Some)
+            .collect::<Vec<Option<L>>>();
+
         let mut all_projects = Vec::new();
         for search_term in search_terms{
-            let mut projects = self.search_projects(dir, &search_term)?;
-            all_projects.append(&mut projects);
+            let search_term = search_term.to_lowercase();
+            for slot in projects.iter_mut() {
+                if slot.as_ref().map_or(false, |project| project.matches_search(&search_term)) {
+                    all_projects.extend(slot.take());
+                }
+            }
         }
 
         Ok(all_projects)
@@ -638,43 +783,109 @@ impl<L:Storable> Storage<L> {
                 archived.filter_by_key_val("Year", year.to_string().as_ref());
                 Ok(archived)
             },
-            _ =>
-                self.list_project_folders(directory)
-                .map(|paths| ProjectList{
-                    projects:paths.iter()
-                        .filter_map(|path|self.open_project(path))
-                        .collect::<Vec<L>>()
-                }
-                )
+            _ => {
+                let paths = self.list_project_folders(directory)?;
+                let projects = pool::open_all(paths, L::open)
+                    .into_iter()
+                    .filter_map(|(path, result)| match result {
+                        Ok(project) => Some(self.with_git_status(project, &path)),
+                        Err(err) => {
+                            warn!("Erroneous Project: {}\n {:#?}", path.display(), err);
+                            None
+                        }
+                    })
+                    .collect();
+                Ok(ProjectList{ projects: projects })
+            }
         }
     }
 
-    #[cfg(not(feature="git_statuses"))]
-    fn open_project(&self, path:&PathBuf) -> Option<L>{
-        match L::open(path) {
-            Ok(project) => Some(project) ,
-            Err(err) => {
-                warn!("Erroneous Project: {}\n {:#?}", path.display(), err);
-                None
+    /// Tries to open every project in `directory` and returns those that fail, together with the reason.
+    ///
+    /// Folders without a project file are reported too.
+    pub fn list_broken_projects(&self, directory:StorageDir) -> StorageResult<Vec<(PathBuf, StorageError)>>{
+        trace!("LISTING BROKEN PROJECTS in {:?}-directory", directory);
+        let paths = match directory {
+            StorageDir::Year(year) => {
+                let mut paths = self.list_project_folders(StorageDir::Archive(year))?;
+                paths.append(&mut self.list_project_folders(StorageDir::Working)?);
+                paths
+            },
+            _ => self.list_project_folders(directory)?
+        };
+
+        Ok(pool::open_all(paths, L::open)
+           .into_iter()
+           .filter_map(|(path, result)| result.err().map(|err| (path, err)))
+           .collect())
+    }
+
+    /// Behaves like `open_projects()` but only provides the requested `fields` of each project.
+    ///
+    /// Unless `use_cache` is `false` these are taken from the `IndexCache` in the storage root,
+    /// only projects whose file changed since the last run are actually opened.
+    pub fn open_index(&self, directory:StorageDir, fields:&[&str], use_cache:bool) -> StorageResult<Vec<IndexEntry>>{
+        trace!("OPENING INDEX of {:?}-directory", directory);
+        let files = match directory {
+            StorageDir::Year(year) => {
+                let mut files = self.filter_project_files(StorageDir::Archive(year), |_| true)?;
+                files.append(&mut self.filter_project_files(StorageDir::Working, |_| true)?);
+                files
+            },
+            _ => self.filter_project_files(directory, |_| true)?
+        };
+
+        let mut fields = fields.to_vec();
+        if let StorageDir::Year(_) = directory {
+            fields.push("Year");
+        }
+
+        let mut cache = if use_cache { cache::IndexCache::open(self.root_dir()) }
+                        else { cache::IndexCache::empty(self.root_dir()) };
+        let mut entries = files.iter()
+            .map(|file| if use_cache { cache.get(file, &fields).cloned() } else { None })
+            .collect::<Vec<Option<IndexEntry>>>();
+
+        let stale = files.iter().zip(entries.iter())
+            .filter(|&(_, entry)| entry.is_none())
+            .map(|(file, _)| file.to_owned())
+            .collect::<Vec<PathBuf>>();
+
+        let mut opened = pool::open_all(stale, L::open_file).into_iter();
+        for entry in entries.iter_mut().filter(|entry| entry.is_none()) {
+            match opened.next() {
+                Some((file, Ok(project))) => *entry = Some(cache.insert(&file, &fields, |field| project.field(field))),
+                Some((file, Err(err))) => warn!("Erroneous Project: {}\n {:#?}", file.display(), err),
+                None => break
+            }
+        }
+        let mut entries = entries.into_iter().filter_map(|entry| entry).collect::<Vec<IndexEntry>>();
+
+        if use_cache {
+            if let Err(err) = cache.save() {
+                warn!("could not save cache: {}", err);
             }
         }
+
+        if let StorageDir::Year(year) = directory {
+            let year = year.to_string();
+            entries.retain(|entry| entry.get("Year") == Some(&year));
+        }
+        Ok(entries)
     }
 
+    #[cfg(not(feature="git_statuses"))]
+    fn with_git_status(&self, project:L, _path:&Path) -> L{
+        project
+    }
 
+    /// Git statuses are cached by the `Repository`, so this is cheap enough to do after loading.
     #[cfg(feature="git_statuses")]
-    fn open_project(&self, path:&PathBuf) -> Option<L>{
-        match L::open(path) {
-            Ok(mut project) => {
-                if let Some(ref repo) = self.repository{
-                    project.set_git_status(repo.get_status(path));
-                }
-                Some(project) // "return"
-            },
-            Err(err) => {
-                warn!("Erroneous Project: {}\n {:#?}", path.display(), err);
-                None
-            }
+    fn with_git_status(&self, mut project:L, path:&Path) -> L{
+        if let Some(ref repo) = self.repository{
+            project.set_git_status(repo.get_status(path));
         }
+        project
     }
 
 
@@ -0,0 +1,69 @@
+//! Opens projects on a bounded number of worker threads.
+
+use std::cmp;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+use num_cpus;
+
+use super::StorageResult;
+
+/// Upper bound of worker threads, parsing is cheap compared to spawning too many.
+const MAX_WORKERS: usize = 8;
+
+/// Applies `open` to every path in `paths` concurrently.
+///
+/// The results come back in the order of `paths`, no matter which worker finished first,
+/// so sorting and listing stay deterministic.
+/// Errors are not dropped, every path is returned together with its own result.
+pub fn open_all<T, F>(paths: Vec<PathBuf>, open: F) -> Vec<(PathBuf, StorageResult<T>)>
+    where T: Send + 'static,
+          F: Fn(&Path) -> StorageResult<T> + Send + Sync + 'static
+{
+    let count = paths.len();
+    let workers = cmp::min(cmp::min(num_cpus::get(), MAX_WORKERS), count);
+    trace!("opening {} files with {} workers", count, workers);
+
+    if workers <= 1 {
+        return paths.into_iter()
+                    .map(|path| { let result = open(&path); (path, result) })
+                    .collect();
+    }
+
+    let queue = Arc::new(Mutex::new(paths.into_iter().enumerate()));
+    let open = Arc::new(open);
+    let (sender, receiver) = mpsc::channel();
+
+    let handles = (0..workers)
+        .map(|_| {
+            let queue = queue.clone();
+            let open = open.clone();
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                let next = match queue.lock() {
+                    Ok(mut queue) => queue.next(),
+                    Err(_) => None // another worker panicked
+                };
+                match next {
+                    Some((index, path)) => {
+                        let result = open(&path);
+                        if sender.send((index, path, result)).is_err() { break }
+                    },
+                    None => break
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    drop(sender);
+
+    let mut results = receiver.iter().collect::<Vec<_>>();
+    for handle in handles {
+        if handle.join().is_err() {
+            error!("a worker thread panicked while opening projects");
+        }
+    }
+
+    results.sort_by_key(|&(index, _, _)| index);
+    results.into_iter().map(|(_, path, result)| (path, result)).collect()
+}
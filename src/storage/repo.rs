@@ -1,5 +1,7 @@
 #![allow(dead_code, unused_variables)]
 use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 #[cfg(feature="git_statuses")]
 use std::collections::HashMap;
@@ -10,6 +12,7 @@ use std::error::Error;
 
 #[cfg(feature="git_statuses")]
 use git2;
+use chrono::*;
 use term::{color,Attr};
 use term::color::Color;
 
@@ -50,6 +53,20 @@ impl GitStatus {
          _                          => (color::WHITE,   None)
         }
     }
+
+    /// Changes that would be part of the next commit.
+    pub fn is_staged(&self) -> bool {
+        match *self {
+            GitStatus::IndexNew | GitStatus::IndexModified | GitStatus::IndexDeleted |
+            GitStatus::IndexRenamed | GitStatus::IndexTypechange => true,
+            _ => false
+        }
+    }
+
+    /// Files git does not know about yet.
+    pub fn is_untracked(&self) -> bool {
+        match *self { GitStatus::WorkingNew => true, _ => false }
+    }
 }
 
 impl fmt::Display for GitStatus {
@@ -93,6 +110,96 @@ impl From<git2::Status> for GitStatus{
     }
 }
 
+#[cfg(feature="git_statuses")]
+pub use git2::Error as GitError;
+
+/// Result of a git operation.
+pub type GitResult<T> = Result<T, GitError>;
+
+/// One changed file, as in `git status`.
+#[derive(Debug,Clone)]
+pub struct StatusEntry {
+    /// Relative to the workdir.
+    pub path: PathBuf,
+    pub status: GitStatus
+}
+
+/// One line of a patch, as in `git diff`.
+#[derive(Debug,Clone)]
+pub struct DiffLine {
+    /// `+`, `-` or ` ` for content, `F` for file headers and `H` for hunk headers.
+    pub origin: char,
+    pub content: String
+}
+
+impl fmt::Display for DiffLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.origin {
+            '+' | '-' | ' ' => write!(f, "{}{}", self.origin, self.content),
+            _ => write!(f, "{}", self.content)
+        }
+    }
+}
+
+/// One commit, as in `git log`.
+#[derive(Debug,Clone)]
+pub struct LogEntry {
+    pub id: String,
+    pub author: String,
+    pub email: String,
+    pub date: DateTime<FixedOffset>,
+    pub message: String
+}
+
+impl LogEntry {
+    /// First line of the message.
+    pub fn summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+
+    /// Abbreviated commit id.
+    pub fn short_id(&self) -> &str {
+        &self.id[..::std::cmp::min(7, self.id.len())]
+    }
+}
+
+/// Content of a file as of one commit.
+#[derive(Debug,Clone)]
+pub struct Revision {
+    pub commit: LogEntry,
+    /// Relative to the workdir, changes when the file was moved.
+    pub path: PathBuf,
+    pub content: String
+}
+
+#[cfg(feature="git_statuses")]
+impl<'a, 'r> From<&'a git2::Commit<'r>> for LogEntry {
+    fn from(commit:&git2::Commit) -> LogEntry {
+        let author = commit.author();
+        let time = author.when();
+        LogEntry {
+            id: commit.id().to_string(),
+            author: author.name().unwrap_or("").to_owned(),
+            email: author.email().unwrap_or("").to_owned(),
+            date: FixedOffset::east(time.offset_minutes() * 60).timestamp(time.seconds(), 0),
+            message: commit.message().unwrap_or("").to_owned()
+        }
+    }
+}
+
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "commit {}", self.id)?;
+        writeln!(f, "Author: {} <{}>", self.author, self.email)?;
+        writeln!(f, "Date:   {}", self.date.format("%a %b %e %H:%M:%S %Y %z"))?;
+        writeln!(f, "")?;
+        for line in self.message.trim_right().lines() {
+            writeln!(f, "    {}", line)?;
+        }
+        Ok(())
+    }
+}
+
 /// Convenience Wrapper for `git2::Repository`
 #[cfg(feature="git_statuses")]
 pub struct Repository{
@@ -130,6 +237,16 @@ impl Repository {
         Ok( Repository{ workdir: path.to_owned()})
     }
 
+    /// Creates a new repository in `path`, like `git init`.
+    #[cfg(feature="git_statuses")]
+    pub fn init(path:&Path) -> GitResult<Self> {
+        git2::Repository::init(path)?;
+        Self::new(path)
+    }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn init(path:&Path) -> GitResult<Self> { Err(GitError) }
+
     #[cfg(feature="git_statuses")]
     fn cache_statuses(repo:&git2::Repository) -> Result<HashMap<PathBuf, GitStatus>, git2::Error>{
         let repo_path = repo.path().parent().unwrap().to_owned();
@@ -169,6 +286,7 @@ impl Repository {
         GitStatus::Unknown
     }
 
+    /// Only used for talking to remotes, everything local goes through `git2`.
     fn execute_git(&self, command:&str, args:&[&str], paths: &[PathBuf]) -> ExitStatus{
         let gitdir  = self.workdir.join(".git");
         debug!("{:?}", Command::new("git")
@@ -189,44 +307,237 @@ impl Repository {
             .unwrap_or_else(|e| { panic!("failed to execute process: {}", e) })
     }
 
-    pub fn add(&self, paths:&[PathBuf]) -> ExitStatus {
+    /// Turns absolute paths into pathspecs relative to the workdir, `*` if `paths` is empty.
+    fn pathspecs(&self, paths:&[PathBuf]) -> Vec<String> {
+        let specs = paths.iter()
+            .map(|path| path.strip_prefix(&self.workdir).unwrap_or(path))
+            .map(|path| if path.as_os_str().is_empty() { "*".to_owned() } else { path.display().to_string() })
+            .collect::<Vec<String>>();
+        if specs.is_empty() { vec!["*".to_owned()] } else { specs }
+    }
+
+    /// Author and committer, the name is taken from `user/name` in the config,
+    /// everything else from git's own config.
+    #[cfg(feature="git_statuses")]
+    fn signature(&self) -> GitResult<git2::Signature<'static>> {
+        let fallback = self.repo.signature().ok();
+        let name = ::CONFIG.get_str("user/name")
+            .map(ToOwned::to_owned)
+            .or_else(|| fallback.as_ref().and_then(|s| s.name().map(ToOwned::to_owned)))
+            .unwrap_or_else(|| "asciii".to_owned());
+        let email = fallback.as_ref().and_then(|s| s.email().map(ToOwned::to_owned))
+            .unwrap_or_else(String::new);
+        git2::Signature::now(&name, &email)
+    }
+
+    /// Stages `paths`, including files that were removed or moved away.
+    #[cfg(feature="git_statuses")]
+    pub fn add(&self, paths:&[PathBuf]) -> GitResult<()> {
         info!("adding to git\n {:?}", paths);
-        self.execute_git("add", &[], paths)
+        let specs = self.pathspecs(paths);
+        let mut index = self.repo.index()?;
+        index.add_all(specs.iter().map(|s| s.as_str()), git2::ADD_DEFAULT, None)?;
+        index.update_all(specs.iter().map(|s| s.as_str()), None)?;
+        index.write()
     }
 
-    pub fn commit(&self) -> ExitStatus {
-        // TODO override git editor with asciii editor
-        self.execute_git("commit", &[], &[])
+    /// Commits whatever is staged, fails like git if that is nothing.
+    #[cfg(feature="git_statuses")]
+    pub fn commit(&self, message:&str) -> GitResult<LogEntry> {
+        let signature = self.signature()?;
+        let mut index = self.repo.index()?;
+        let tree = self.repo.find_tree(index.write_tree()?)?;
+
+        let parent = match self.repo.head() {
+            Ok(head) => match head.target() {
+                Some(oid) => Some(self.repo.find_commit(oid)?),
+                None => None
+            },
+            Err(_) => None // unborn branch, first commit
+        };
+        let unchanged = match parent {
+            Some(ref parent) => parent.tree_id() == tree.id(),
+            None => tree.len() == 0
+        };
+        if unchanged {
+            return Err(GitError::from_str("nothing to commit, stage changes with \"asciii add\""));
+        }
+        let parents = parent.iter().collect::<Vec<_>>();
+
+        let oid = self.repo.commit(Some("HEAD"), &signature, &signature, message, &tree, &parents)?;
+        Ok(LogEntry::from(&self.repo.find_commit(oid)?))
     }
 
-    pub fn status(&self) -> ExitStatus {
-        self.execute_git("status", &[], &[])
+    /// Name of the current branch.
+    #[cfg(feature="git_statuses")]
+    pub fn branch(&self) -> GitResult<Option<String>> {
+        match self.repo.head() {
+            Ok(head) => Ok(head.shorthand().map(ToOwned::to_owned)),
+            Err(_) => Ok(None)
+        }
     }
 
-    pub fn checkout(&self, paths:&[PathBuf]) -> ExitStatus {
-        self.execute_git("checkout", &[], paths)
+    /// Everything that is not `Current`, paths are relative to the workdir.
+    #[cfg(feature="git_statuses")]
+    pub fn status(&self) -> GitResult<Vec<StatusEntry>> {
+        let statuses = self.repo.statuses( Some( git2::StatusOptions::new()
+                                                      .include_ignored(false)
+                                                      .include_untracked(true)
+                                                      .recurse_untracked_dirs(true) ))?;
+        Ok(statuses.iter()
+           .filter_map(|entry| entry.path().map(|path| StatusEntry{
+               path: PathBuf::from(path),
+               status: entry.status().into()
+           }))
+           .collect())
     }
 
-    pub fn clean(&self, paths:&[PathBuf]) -> ExitStatus {
-        self.execute_git("clean", &["-d", "--force"], paths)
+    /// Unstaged changes in `paths`, or everywhere if `paths` is empty.
+    #[cfg(feature="git_statuses")]
+    pub fn diff(&self, paths:&[PathBuf]) -> GitResult<Vec<DiffLine>> {
+        let mut options = git2::DiffOptions::new();
+        for spec in self.pathspecs(paths) {
+            options.pathspec(spec);
+        }
+        let diff = self.repo.diff_index_to_workdir(None, Some(&mut options))?;
+
+        let mut lines = Vec::new();
+        diff.print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+            lines.push(DiffLine{
+                origin: line.origin(),
+                content: String::from_utf8_lossy(line.content()).into_owned()
+            });
+            true
+        })?;
+        Ok(lines)
     }
 
-    /// TODO not yet functional
-    pub fn stash(&self) -> ExitStatus {
-        self.execute_git("stash", &[], &[])
+    /// All commits reachable from `HEAD`, newest first.
+    #[cfg(feature="git_statuses")]
+    pub fn log(&self) -> GitResult<Vec<LogEntry>> {
+        let mut walk = self.repo.revwalk()?;
+        walk.push_head()?;
+        walk.set_sorting(git2::SORT_TIME);
+
+        let mut entries = Vec::new();
+        for oid in walk {
+            entries.push(LogEntry::from(&self.repo.find_commit(oid?)?));
+        }
+        Ok(entries)
     }
 
-    pub fn stash_pop(&self) -> ExitStatus {
-        self.execute_git("stash", &["pop"], &[])
+    /// Every commit that changed `file`, newest first, following renames.
+    ///
+    /// Moving a project folder, like archiving does, counts as a rename of the project file.
+    #[cfg(feature="git_statuses")]
+    pub fn file_history(&self, file:&Path) -> GitResult<Vec<Revision>> {
+        let mut path = file.strip_prefix(&self.workdir).unwrap_or(file).to_owned();
+
+        let mut walk = self.repo.revwalk()?;
+        walk.push_head()?;
+        walk.set_sorting(git2::SORT_TIME);
+
+        let mut revisions = Vec::new();
+        for oid in walk {
+            let commit = self.repo.find_commit(oid?)?;
+            let tree = commit.tree()?;
+            let parent_tree = if commit.parent_count() > 0 { Some(commit.parent(0)?.tree()?) } else { None };
+
+            let mut diff = self.repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)?;
+            let mut find = git2::DiffFindOptions::new();
+            find.renames(true);
+            diff.find_similar(Some(&mut find))?;
+
+            let delta = diff.deltas()
+                            .find(|delta| delta.new_file().path() == Some(path.as_path()))
+                            .map(|delta| (delta.status(), delta.old_file().path().map(ToOwned::to_owned)));
+
+            if let Some((status, old_path)) = delta {
+                if status == git2::Delta::Deleted { continue }
+                let blob = self.repo.find_blob(tree.get_path(&path)?.id())?;
+                revisions.push(Revision {
+                    commit: LogEntry::from(&commit),
+                    path: path.to_owned(),
+                    content: String::from_utf8_lossy(blob.content()).into_owned()
+                });
+
+                match (status, old_path) {
+                    (git2::Delta::Renamed, Some(old_path)) => path = old_path,
+                    (git2::Delta::Added, _) => break,
+                    _ => {}
+                }
+            }
+        }
+        Ok(revisions)
     }
 
-    pub fn push(&self) -> ExitStatus {
-        self.execute_git("push", &["origin", "master"], &[])
+    /// Registers a custom merge driver in `.git/config`,
+    /// files are assigned to it through `.gitattributes`.
+    #[cfg(feature="git_statuses")]
+    pub fn install_merge_driver(&self, name:&str, description:&str, command:&str) -> GitResult<()> {
+        let mut config = git2::Config::open(&self.workdir.join(".git").join("config"))?;
+        config.set_str(&format!("merge.{}.name", name), description)?;
+        config.set_str(&format!("merge.{}.driver", name), command)
+    }
+
+    /// Stashes all changes, including untracked files.
+    ///
+    /// Opens its own handle, because stashing needs exclusive access.
+    #[cfg(feature="git_statuses")]
+    pub fn stash(&self) -> GitResult<String> {
+        let signature = self.signature()?;
+        let mut repo = git2::Repository::open(&self.workdir)?;
+        let oid = repo.stash_save(&signature, "stashed by asciii", Some(git2::STASH_INCLUDE_UNTRACKED))?;
+        Ok(oid.to_string())
+    }
+
+    /// Applies and drops the most recent stash.
+    #[cfg(feature="git_statuses")]
+    pub fn stash_pop(&self) -> GitResult<()> {
+        let mut repo = git2::Repository::open(&self.workdir)?;
+        repo.stash_pop(0, None)
+    }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn add(&self, paths:&[PathBuf]) -> GitResult<()> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn commit(&self, message:&str) -> GitResult<LogEntry> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn branch(&self) -> GitResult<Option<String>> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn status(&self) -> GitResult<Vec<StatusEntry>> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn diff(&self, paths:&[PathBuf]) -> GitResult<Vec<DiffLine>> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn log(&self) -> GitResult<Vec<LogEntry>> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn file_history(&self, file:&Path) -> GitResult<Vec<Revision>> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn install_merge_driver(&self, name:&str, description:&str, command:&str) -> GitResult<()> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn stash(&self) -> GitResult<String> { Err(GitError) }
+
+    #[cfg(not(feature="git_statuses"))]
+    pub fn stash_pop(&self) -> GitResult<()> { Err(GitError) }
+
+    pub fn checkout(&self, paths:&[PathBuf]) -> ExitStatus {
+        self.execute_git("checkout", &[], paths)
+    }
+
+    pub fn clean(&self, paths:&[PathBuf]) -> ExitStatus {
+        self.execute_git("clean", &["-d", "--force"], paths)
     }
 
-    pub fn diff(&self,paths:&[PathBuf]) -> ExitStatus {
-        let paths:Vec<&str> = paths.iter().filter_map(|p|p.to_str()).collect();
-        self.execute_git("diff", &paths, &[])
+    pub fn push(&self) -> ExitStatus {
+        self.execute_git("push", &["origin", "master"], &[])
     }
 
     pub fn pull(&self) -> ExitStatus {
@@ -240,10 +551,34 @@ impl Repository {
     pub fn remote(&self) -> ExitStatus {
         self.execute_git("remote", &[], &[])
     }
+}
+
+/// Keeps `name` in the storage root out of git by listing it in `.git/info/exclude`.
+///
+/// Does nothing if `root` is not the workdir of a git repository.
+pub fn exclude(root:&Path, name:&str) -> io::Result<()> {
+    let git_dir = root.join(".git");
+    if !git_dir.is_dir() {
+        return Ok(());
+    }
+    let info_dir = git_dir.join("info");
+    fs::create_dir_all(&info_dir)?;
+
+    let pattern = format!("/{}", name);
+    let path = info_dir.join("exclude");
+    let mut content = String::new();
+    if path.exists() {
+        File::open(&path)?.read_to_string(&mut content)?;
+    }
+    if content.lines().any(|line| line.trim() == pattern) {
+        return Ok(());
+    }
 
-    pub fn log(&self) -> ExitStatus {
-        self.execute_git("log", &[], &[])
+    let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+    if !content.is_empty() && !content.ends_with('\n') {
+        writeln!(file, "")?;
     }
+    writeln!(file, "{}", pattern)
 }
 
 #[cfg(not(feature="git_statuses"))]
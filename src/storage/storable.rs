@@ -16,7 +16,7 @@ pub type FolderPath = Path;
 pub type FilePathBuf = PathBuf;
 pub type FolderPathBuf = PathBuf;
 
-pub trait Storable:Send+Sync{
+pub trait Storable:Send+Sync+'static{
     /// opens a projectfolder
     fn open(&FolderPath) -> StorageResult<Self> where Self: Sized;
     fn open_file(&FilePath) -> StorageResult<Self> where Self: Sized;
@@ -24,6 +24,9 @@ pub trait Storable:Send+Sync{
     /// creates in tempfile
     fn from_template(project_name:&str,template:&Path, data:&HashMap<&str, String>) -> StorageResult<Self> where Self: Sized;
 
+    /// creates in tempfile, based on an existing project instead of a template
+    fn from_existing(project_name:&str, source:&Self, data:&HashMap<&str, String>) -> StorageResult<Self> where Self: Sized;
+
     /// For file names
     fn ident(&self) -> String{ self.dir().file_stem().and_then(|s|s.to_str()).unwrap().to_owned() }
 
@@ -70,6 +73,15 @@ pub trait Storable:Send+Sync{
     /// Path to project folder
     fn dir(&self)  -> FolderPathBuf{ self.file().parent().unwrap().to_owned() }
 
+    /// Value of a field by name, used to fill the `IndexCache`.
+    fn field(&self, _key: &str) -> Option<String> { None }
+
+    /// Names of the templates the project is made from and rendered with, used by `Storage::export_bundle()`.
+    fn template_names(&self) -> Vec<String> { Vec::new() }
+
+    /// Whether the data changed after the final document was issued, used by `Storage::check()`.
+    fn modified_since_issued(&self) -> bool { false }
+
     fn matches_filter(&self, key: &str, val: &str) -> bool;
     fn matches_search(&self, term: &str) -> bool;
 
@@ -1,11 +1,13 @@
 use std::path::{Path,PathBuf};
 use std::fs;
+use std::io::{Read, Write};
 use std::collections::HashMap;
 
 use chrono::*;
 use tempdir::TempDir;
 
 use util;
+use project::Project;
 use super::*;
 
 // TODO add tests for file or directories in return values
@@ -32,6 +34,11 @@ impl Storable for TestProject{
             temp_dir: Some(temp_dir),
         })
     }
+
+    fn from_existing(project_name:&str, source:&Self, _fill:&HashMap<&str, String>) -> StorageResult<Self> where Self: Sized{
+        Self::from_template(project_name, &source.file(), _fill)
+    }
+
     fn short_desc(&self) -> String{ self.file().file_stem().unwrap().to_str().unwrap().to_owned() }
     fn modified_date(&self) -> Option<Date<UTC>>{ Some(UTC::today()) }
     fn file(&self) -> PathBuf{ self.file_path.to_owned() }
@@ -253,3 +260,396 @@ fn unarchive_project_dir(){
         }
     }
 }
+
+#[test]
+fn open_index(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    copy_template(storage_path.join("templates"));
+
+    let templates = storage.list_template_names().unwrap();
+    for test_project in TEST_PROJECTS.iter() {
+        storage.create_project(&test_project, &templates[0], &hashmap!{}).unwrap();
+    }
+
+    let uncached = storage.open_index(StorageDir::Working, &["Name"], false).unwrap();
+    assert_eq!(uncached.len(), TEST_PROJECTS.len());
+    assert!(!storage_path.join(cache::CACHE_FILE).exists());
+
+    let fresh = storage.open_index(StorageDir::Working, &["Name"], true).unwrap();
+    assert!(storage_path.join(cache::CACHE_FILE).exists());
+
+    let cached = storage.open_index(StorageDir::Working, &["Name"], true).unwrap();
+    assert_eq!(fresh.len(), cached.len());
+    for (a, b) in fresh.iter().zip(cached.iter()) {
+        assert_eq!(a.file(), b.file());
+    }
+}
+
+#[test]
+fn open_index_notices_changes(){
+    let (_dir , storage_path, _) = setup();
+    let storage = Storage::<Project>::new(&storage_path, "working", "archive", "templates").unwrap();
+    assert!(storage.create_dirs().is_ok());
+    fs::copy("./templates/default.tyml", storage_path.join("templates").join("default.tyml")).unwrap();
+
+    let file = storage.create_project("Sommerfest", "default", &hashmap!{}).unwrap().file();
+    let index = storage.open_index(StorageDir::Working, &["Name"], true).unwrap();
+    assert_eq!(index[0].get("Name"), Some("Sommerfest"));
+
+    // makes sure the modification time differs, even on coarse file systems
+    ::std::thread::sleep(::std::time::Duration::from_millis(1100));
+    let mut content = String::new();
+    fs::File::open(&file).unwrap().read_to_string(&mut content).unwrap();
+    fs::File::create(&file).unwrap()
+        .write_all(content.replace("name: Sommerfest", "name: Winterfest").as_bytes()).unwrap();
+
+    let index = storage.open_index(StorageDir::Working, &["Name"], true).unwrap();
+    assert_eq!(index.len(), 1);
+    assert_eq!(index[0].get("Name"), Some("Winterfest"));
+}
+
+#[test]
+fn index_cache_is_excluded_from_git(){
+    let (_dir , storage_path, _) = setup();
+    let storage = Storage::<Project>::new(&storage_path, "working", "archive", "templates").unwrap();
+    assert!(storage.create_dirs().is_ok());
+    fs::copy("./templates/default.tyml", storage_path.join("templates").join("default.tyml")).unwrap();
+    fs::create_dir(storage_path.join(".git")).unwrap();
+
+    storage.create_project("Sommerfest", "default", &hashmap!{}).unwrap();
+    storage.open_index(StorageDir::Working, &["Name"], true).unwrap();
+    storage.open_index(StorageDir::Working, &["Name"], true).unwrap();
+    assert!(storage_path.join(cache::CACHE_FILE).exists());
+
+    let mut exclude = String::new();
+    fs::File::open(storage_path.join(".git/info/exclude")).unwrap().read_to_string(&mut exclude).unwrap();
+    assert_eq!(exclude, format!("/{}\n", cache::CACHE_FILE));
+}
+
+#[test]
+fn open_projects_keeps_order(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    copy_template(storage_path.join("templates"));
+
+    let templates = storage.list_template_names().unwrap();
+    for i in 0..20 {
+        storage.create_project(&format!("project{}", i), &templates[0], &hashmap!{}).unwrap();
+    }
+
+    let folders = storage.list_project_folders(StorageDir::Working).unwrap();
+    let projects = storage.open_projects(StorageDir::Working).unwrap();
+    // `TestProject::open()` remembers the folder it was opened from
+    let opened = projects.iter().map(|p| p.file()).collect::<Vec<PathBuf>>();
+    assert_eq!(folders, opened);
+    assert!(storage.list_broken_projects(StorageDir::Working).unwrap().is_empty());
+}
+
+#[test]
+fn delete_and_undo(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    copy_template(storage_path.join("templates"));
+
+    let templates = storage.list_template_names().unwrap();
+    let project = storage.create_project("test1", &templates[0], &hashmap!{}).unwrap();
+    let origin = project.dir();
+
+    storage.delete_project_if(&project, || true).unwrap();
+    assert!(!origin.exists());
+    let trash = storage.list_trash().unwrap();
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].origin, origin);
+
+    let undone = storage.undo().unwrap();
+    assert_eq!(undone.len(), 1);
+    assert!(origin.exists());
+    assert!(storage.list_trash().unwrap().is_empty());
+    assert!(storage.undo().is_err());
+}
+
+#[test]
+fn restore_or_empty_trash_and_undo(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    copy_template(storage_path.join("templates"));
+
+    let templates = storage.list_template_names().unwrap();
+    let project = storage.create_project("test1", &templates[0], &hashmap!{}).unwrap();
+    let origin = project.dir();
+    storage.delete_project_if(&project, || true).unwrap();
+
+    // restoring already reverted the delete
+    let trash = storage.list_trash().unwrap();
+    assert_eq!(storage.restore_from_trash(&trash[0]).unwrap(), origin);
+    assert!(storage.undo().is_err());
+
+    // nothing left to undo once it is gone for good
+    storage.delete_project_if(&project, || true).unwrap();
+    assert_eq!(storage.empty_trash().unwrap().len(), 1);
+    assert!(storage.undo().is_err());
+    assert!(!origin.exists());
+}
+
+#[test]
+fn trash_and_journal_are_excluded_from_git(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    copy_template(storage_path.join("templates"));
+    fs::create_dir(storage_path.join(".git")).unwrap();
+
+    let templates = storage.list_template_names().unwrap();
+    let first = storage.create_project("test1", &templates[0], &hashmap!{}).unwrap();
+    let second = storage.create_project("test2", &templates[0], &hashmap!{}).unwrap();
+    storage.delete_project_if(&first, || true).unwrap();
+    storage.delete_project_if(&second, || true).unwrap();
+
+    let mut exclude = String::new();
+    fs::File::open(storage_path.join(".git/info/exclude")).unwrap().read_to_string(&mut exclude).unwrap();
+    let mut lines = exclude.lines().collect::<Vec<_>>();
+    lines.sort();
+    assert_eq!(lines, vec![format!("/{}", journal::JOURNAL_FILE), format!("/{}", trash::TRASH_DIR)]);
+}
+
+#[test]
+fn undo_partially_failing(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    copy_template(storage_path.join("templates"));
+
+    let templates = storage.list_template_names().unwrap();
+    let first = storage.create_project("test1", &templates[0], &hashmap!{}).unwrap();
+    let second = storage.create_project("test2", &templates[0], &hashmap!{}).unwrap();
+    storage.delete_project_if(&first, || true).unwrap();
+    storage.delete_project_if(&second, || true).unwrap();
+
+    // something new took the place of the first project
+    fs::create_dir(first.dir()).unwrap();
+    assert!(storage.undo().is_err());
+    assert!(second.dir().exists());
+    assert_eq!(storage.list_trash().unwrap().len(), 1);
+
+    // only what is left gets undone
+    fs::remove_dir(first.dir()).unwrap();
+    let undone = storage.undo().unwrap();
+    assert_eq!(undone.len(), 1);
+    assert_eq!(undone[0].from, first.dir());
+    assert!(first.dir().exists());
+    assert!(storage.undo().is_err());
+}
+
+#[test]
+fn check_empty_dirs(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    fs::create_dir(storage_path.join("working").join("empty")).unwrap();
+
+    let issues = storage.check().unwrap();
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].is_fixable());
+
+    storage.fix(&issues[0]).unwrap();
+    assert!(!storage_path.join("working").join("empty").exists());
+    assert!(storage.check().unwrap().is_empty());
+}
+
+#[test]
+fn check_and_fix_archived(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    let this_year = UTC::today().year();
+    let dir = storage.create_archive(1999).unwrap().join("R042_foo");
+    fs::create_dir(&dir).unwrap();
+    fs::File::create(dir.join("foo.PROJECT")).unwrap()
+        .write_all(b"name: ##NAME##\ndate: ##DATE##\nagain: ##NAME##\n").unwrap();
+
+    let issues = storage.check().unwrap();
+    assert_eq!(issues.len(), 3);
+    let keywords = issues.iter().filter_map(|issue| match *issue {
+        Issue::Placeholders{ref keywords, ..} => Some(keywords.to_owned()),
+        _ => None
+    }).next();
+    assert_eq!(keywords, Some(vec![String::from("DATE"), String::from("NAME")]));
+    assert!(!issues.iter().find(|issue| match **issue { Issue::Placeholders{..} => true, _ => false }).unwrap().is_fixable());
+
+    // TestProject always belongs to ZZ99 of this year
+    let wrong_prefix = issues.iter().find(|issue| match **issue {
+        Issue::WrongPrefix{ref found, ref expected, ..} => found == &Some(String::from("R042")) && expected == &Some(String::from("ZZ99")),
+        _ => false
+    }).unwrap();
+    let renamed = storage_path.join("archive").join("1999").join("ZZ99_foo");
+    assert_eq!(storage.fix(wrong_prefix).unwrap(), vec![dir.to_owned(), renamed.to_owned()]);
+    assert!(renamed.join("foo.PROJECT").exists());
+    assert_eq!(storage.undo().unwrap().len(), 1);
+    assert!(dir.exists() && !renamed.exists());
+
+    let wrong_year = issues.iter().find(|issue| match **issue {
+        Issue::WrongYear{archived, year, ..} => archived == 1999 && year == this_year,
+        _ => false
+    }).unwrap();
+    let moved = storage_path.join("archive").join(this_year.to_string()).join("R042_foo");
+    assert_eq!(storage.fix(wrong_year).unwrap(), vec![dir.to_owned(), moved.to_owned()]);
+    assert!(moved.join("foo.PROJECT").exists());
+    assert_eq!(storage.undo().unwrap().len(), 1);
+    assert!(dir.exists() && !moved.exists());
+}
+
+#[test]
+fn export_and_import_bundle(){
+    use sha2::{Sha256, Digest};
+
+    let read = |path:&Path| {
+        let mut content = Vec::new();
+        fs::File::open(path).unwrap().read_to_end(&mut content).unwrap();
+        content
+    };
+    let (dir , storage_path, _) = setup();
+    let storage = Storage::<Project>::new(&storage_path, "working", "archive", "templates").unwrap();
+    assert!(storage.create_dirs().is_ok());
+    let templates = storage_path.join("templates");
+    fs::copy("./templates/default.tyml", templates.join("default.tyml")).unwrap();
+    fs::copy("./templates/document.tex.hbs", templates.join("document.tex.hbs")).unwrap();
+    // not used by any project
+    fs::copy("./templates/default.tyml", templates.join("other.tyml")).unwrap();
+    fs::copy("./templates/simple.hbs", templates.join("simple.hbs")).unwrap();
+
+    let project = storage.create_project("Sommerfest", "default", &hashmap!{}).unwrap();
+    let project_file = project.file().strip_prefix(&storage_path).unwrap().to_owned();
+    let pdf = dir.path().join("Sommerfest.pdf");
+    fs::File::create(&pdf).unwrap().write_all(b"%PDF-1.4 offer").unwrap();
+    let pdf_file = project_file.with_file_name("Sommerfest.pdf");
+
+    let bundle = dir.path().join("bundle.tar");
+    let bundled = storage.export_bundle(project.year().unwrap(), &bundle, |_| vec![pdf.to_owned()]).unwrap();
+    let mut expected = vec![PathBuf::from("templates/default.tyml"), PathBuf::from("templates/document.tex.hbs"),
+                            project_file.to_owned(), pdf_file.to_owned()];
+    expected.sort();
+    assert_eq!(bundled, expected);
+
+    // the manifest comes first and lists every file with its checksum
+    let mut archive = ::tar::Archive::new(fs::File::open(&bundle).unwrap());
+    let mut manifest = String::new();
+    archive.entries().unwrap().next().unwrap().unwrap().read_to_string(&mut manifest).unwrap();
+    let manifest = util::yaml::parse(&manifest).unwrap();
+    let checksums = util::yaml::get_hash(&manifest, "files").unwrap();
+    assert_eq!(checksums.len(), 4);
+    let mut hasher = Sha256::default();
+    hasher.input(&read(&project.file()));
+    let checksum = hasher.result().iter().map(|b| format!("{:02x}", b)).collect::<String>();
+    assert_eq!(checksums.get(&::yaml_rust::Yaml::String(project_file.display().to_string())).and_then(|c| c.as_str()),
+               Some(checksum.as_str()));
+
+    let other_path = dir.path().join("other");
+    let other = Storage::<Project>::new(&other_path, "working", "archive", "templates").unwrap();
+    other.create_dirs().unwrap();
+
+    let report = other.import_bundle(&bundle).unwrap();
+    assert_eq!(report.imported.len(), 3);
+    assert_eq!(read(&other_path.join(&project_file)), read(&project.file()));
+    assert_eq!(read(&other_path.join(&pdf_file)), b"%PDF-1.4 offer".to_vec());
+    assert!(other_path.join("templates").join("document.tex.hbs").exists());
+    assert!(!other_path.join("templates").join("simple.hbs").exists());
+
+    // nothing is overwritten
+    fs::File::create(other_path.join(&project_file)).unwrap().write_all(b"changed").unwrap();
+    let report = other.import_bundle(&bundle).unwrap();
+    assert!(report.imported.is_empty());
+    assert_eq!(report.skipped.len(), 3);
+    assert_eq!(read(&other_path.join(&project_file)), b"changed".to_vec());
+}
+
+#[test]
+#[cfg(feature="git_statuses")]
+fn git_add_commit_log(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    ::git2::Repository::init(&storage_path).unwrap();
+    copy_template(storage_path.join("templates"));
+
+    let repo = repo::Repository::new(&storage_path).unwrap();
+    assert!(repo.status().unwrap().iter().all(|e| e.status.is_untracked()));
+
+    repo.add(&[storage_path.join("templates")]).unwrap();
+    assert!(repo.status().unwrap().iter().all(|e| e.status.is_staged()));
+
+    let commit = repo.commit("add templates").unwrap();
+    assert_eq!(commit.summary(), "add templates");
+    assert!(repo.status().unwrap().is_empty());
+
+    let log = repo.log().unwrap();
+    assert_eq!(log.len(), 1);
+    assert_eq!(log[0].id, commit.id);
+
+    // nothing staged, nothing to commit
+    assert!(repo.commit("empty").is_err());
+    assert_eq!(repo.log().unwrap().len(), 1);
+}
+
+#[test]
+fn autocommit_message(){
+    let first = TestProject::open_file(Path::new("/storage/working/first/first.yml")).unwrap();
+    let second = TestProject::open_file(Path::new("/storage/working/second/second.yml")).unwrap();
+    let year = UTC::today().year();
+
+    let mut commit = AutoCommit::new("archive");
+    assert!(commit.is_empty());
+    assert_eq!(commit.message(), "archive");
+
+    commit.touch(&first, &[first.dir()]);
+    assert_eq!(commit.message(), format!("archive ZZ99 first ({})", year));
+
+    // touching the same project again changes nothing
+    commit.touch(&first, &[first.dir()]);
+    commit.touch(&second, &[second.dir(), first.dir()]);
+    assert_eq!(commit.paths(), &[first.dir(), second.dir()][..]);
+    assert_eq!(commit.message(), format!("archive 2 projects\n\nZZ99 first ({0})\nZZ99 second ({0})\n", year));
+}
+
+#[test]
+#[cfg(feature="git_statuses")]
+fn autocommit_only_own_changes(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+    ::git2::Repository::init(&storage_path).unwrap();
+    copy_template(storage_path.join("templates"));
+    let storage = Storage::<TestProject>::new_with_git(&storage_path, "working", "archive", "templates").unwrap();
+    let repo = repo::Repository::new(&storage_path).unwrap();
+
+    // something the user staged by hand
+    repo.add(&[storage_path.join("templates")]).unwrap();
+
+    let project = storage.create_project("foobar", "template1", &hashmap!()).unwrap();
+    let mut commit = AutoCommit::new("new");
+    commit.touch(&project, &[project.dir()]);
+    assert!(storage.auto_commit(&commit).is_err());
+    assert!(repo.log().is_err() || repo.log().unwrap().is_empty());
+
+    repo.commit("add templates").unwrap();
+    let entry = storage.auto_commit(&commit).unwrap().unwrap();
+    assert!(entry.summary().starts_with("new ZZ99 foobar"));
+    assert!(repo.status().unwrap().is_empty());
+
+    // nothing changed since
+    assert!(storage.auto_commit(&commit).unwrap().is_none());
+}
+
+#[test]
+fn builtin_templates(){
+    let (_dir , storage_path, storage) = setup();
+    assert!(storage.create_dirs().is_ok());
+
+    let installed = storage.install_templates().unwrap();
+    assert_eq!(installed.len(), BUILTIN_TEMPLATES.len());
+    assert!(storage.compare_templates().unwrap().iter().all(|c| !c.is_missing() && !c.is_modified()));
+
+    // never overwrites
+    let default = storage_path.join("templates").join("default.tyml");
+    fs::File::create(&default).unwrap();
+    assert!(storage.install_templates().unwrap().is_empty());
+
+    let comparisons = storage.compare_templates().unwrap();
+    let modified = comparisons.iter().filter(|c| c.is_modified()).collect::<Vec<_>>();
+    assert_eq!(modified.len(), 1);
+    assert_eq!(modified[0].path, default);
+}
@@ -0,0 +1,145 @@
+//! Deleted projects are moved into a trash folder inside the storage root instead of being removed.
+//!
+//! ```bash
+//! # root dir
+//! └── .trash
+//!     └── 20161023143012_sommerfest
+//!         ├── trashed.yml   # origin and time of deletion
+//!         └── sommerfest
+//!             └── sommerfest.yml
+//! ```
+//!
+//! The trash is listed in `.git/info/exclude`, so trashed projects are not committed again.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::*;
+use yaml_rust::Yaml;
+use yaml_rust::yaml::Hash as YamlHash;
+use yaml_rust::emitter::{YamlEmitter, EmitError};
+
+use util::yaml;
+use super::{StorageResult, ErrorKind};
+use super::repo;
+
+/// Name of the trash folder inside the storage root.
+pub const TRASH_DIR: &'static str = ".trash";
+
+/// Metadata file inside every item in the trash.
+const META_FILE: &'static str = "trashed.yml";
+
+/// A project folder in the trash.
+#[derive(Debug, Clone)]
+pub struct TrashItem {
+    /// Name of the item inside the trash folder, used to restore it.
+    pub id: String,
+    /// The trashed project folder.
+    pub dir: PathBuf,
+    /// Where the project folder used to be.
+    pub origin: PathBuf,
+    /// When it was deleted.
+    pub deleted: String
+}
+
+impl TrashItem {
+    /// Folder of this item in the trash, containing the project folder and the metadata.
+    pub fn item_dir(&self) -> &Path {
+        self.dir.parent().unwrap_or(&self.dir)
+    }
+}
+
+/// Moves `dir` into the trash of the storage at `root`.
+///
+/// Returns the new location of the project folder.
+pub fn put(root:&Path, dir:&Path) -> StorageResult<PathBuf> {
+    let trash = root.join(TRASH_DIR);
+    if !trash.exists() {
+        fs::create_dir(&trash)?;
+    }
+    repo::exclude(root, TRASH_DIR)?;
+
+    let name = dir.file_name().ok_or(ErrorKind::BadProjectFileName)?;
+    let now = Local::now();
+    let stamp = now.format("%Y%m%d%H%M%S").to_string();
+    let mut item_dir = trash.join(format!("{}_{}", stamp, name.to_string_lossy()));
+    let mut counter = 1;
+    while item_dir.exists() {
+        item_dir = trash.join(format!("{}_{}_{}", stamp, name.to_string_lossy(), counter));
+        counter += 1;
+    }
+    fs::create_dir(&item_dir)?;
+
+    let s = |s:&str| Yaml::String(s.to_owned());
+    let mut meta = YamlHash::new();
+    meta.insert(s("origin"), s(&dir.strip_prefix(root).unwrap_or(dir).display().to_string()));
+    meta.insert(s("deleted"), s(&now.format("%Y-%m-%d %H:%M:%S").to_string()));
+    let mut buf = String::new();
+    {
+        let mut emitter = YamlEmitter::new(&mut buf);
+        if let Err(EmitError::FmtError(err)) = emitter.dump(&Yaml::Hash(meta)) {
+            return Err(err.into());
+        }
+    }
+    File::create(item_dir.join(META_FILE))?.write_all(buf.as_bytes())?;
+
+    let target = item_dir.join(name);
+    debug!("$ mv {} {}", dir.display(), target.display());
+    fs::rename(dir, &target)?;
+    Ok(target)
+}
+
+/// Lists everything in the trash of the storage at `root`, oldest first.
+pub fn list(root:&Path) -> StorageResult<Vec<TrashItem>> {
+    let trash = root.join(TRASH_DIR);
+    if !trash.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut items = Vec::new();
+    for entry in fs::read_dir(&trash)? {
+        let item_dir = entry?.path();
+        let meta = match yaml::open(&item_dir.join(META_FILE)) {
+            Ok(meta) => meta,
+            Err(err) => { warn!("broken item in trash {}: {}", item_dir.display(), err); continue }
+        };
+        let origin = match yaml::get_str(&meta, "origin") {
+            Some(origin) => root.join(origin),
+            None => { warn!("item in trash without origin: {}", item_dir.display()); continue }
+        };
+        let dir = match origin.file_name() {
+            Some(name) => item_dir.join(name),
+            None => continue
+        };
+        items.push(TrashItem {
+            id: item_dir.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_else(String::new),
+            dir: dir,
+            origin: origin,
+            deleted: yaml::get_string(&meta, "deleted").unwrap_or_else(String::new)
+        });
+    }
+    items.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(items)
+}
+
+/// Moves a project folder out of the trash, back to where it came from.
+pub fn restore(item:&TrashItem) -> StorageResult<PathBuf> {
+    if item.origin.exists() {
+        return Err(ErrorKind::ProjectDirExists.into());
+    }
+    if let Some(parent) = item.origin.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    debug!("$ mv {} {}", item.dir.display(), item.origin.display());
+    fs::rename(&item.dir, &item.origin)?;
+    fs::remove_dir_all(item.item_dir())?;
+    Ok(item.origin.to_owned())
+}
+
+/// Removes an item from the trash for good.
+pub fn remove(item:&TrashItem) -> StorageResult<()> {
+    debug!("$ rm -r {}", item.item_dir().display());
+    fs::remove_dir_all(item.item_dir())?;
+    Ok(())
+}
@@ -0,0 +1,42 @@
+//! Windows-1252, the encoding german banking and accounting software still insists on.
+//!
+//! It matches Latin-1, except for `0x80` to `0x9F`, which hold typographic characters instead.
+
+/// The characters at `0x80` to `0x9F`, `None` where Windows-1252 leaves a gap.
+const SPECIALS: [Option<char>; 32] = [
+    Some('€'), None,      Some('‚'), Some('ƒ'), Some('„'), Some('…'), Some('†'), Some('‡'),
+    Some('ˆ'), Some('‰'), Some('Š'), Some('‹'), Some('Œ'), None,      Some('Ž'), None,
+    None,      Some('‘'), Some('’'), Some('“'), Some('”'), Some('•'), Some('–'), Some('—'),
+    Some('˜'), Some('™'), Some('š'), Some('›'), Some('œ'), None,      Some('ž'), Some('Ÿ'),
+];
+
+/// Characters Windows-1252 doesn't know become `?`.
+pub fn encode(text:&str) -> Vec<u8> {
+    text.chars().map(|c| match c as u32 {
+        0x00...0x7F | 0xA0...0xFF => c as u32 as u8,
+        _ => SPECIALS.iter()
+                     .position(|&special| special == Some(c))
+                     .map_or(b'?', |index| 0x80 + index as u8)
+    }).collect()
+}
+
+/// The gaps are kept as the control characters of the same value.
+pub fn decode(bytes:&[u8]) -> String {
+    bytes.iter().map(|&b| match b {
+        0x80...0x9F => SPECIALS[(b - 0x80) as usize].unwrap_or(b as char),
+        _ => b as char
+    }).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trip(){
+        let text = "Grüße 5€ „Zitat“ – Œuvre™ …";
+        assert_eq!(decode(&encode(text)), text);
+        assert_eq!(encode("Grüße 5€ →"), b"Gr\xfc\xdfe 5\x80 ?".to_vec());
+        assert_eq!(SPECIALS.iter().filter(|special| special.is_some()).count(), 27);
+    }
+}
@@ -0,0 +1,189 @@
+//! Running the converter (usually `pdflatex`) and making sense of its log when it fails.
+
+use std::io;
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use regex::Regex;
+
+/// The first error found in a LaTeX log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogError {
+    /// e.g. `Undefined control sequence.`
+    pub message: String,
+    /// Line in the `.tex` file, if LaTeX could tell.
+    pub line: Option<usize>,
+    /// The offending source line as quoted by LaTeX, e.g. `\invoicenumber{R042}`.
+    pub context: Option<String>
+}
+
+impl fmt::Display for LogError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(line) = self.line {
+            write!(f, " in line {}", line)?;
+        }
+        if let Some(ref context) = self.context {
+            write!(f, ": {}", context)?;
+        }
+        Ok(())
+    }
+}
+
+/// A conversion that did not produce a document.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub tool: String,
+    pub file: PathBuf,
+    /// Exit code, `None` if the converter was killed.
+    pub status: Option<i32>,
+    pub error: Option<LogError>,
+    /// The log, which is kept for inspection.
+    pub log: Option<PathBuf>,
+    /// Everything the converter printed.
+    pub output: String
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} failed on {}", self.tool, self.file.display())?;
+        match self.status {
+            Some(code) => write!(f, " (exit code {})", code)?,
+            None => write!(f, " (killed)")?
+        }
+        match self.error {
+            Some(ref error) => write!(f, "\n{}", error)?,
+            // nothing in the log, show what it said last
+            None => for line in self.output.lines().rev().take(5).collect::<Vec<_>>().into_iter().rev() {
+                write!(f, "\n{}", line)?;
+            }
+        }
+        if let Some(ref log) = self.log {
+            write!(f, "\nsee {}", log.display())?;
+        }
+        Ok(())
+    }
+}
+
+/// Finds the first error in a LaTeX log.
+///
+/// Understands the classic `! message` followed by `l.42 offending line`
+/// as well as `file.tex:42: message` of `-file-line-error`.
+pub fn first_error(log:&str) -> Option<LogError> {
+    let file_line = Regex::new(r"^.*\.tex:(\d+): (.*)$").expect("broken regex");
+    let context_line = Regex::new(r"^l\.(\d+) ?(.*)$").expect("broken regex");
+
+    let lines = log.lines().collect::<Vec<_>>();
+    for (index, line) in lines.iter().enumerate() {
+        let (message, mut line_number) = if line.starts_with("! ") {
+            (line[2..].trim().to_owned(), None)
+        } else if let Some(caps) = file_line.captures(line) {
+            (caps.at(2).unwrap_or("").trim().to_owned(), caps.at(1).and_then(|n| n.parse().ok()))
+        } else {
+            continue
+        };
+
+        // LaTeX quotes the offending line a few lines below
+        let mut context = None;
+        for following in lines.iter().skip(index + 1).take(10) {
+            if let Some(caps) = context_line.captures(following) {
+                line_number = line_number.or_else(|| caps.at(1).and_then(|n| n.parse().ok()));
+                context = caps.at(2).map(|c| c.trim().to_owned()).and_then(|c| if c.is_empty() {None} else {Some(c)});
+                break;
+            }
+        }
+
+        return Some(LogError { message: message, line: line_number, context: context });
+    }
+    None
+}
+
+/// Runs `tool` (including its arguments) on `file`, output ends up in the current directory.
+///
+/// Returns the `Failure` if the converter did not exit successfully,
+/// reading the first error from `log` if it exists.
+pub fn convert(tool:&str, file:&Path, log:&Path) -> io::Result<Option<Failure>> {
+    let mut args = tool.split_whitespace();
+    let command = args.next().unwrap_or("pdflatex");
+    info!("launching {:?} on {:?}", tool, file);
+
+    // stdin is closed, so LaTeX stops at the first error instead of waiting for input
+    let output = Command::new(command)
+        .args(&args.collect::<Vec<_>>())
+        .arg(file)
+        .output()?;
+
+    if output.status.success() {
+        return Ok(None);
+    }
+
+    let mut printed = String::from_utf8_lossy(&output.stdout).into_owned();
+    printed.push_str(&String::from_utf8_lossy(&output.stderr));
+
+    let mut log_content = String::new();
+    let log = match File::open(log) {
+        Ok(mut log_file) => {
+            // LaTeX logs are not necessarily utf8
+            let mut bytes = Vec::new();
+            log_file.read_to_end(&mut bytes)?;
+            log_content = String::from_utf8_lossy(&bytes).into_owned();
+            Some(log.to_owned())
+        },
+        Err(_) => None
+    };
+
+    Ok(Some(Failure {
+        tool: command.to_owned(),
+        file: file.to_owned(),
+        status: output.status.code(),
+        error: first_error(&log_content).or_else(|| first_error(&printed)),
+        log: log,
+        output: printed
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn classic_error(){
+        let log = "\
+This is pdfTeX, Version 3.14159265-2.6-1.40.17 (TeX Live 2016) (preloaded format=pdflatex)
+(./R042_Sommerfest.tex
+LaTeX2e <2016/03/31>
+! Undefined control sequence.
+l.42 \\invoicenumbr
+                  {R042}
+?
+! Emergency stop.
+";
+        let error = first_error(log).unwrap();
+        assert_eq!(error.message, "Undefined control sequence.");
+        assert_eq!(error.line, Some(42));
+        assert_eq!(error.context, Some("\\invoicenumbr".to_owned()));
+    }
+
+    #[test]
+    fn file_line_error(){
+        let log = "\
+(./R042_Sommerfest.tex
+./R042_Sommerfest.tex:17: Missing $ inserted.
+<inserted text>
+                $
+l.17 Kaffee & 5 & 2,50_
+";
+        let error = first_error(log).unwrap();
+        assert_eq!(error.message, "Missing $ inserted.");
+        assert_eq!(error.line, Some(17));
+        assert_eq!(error.context, Some("Kaffee & 5 & 2,50_".to_owned()));
+    }
+
+    #[test]
+    fn no_error(){
+        assert_eq!(first_error("Output written on R042_Sommerfest.pdf (1 page, 41337 bytes).\n"), None);
+    }
+}
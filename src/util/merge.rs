@@ -0,0 +1,250 @@
+//! Three-way merge of yaml documents, key by key, used by `asciii merge-driver`.
+//!
+//! The merge works on the text, not on parsed yaml, so comments, anchors and the layout of
+//! the project file survive.
+//! A document is split into entries by indentation, every entry that starts with `key:` at
+//! the same indentation is one entry, its deeper indented lines belong to it.
+//! Entries that were only changed on one side are taken from that side,
+//! entries that were changed on both sides are merged recursively if they are mappings.
+//! Only entries that are no mappings and were changed differently on both sides conflict,
+//! in that case our version is kept.
+
+use std::fmt;
+
+/// An entry that was changed differently on both sides.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    /// Keys leading to the entry, like `products/*kaffee/amount`.
+    pub path: String,
+    pub base: Option<String>,
+    pub ours: Option<String>,
+    pub theirs: Option<String>
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |side:&Option<String>| side.as_ref().map_or("deleted".to_owned(), |s| format!("{:?}", s.trim()));
+        write!(f, "{}: ours {}, theirs {}, base {}",
+               self.path, show(&self.ours), show(&self.theirs), show(&self.base))
+    }
+}
+
+/// The merged document and everything that could not be merged.
+#[derive(Debug)]
+pub struct Merged {
+    pub content: String,
+    pub conflicts: Vec<Conflict>
+}
+
+/// One key with its value, or the comments and blank lines before the first key.
+#[derive(Debug, Clone)]
+struct Entry<'a> {
+    /// Key and the how many-th entry with that key this is, `None` for the preamble.
+    id: Option<(&'a str, usize)>,
+    lines: Vec<&'a str>
+}
+
+impl<'a> Entry<'a> {
+    /// Comparable content, ignores trailing whitespace and trailing blank lines.
+    fn normalized(&self) -> String {
+        let mut lines = self.lines.iter().map(|l| l.trim_right()).collect::<Vec<_>>();
+        while lines.last() == Some(&"") { lines.pop(); }
+        lines.join("\n")
+    }
+
+    /// The deeper indented lines, if this entry is a mapping.
+    fn children(&self) -> Option<(usize, &[&'a str])> {
+        let header = self.lines[0];
+        let value = strip_comment(header.splitn(2, ':').nth(1).unwrap_or("")).trim();
+        if !value.is_empty() { return None }
+
+        let body = &self.lines[1..];
+        let first = match body.iter().find(|l| is_content(l)) {
+            Some(first) => first,
+            None => return None
+        };
+        let indent = indentation(first);
+        if indent > indentation(header) && key_of(first).is_some() {
+            Some((indent, body))
+        } else {
+            None // a sequence
+        }
+    }
+}
+
+fn indentation(line:&str) -> usize {
+    line.len() - line.trim_left().len()
+}
+
+fn strip_comment(value:&str) -> &str {
+    match value.find(" #") {
+        Some(pos) => &value[..pos],
+        None => if value.trim_left().starts_with('#') { "" } else { value }
+    }
+}
+
+/// Neither blank nor a comment.
+fn is_content(line:&str) -> bool {
+    let trimmed = line.trim();
+    !trimmed.is_empty() && !trimmed.starts_with('#')
+}
+
+fn key_of(line:&str) -> Option<&str> {
+    let trimmed = line.trim();
+    if !is_content(line) || trimmed.starts_with('-') || trimmed == "---" || trimmed == "..." {
+        return None;
+    }
+    trimmed.find(':').map(|pos| trimmed[..pos].trim())
+}
+
+/// Splits lines into entries at `indent`.
+fn entries<'a>(lines:&[&'a str], indent:usize) -> Vec<Entry<'a>> {
+    let mut entries:Vec<Entry> = vec![Entry{ id: None, lines: Vec::new() }];
+    for &line in lines {
+        let key = if indentation(line) == indent { key_of(line) } else { None };
+        match key {
+            Some(key) => {
+                let occurrence = entries.iter().filter(|e| e.id.map(|(k, _)| k) == Some(key)).count();
+                entries.push(Entry{ id: Some((key, occurrence)), lines: vec![line] });
+            },
+            None => entries.last_mut().expect("there is always a preamble").lines.push(line)
+        }
+    }
+    entries
+}
+
+fn find<'a, 'e>(entries:&'e [Entry<'a>], id:&Option<(&str, usize)>) -> Option<&'e Entry<'a>> {
+    entries.iter().find(|e| e.id == *id)
+}
+
+fn path_of(parent:&str, id:&Option<(&str, usize)>) -> String {
+    let key = match *id {
+        Some((key, 0)) => key.to_owned(),
+        Some((key, n)) => format!("{}[{}]", key, n),
+        None => "(comments)".to_owned()
+    };
+    if parent.is_empty() { key } else { format!("{}/{}", parent, key) }
+}
+
+/// Order of the result: ours, with entries that only theirs has inserted after their predecessor.
+fn merged_order<'a>(ours:&[Entry<'a>], theirs:&[Entry<'a>]) -> Vec<Option<(&'a str, usize)>> {
+    let mut order = ours.iter().map(|e| e.id).collect::<Vec<_>>();
+    let mut position = 0;
+    for entry in theirs {
+        match order.iter().position(|id| *id == entry.id) {
+            Some(found) => position = found + 1,
+            None => {
+                order.insert(position, entry.id);
+                position += 1;
+            }
+        }
+    }
+    order
+}
+
+fn merge_entries<'a>(path:&str, base:&[&'a str], ours:&[&'a str], theirs:&[&'a str], indent:usize,
+                     result:&mut Vec<&'a str>, conflicts:&mut Vec<Conflict>) {
+    let base = entries(base, indent);
+    let ours = entries(ours, indent);
+    let theirs = entries(theirs, indent);
+
+    for id in merged_order(&ours, &theirs) {
+        let (b, o, t) = (find(&base, &id), find(&ours, &id), find(&theirs, &id));
+        let text = |e:Option<&Entry>| e.map(|e| e.normalized());
+
+        let chosen = if text(o) == text(t) || text(b) == text(t) {
+            o
+        } else if text(b) == text(o) {
+            t
+        } else {
+            let children = (o.and_then(|o| o.children()), t.and_then(|t| t.children()));
+            if let (Some((o_indent, o_body)), Some((t_indent, t_body))) = children {
+                if o_indent == t_indent {
+                    let b_body = match b.and_then(|b| b.children()) {
+                        Some((b_indent, b_body)) if b_indent == o_indent => b_body,
+                        _ => &[]
+                    };
+                    result.push(o.unwrap().lines[0]);
+                    merge_entries(&path_of(path, &id), b_body, o_body, t_body, o_indent, result, conflicts);
+                    continue;
+                }
+            }
+
+            conflicts.push(Conflict{ path: path_of(path, &id), base: text(b), ours: text(o), theirs: text(t) });
+            // don't lose a change because the other side deleted the entry
+            if o.is_some() { o } else { t }
+        };
+
+        if let Some(entry) = chosen {
+            result.extend_from_slice(&entry.lines);
+        }
+    }
+}
+
+/// Merges `ours` and `theirs`, which both started out as `base`.
+pub fn merge(base:&str, ours:&str, theirs:&str) -> Merged {
+    let base_lines = base.lines().collect::<Vec<_>>();
+    let ours_lines = ours.lines().collect::<Vec<_>>();
+    let theirs_lines = theirs.lines().collect::<Vec<_>>();
+
+    let mut result = Vec::new();
+    let mut conflicts = Vec::new();
+    merge_entries("", &base_lines, &ours_lines, &theirs_lines, 0, &mut result, &mut conflicts);
+
+    let mut content = result.join("\n");
+    if ours.ends_with('\n') { content.push('\n'); }
+    Merged { content: content, conflicts: conflicts }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    static BASE: &'static str = "\
+# comment
+invoice:
+  number: 41
+  date: 06.12.2014
+
+products:
+  *kaffee:
+    amount: 5
+  *tee:
+    amount: 3
+
+hours:
+  caterers:
+    Maxime: 2
+";
+
+    #[test]
+    fn merges_different_keys(){
+        let ours = BASE.replace("number: 41", "number: 42");
+        let theirs = BASE.replace("amount: 3", "amount: 4")
+                         .replace("    Maxime: 2\n", "    Maxime: 2\n    Sebastian: 3\n");
+        let merged = merge(BASE, &ours, &theirs);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.content,
+                   BASE.replace("number: 41", "number: 42")
+                       .replace("amount: 3", "amount: 4")
+                       .replace("    Maxime: 2\n", "    Maxime: 2\n    Sebastian: 3\n"));
+    }
+
+    #[test]
+    fn conflicts_on_divergent_scalars(){
+        let ours = BASE.replace("amount: 5", "amount: 6");
+        let theirs = BASE.replace("amount: 5", "amount: 7");
+        let merged = merge(BASE, &ours, &theirs);
+        assert_eq!(merged.conflicts.len(), 1);
+        assert_eq!(merged.conflicts[0].path, "products/*kaffee/amount");
+        assert_eq!(merged.content, ours);
+    }
+
+    #[test]
+    fn same_change_on_both_sides(){
+        let ours = BASE.replace("number: 41", "number: 42");
+        let merged = merge(BASE, &ours, &ours);
+        assert!(merged.conflicts.is_empty());
+        assert_eq!(merged.content, ours);
+    }
+}
@@ -11,6 +11,9 @@ use std::process::{Command, ExitStatus};
 use open;
 
 pub mod yaml;
+pub mod merge;
+pub mod latex;
+pub mod cp1252;
 
 #[export_macro]
 macro_rules! try_some {
@@ -86,12 +86,124 @@ pub fn parse( file_content:&str ) -> Result<Yaml, YamlError> {
       )
 }
 
+/// Replaces or removes values in a yaml document on a textual level.
+///
+/// Unlike parsing and emitting again, this keeps comments, anchors and the order of the original.
+/// Paths look like `invoice/number`, `*` matches any key and list items are not indexed,
+/// so `products/*/sold` matches every product and `event/dates/begin` every date.
+/// A replacement of `None` removes the line including everything nested below it.
+pub fn replace_values(content:&str, replacements:&[(&str, Option<String>)]) -> String {
+    let mut output = String::new();
+    let mut parents:Vec<(usize, String)> = Vec::new();
+    let mut skip_below:Option<usize> = None;     // lines of a removed or replaced value
+    let mut verbatim_below:Option<usize> = None; // lines of an untouched block scalar
+    let mut skipped_blanks = 0;
+
+    for line in content.lines() {
+        let trimmed = line.trim_left();
+        let indent = line.len() - trimmed.len();
+
+        if let Some(block_indent) = skip_below {
+            if trimmed.is_empty() { skipped_blanks += 1; continue; }
+            if indent > block_indent { skipped_blanks = 0; continue; }
+            skip_below = None;
+            // blank lines behind a skipped block most likely separate it from the next one
+            for _ in 0..skipped_blanks { output.push('\n'); }
+            skipped_blanks = 0;
+        }
+
+        if let Some(block_indent) = verbatim_below {
+            if trimmed.is_empty() || indent > block_indent {
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+            verbatim_below = None;
+        }
+
+        // list items carry their key behind the dash
+        let entry = if trimmed.starts_with("- ") { trimmed[2..].trim_left() } else { trimmed };
+        let key_indent = line.len() - entry.len();
+        let key_end = entry.find(": ").or_else(|| if entry.ends_with(':') {Some(entry.len() - 1)} else {None});
+
+        let key_end = match key_end {
+            Some(key_end) if !entry.starts_with('#') && !entry.starts_with("---") => key_end,
+            _ => { // comments, plain list items and the like
+                output.push_str(line);
+                output.push('\n');
+                continue;
+            }
+        };
+
+        let key = entry[..key_end].trim().trim_matches('"').to_owned();
+        while parents.last().map_or(false, |&(parent_indent,_)| parent_indent >= key_indent) {
+            parents.pop();
+        }
+
+        let opens_block = {
+            let value = entry[key_end+1..].trim();
+            value.starts_with('|') || value.starts_with('>')
+        };
+
+        let replacement = {
+            let path = parents.iter()
+                              .map(|&(_, ref k)| k.as_str())
+                              .chain(Some(key.as_str()))
+                              .collect::<Vec<&str>>();
+            replacements.iter()
+                        .find(|&&(pattern,_)| path_matches(pattern, &path))
+                        .map(|&(_, ref value)| value)
+        };
+
+        match replacement {
+            Some(&None) => {
+                skip_below = Some(key_indent);
+                continue;
+            },
+            Some(&Some(ref new_value)) => {
+                let colon = line.len() - entry.len() + key_end;
+                output.push_str(format!("{}: {}", &line[..colon], new_value).trim_right());
+                output.push('\n');
+                if opens_block { skip_below = Some(key_indent); }
+            },
+            None => {
+                output.push_str(line);
+                output.push('\n');
+                if opens_block { verbatim_below = Some(key_indent); }
+            }
+        }
+
+        parents.push((key_indent, key));
+    }
+    output
+}
+
+fn path_matches(pattern:&str, path:&[&str]) -> bool {
+    let pattern = pattern.split('/').filter(|k|!k.is_empty()).collect::<Vec<&str>>();
+    pattern.len() == path.len() &&
+        pattern.iter().zip(path.iter()).all(|(p, k)| *p == "*" || p == k)
+}
+
+/// Makes `value` safe to fill in behind a key, quoting it only if it would not read as a plain string.
+pub fn quote(value:&str) -> String {
+    let special = |c:char| ":#{}[],&*!|>'\"%@`\\".contains(c);
+    let plain = !value.is_empty()
+        && !value.chars().any(|c| special(c) || c.is_control())
+        && !value.starts_with(|c:char| c.is_whitespace() || c == '-' || c == '?')
+        && !value.ends_with(char::is_whitespace);
+    if plain {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+    }
+}
+
 /// Interprets `"25.12.2016"` as date.
 pub fn parse_dmy_date(date_str:&str) -> Option<Date<UTC>>{
     let date = date_str.split('.')
         .map(|f|f.parse().unwrap_or(0))
         .collect::<Vec<i32>>();
-    if date.len() >=2 && date[0] > 0 && date[2] > 1900 {
+    if date.len() >= 3 && date[0] > 0 && date[2] > 1900 {
         // XXX this neglects the old "01-05.12.2015" format
         UTC.ymd_opt(date[2], date[1] as u32, date[0] as u32).single()
     } else {
@@ -236,3 +348,17 @@ fn get_path<'a>(yaml:&'a Yaml, path:&[&str]) -> Option<&'a Yaml>{
     }
     None
 }
+
+#[cfg(test)]
+mod test {
+    use chrono::*;
+    use super::parse_dmy_date;
+
+    #[test]
+    fn dmy_dates(){
+        assert_eq!(parse_dmy_date("24.12.2016"), Some(UTC.ymd(2016, 12, 24)));
+        assert_eq!(parse_dmy_date("24.12"), None);
+        assert_eq!(parse_dmy_date("31.02.2016"), None);
+        assert_eq!(parse_dmy_date(""), None);
+    }
+}